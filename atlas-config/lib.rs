@@ -1,21 +1,92 @@
 // NOTE: Yes, for now this is very much useless.
 // But will come in handy in the future when we want to set colorschemes and other various configurations
 // en editor might want to hold.
-// For now, we just store a simple font size constant really.
+// For now, we just store a couple of simple font constants really.
 
-use iced::Pixels;
+use std::time::Duration;
+
+use iced::{Color, Pixels};
 
 const DEFAULT_FONT_SIZE: f32 = 50.0;
+const DEFAULT_LINE_HEIGHT: f32 = 1.2;
+const DEFAULT_SCROLL_SPEED: f32 = 1.0;
+const DEFAULT_AUTOSAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How a file buffer's path is shown in its pane title.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PathDisplay {
+    /// Just the file name.
+    #[default]
+    Name,
+    /// Relative to the working directory, absolute for files outside of it.
+    Relative,
+    Absolute,
+}
 
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 pub struct Config {
-    pub font_size: Pixels
+    pub font_size: Pixels,
+    /// Line height as a multiplier of `font_size`.
+    pub line_height: f32,
+    /// Multiplier applied to mouse-wheel scroll deltas.
+    pub scroll_speed: f32,
+    /// Invert the mouse-wheel scroll direction.
+    pub scroll_invert: bool,
+    /// Columns at which a vertical ruler is drawn (Vim's `colorcolumn`), 0-based.
+    pub color_columns: Vec<usize>,
+    /// Soft wrap long lines. Buffers can override this (and `number`/`list`) with `:setlocal`.
+    pub wrap: bool,
+    /// Wrap at this column instead of the window's width, so the layout doesn't change when the
+    /// window is resized. Windows narrower than it still wrap at their width.
+    pub wrap_column: Option<usize>,
+    /// Indent the continuation rows of wrapped lines like the line itself (Vim's `breakindent`).
+    pub break_indent: bool,
+    /// Show line numbers.
+    pub number: bool,
+    /// Columns the line numbers take at least, the space after them included (Vim's
+    /// `numberwidth`), so the text doesn't shift as a short file grows.
+    pub number_width: usize,
+    /// Show tabs and trailing whitespace.
+    pub list: bool,
+    /// Mark the rows past the end of the buffer with a `~`, like Vim.
+    pub end_of_buffer_markers: bool,
+    /// Flash the window when a motion or command fails, like `h` on the first column (Vim's
+    /// `visualbell`).
+    pub visual_bell: bool,
+    /// Write modified file buffers out every `autosave_interval` and when the window loses focus.
+    pub autosave: bool,
+    pub autosave_interval: Duration,
+    pub path_display: PathDisplay,
+    /// Bracket pairs `%` jumps between and the cursor's match is highlighted for (Vim's
+    /// `matchpairs`). Each pair needs two different characters.
+    pub match_pairs: Vec<(char, char)>,
+    /// Border of the pane with focus, and of the others.
+    pub focused_border_color: Color,
+    pub border_color: Color,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
-            font_size: Pixels(DEFAULT_FONT_SIZE)
+            font_size: Pixels(DEFAULT_FONT_SIZE),
+            line_height: DEFAULT_LINE_HEIGHT,
+            scroll_speed: DEFAULT_SCROLL_SPEED,
+            scroll_invert: false,
+            color_columns: Vec::new(),
+            wrap: false,
+            wrap_column: None,
+            break_indent: false,
+            number: false,
+            number_width: 4,
+            list: false,
+            end_of_buffer_markers: true,
+            visual_bell: false,
+            autosave: false,
+            autosave_interval: DEFAULT_AUTOSAVE_INTERVAL,
+            path_display: PathDisplay::default(),
+            match_pairs: vec![('(', ')'), ('[', ']'), ('{', '}')],
+            focused_border_color: Color::from_rgb(0.37, 0.49, 0.89),
+            border_color: Color::BLACK,
         }
     }
 }
@@ -1,18 +1,57 @@
-use ropey::Rope;
+use std::{
+    borrow::Cow,
+    fs, io,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use regex::Regex;
+use ropey::{Rope, RopeSlice};
 use unicode_segmentation::UnicodeSegmentation;
 
 use crate::{
-    cursor::{MoveOpts, TextPosition},
-    MultiCursor,
+    cursor::{get_char_class, CharClass, MoveOpts, TextPosition},
+    editorconfig,
+    history::History,
+    BufferOptions, Cursor, EditorMode, IndentStyle, LineColumn, LineEnding, MultiCursor,
 };
 
+const BOM: char = '\u{feff}';
+/// How many lines `Buffer::detect_indent` looks at.
+const INDENT_SAMPLE_LINES: usize = 1000;
+
+/// A replacement of a char range with new text, see `Buffer::apply_edits`.
+pub type Edit = (Range<usize>, String);
+
 /// Represents a text buffer in the editor.
 /// Handles the actual content storage and text manipulation operations.
 #[derive(Debug, Clone, Default)]
 pub struct Buffer {
     pub content: Rope,
     pub name: String,
-    // TODO: Add file_path, modified.
+    pub options: BufferOptions,
+    /// Whether there are changes that haven't been written out.
+    pub modified: bool,
+    /// The file started with a UTF-8 byte order mark. It's kept out of `content` so it doesn't
+    /// show up as a character, and written back by `write_to`.
+    pub bom: bool,
+    /// What the file's lines end with, written back by `write_to` unless `end_of_line` says
+    /// otherwise. Lines added since end however `content` has them, ropey treats `\r\n` as one
+    /// line break either way.
+    pub line_ending: LineEnding,
+    /// Where the buffer is saved to, `None` for scratch buffers.
+    pub file_path: Option<PathBuf>,
+    /// Where Insert mode was last left (Vim's `'^` mark), for `gi`. Edits since may have moved it
+    /// past the end of the buffer.
+    pub last_insert: Option<TextPosition>,
+    /// Where the last Visual selection started and ended (Vim's `'<` and `'>` marks), for
+    /// `:'<,'>`.
+    pub last_visual: Option<(TextPosition, TextPosition)>,
+    history: History,
+    /// Cursor offsets from before the changes since the last checkpoint, see `begin_change`.
+    change_cursors: Option<Vec<usize>>,
+    /// Bytes changed since `take_dirty_range` was last called.
+    dirty: Option<Range<usize>>,
 }
 
 /// Macro to handle multi-cursor operations with proper ordering.
@@ -31,6 +70,10 @@ pub struct Buffer {
 /// ```
 macro_rules! multi_cursor_operation {
     ($multi_cursor:expr, ascending, $idx: ident => $body:block) => {{
+        // Cursors sharing an offset would each insert there and then shift one another past
+        // their own text, so they're merged first.
+        $multi_cursor.merge_overlapping();
+
         // Collect indices and sort by offset (ascending).
         let mut cursor_indices: Vec<usize> = (0..$multi_cursor.cursors.len()).collect();
         cursor_indices.sort_by_key(|&i| $multi_cursor.cursors[i].position().offset);
@@ -56,47 +99,325 @@ macro_rules! multi_cursor_operation {
 
 impl Buffer {
     pub fn new(content: &str, name: &str) -> Self {
+        Self::from_rope(Rope::from_str(content), name)
+    }
+
+    /// A buffer holding `content`, minus the byte order mark it may start with.
+    fn from_rope(mut content: Rope, name: &str) -> Self {
+        let bom = content.get_char(0) == Some(BOM);
+        if bom {
+            content.remove(0..1);
+        }
+
         Self {
-            content: Rope::from_str(content),
+            history: History::new(&content),
+            change_cursors: None,
+            dirty: None,
+            line_ending: LineEnding::detect(&content),
+            content,
             name: name.to_string(),
+            options: BufferOptions::for_file(name),
+            modified: false,
+            bom,
+            file_path: None,
+            last_insert: None,
+            last_visual: None,
+        }
+    }
+
+    /// Reads the file at `path` into a new buffer named after it, indenting like the file does
+    /// rather than its filetype's defaults if that can be told. `.editorconfig` files have the
+    /// last word.
+    pub fn from_path(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        // Straight into the rope, without holding the whole file in a `String` first.
+        let content = Rope::from_reader(io::BufReader::new(fs::File::open(path)?))?;
+        let name = path.file_name().unwrap_or(path.as_os_str()).to_string_lossy();
+
+        let mut buffer = Self {
+            file_path: Some(path.to_path_buf()),
+            ..Self::from_rope(content, &name)
+        };
+        if let Some(style) = buffer.detect_indent() {
+            buffer.options.set_indent_style(style);
+        }
+        editorconfig::apply(&mut buffer.options, path);
+
+        Ok(buffer)
+    }
+
+    /// Guesses the indentation from the leading whitespace of the first lines. Spaces go by the
+    /// most common step between the indents of consecutive lines.
+    /// `None` without indented lines, or with lines indented both ways. Single spaces, like those
+    /// lining up C block comments, don't count.
+    pub fn detect_indent(&self) -> Option<IndentStyle> {
+        let mut tab_lines = 0;
+        let mut space_lines = 0;
+        let mut steps = [0usize; 9];
+        let mut previous = 0;
+
+        for line in self.content.lines().take(INDENT_SAMPLE_LINES) {
+            let indent: String = line.chars().take_while(|c| matches!(c, ' ' | '\t')).collect();
+            if line.chars().nth(indent.chars().count()).is_none_or(char::is_whitespace) {
+                continue; // Blank.
+            }
+
+            if indent.starts_with('\t') {
+                tab_lines += 1;
+                continue;
+            }
+
+            let spaces = indent.len();
+            if spaces == 1 || indent.contains('\t') {
+                continue;
+            }
+
+            space_lines += usize::from(spaces > 0);
+            if let Some(count) = steps.get_mut(spaces.abs_diff(previous)) {
+                *count += 1;
+            }
+            previous = spaces;
+        }
+
+        match (tab_lines, space_lines) {
+            (0, 0) => None,
+            (_, 0) => Some(IndentStyle::Tabs),
+            (0, _) => {
+                // Reversed so that ties go to the narrower step.
+                let (width, _) = steps
+                    .iter()
+                    .enumerate()
+                    .skip(2)
+                    .rev()
+                    .max_by_key(|&(_, count)| count)
+                    .filter(|&(_, &count)| count > 0)?;
+                Some(IndentStyle::Spaces(width))
+            }
+            _ => None,
+        }
+    }
+
+    /// Writes the buffer to its `file_path` and marks it unmodified.
+    /// It's written to a temporary file next to it first, which then replaces it, so a write that
+    /// fails halfway never leaves the file truncated.
+    /// Scratch buffers have nowhere to go, so that's an error.
+    pub fn save(&mut self) -> io::Result<()> {
+        let Some(path) = &self.file_path else {
+            return Err(io::Error::other("E32: No file name"));
+        };
+
+        // Symlinks stay links, it's the file they point to that's replaced.
+        let path = fs::canonicalize(path).unwrap_or_else(|_| path.clone());
+        let Some(name) = path.file_name() else {
+            return Err(io::Error::other(format!("E502: \"{}\" is a directory", path.display())));
+        };
+        let temp = path.with_file_name(format!(".{}.atlas-save", name.to_string_lossy()));
+
+        let result = self.write_file(&temp, &path).and_then(|()| fs::rename(&temp, &path));
+        if result.is_err() {
+            let _ = fs::remove_file(&temp);
         }
+        result?;
+
+        self.modified = false;
+        Ok(())
+    }
+
+    /// Writes the buffer out to a new file at `path`, with the permissions of the file at
+    /// `original` if there's one, and waits until it's on disk.
+    fn write_file(&self, path: &Path, original: &Path) -> io::Result<()> {
+        let file = fs::File::create(path)?;
+        if let Ok(metadata) = fs::metadata(original) {
+            file.set_permissions(metadata.permissions())?;
+        }
+
+        let mut writer = io::BufWriter::new(file);
+        self.write_to(&mut writer)?;
+        writer.into_inner().map_err(io::IntoInnerError::into_error)?.sync_all()
+    }
+
+    /// Whether autosave should write this buffer out: it has changes and somewhere to put them.
+    pub fn needs_autosave(&self) -> bool {
+        self.modified && self.file_path.is_some()
+    }
+
+    /// Writes the buffer out as it should be saved, with the byte order mark it was loaded with
+    /// and the line endings and whitespace its options ask for.
+    pub fn write_to(&self, mut writer: impl io::Write) -> io::Result<()> {
+        if self.bom {
+            write!(writer, "{BOM}")?;
+        }
+
+        // Lines typed into a CRLF file end in a bare `\n` until they're written out.
+        let options = &self.options;
+        let as_is = options.end_of_line.is_none() && options.insert_final_newline.is_none() && !options.trim_trailing_whitespace;
+        if as_is && self.line_ending == LineEnding::Lf {
+            return self.content.write_to(writer);
+        }
+        writer.write_all(self.saved_text().as_bytes())
+    }
+
+    /// The content with every line ending in `line_ending` (or `end_of_line`), and
+    /// `insert_final_newline` and `trim_trailing_whitespace` applied.
+    fn saved_text(&self) -> String {
+        let text = self.content.to_string();
+        let options = &self.options;
+        let ending = options.end_of_line.unwrap_or(self.line_ending).as_str();
+
+        let mut lines: Vec<&str> = text.lines().collect();
+        if options.trim_trailing_whitespace {
+            for line in &mut lines {
+                *line = line.trim_end_matches([' ', '\t']);
+            }
+        }
+
+        let mut saved = lines.join(ending);
+        if !lines.is_empty() && options.insert_final_newline.unwrap_or(text.ends_with('\n')) {
+            saved.push_str(ending);
+        }
+        saved
+    }
+
+    /// Remembers where the cursors are before a command that may change the content, for undoing
+    /// the change to put them back. Only the first call between checkpoints counts, so an Insert
+    /// session goes back to where it started.
+    pub fn begin_change(&mut self, mc: &MultiCursor) {
+        if self.change_cursors.is_none() {
+            self.change_cursors = Some(cursor_offsets(mc));
+        }
+    }
+
+    /// Records the content as an undo state if it changed since the last one. Called between
+    /// commands, so everything typed in one Insert session makes a single state.
+    pub fn checkpoint(&mut self, mc: &MultiCursor) {
+        let before = self.change_cursors.take().unwrap_or_default();
+        self.history.record(&self.content, before, cursor_offsets(mc));
+    }
+
+    /// `u`/`<C-r>` and `:earlier`/`:later`: goes `steps` undo states back (forward if positive),
+    /// with the cursors where they were before the change undone, or after the one redone. Changes
+    /// made since the last checkpoint are recorded first. Returns whether there was anywhere to go.
+    pub fn travel_history(&mut self, steps: isize, mc: &mut MultiCursor) -> bool {
+        self.checkpoint(mc);
+        let Some((state, cursors)) = self.history.travel(steps) else {
+            return false;
+        };
+
+        let old_len = self.content.len_bytes();
+        self.content = state.clone();
+        self.modified = true;
+
+        // Without recorded cursors, they keep their offsets as far as the new content goes.
+        let offsets = match cursors {
+            [] => cursor_offsets(mc),
+            cursors => cursors.to_vec(),
+        };
+        mc.cursors = offsets
+            .into_iter()
+            .map(|offset| {
+                let pos = self.offset_to_position(offset.min(self.content.len_chars()));
+                let mut cursor = Cursor::new();
+                cursor.move_to(pos, MoveOpts { anchor: None, update_preferred_col: true }, self);
+                cursor
+            })
+            .collect();
+        mc.primary_index = mc.primary_index.min(mc.cursors.len() - 1);
+        mc.refresh_positions(self);
+        mc.merge_overlapping();
+        self.mark_dirty(0..old_len, self.content.len_bytes());
+
+        true
     }
 
     pub fn visible_line_content(&self, line: usize) -> String {
+        self.visible_line_slice(line).to_string()
+    }
+
+    /// Borrowed view of `line` without its trailing line break.
+    /// Prefer this over `visible_line_content` on hot paths (e.g. rendering) to avoid allocating.
+    pub fn visible_line_slice(&self, line: usize) -> RopeSlice<'_> {
         assert!(
             line < self.content.len_lines(),
             "Line index out of range ({line})"
         );
 
-        self.content
-            .line(line)
-            .to_string()
-            .trim_end_matches(['\r', '\n'])
-            .to_string()
+        let slice = self.content.line(line);
+        let mut end = slice.len_chars();
+        while end > 0 && matches!(slice.char(end - 1), '\r' | '\n') {
+            end -= 1;
+        }
+
+        slice.slice(..end)
+    }
+
+    /// Leading whitespace (spaces and tabs) of `line`: its length in chars, and the prefix itself.
+    pub fn line_indent(&self, line: usize) -> (usize, String) {
+        let prefix: String = self
+            .visible_line_slice(line)
+            .chars()
+            .take_while(|c| matches!(c, ' ' | '\t'))
+            .collect();
+
+        (prefix.chars().count(), prefix)
     }
 
     pub fn grapheme_substring(&self, line: usize, start: usize, len: usize) -> String {
-        assert!(
-            line < self.content.len_lines(),
-            "Line index out of range ({})",
-            line
-        );
-        let content = self.visible_line_content(line);
-        content
-            .graphemes(true)
-            .skip(start)
-            .take(len)
-            .collect::<Vec<_>>()
-            .join("")
+        // Only allocates for the line itself when it spans several rope chunks.
+        let content: Cow<str> = self.visible_line_slice(line).into();
+        content.graphemes(true).skip(start).take(len).collect()
     }
 
     pub fn visual_line_length(&self, line: usize) -> usize {
-        self.visible_line_content(line).chars().count()
+        self.visible_line_slice(line).len_chars()
     }
 
     /// Number of grapheme clusters in the visible part of 'line'.
     pub fn grapheme_len(&self, line: usize) -> usize {
-        self.visible_line_content(line).graphemes(true).count()
+        let content: Cow<str> = self.visible_line_slice(line).into();
+        content.graphemes(true).count()
+    }
+
+    /// Screen column of grapheme column `col` of `line`, with tabs reaching the next `tab_width`
+    /// stop. Columns past the end of the line (virtual space) take one cell each.
+    pub fn display_col(&self, line: usize, col: usize) -> usize {
+        let content: Cow<str> = self.visible_line_slice(line).into();
+        let mut display = 0;
+        let mut graphemes = 0;
+
+        for g in content.graphemes(true).take(col) {
+            display += self.grapheme_width(g, display);
+            graphemes += 1;
+        }
+
+        display + (col - graphemes)
+    }
+
+    /// Grapheme column of `line` covering screen column `display_col`, the inverse of
+    /// `display_col`. Past the end of the line this is the line's length.
+    pub fn col_at_display(&self, line: usize, display_col: usize) -> usize {
+        let content: Cow<str> = self.visible_line_slice(line).into();
+        let mut display = 0;
+        let mut len = 0;
+
+        for (col, g) in content.graphemes(true).enumerate() {
+            display += self.grapheme_width(g, display);
+            if display > display_col {
+                return col;
+            }
+            len = col + 1;
+        }
+
+        len
+    }
+
+    /// Cells taken by grapheme `g` starting at screen column `display_col`.
+    pub(crate) fn grapheme_width(&self, g: &str, display_col: usize) -> usize {
+        if g == "\t" {
+            let tab_width = self.options.tab_width.max(1);
+            tab_width - display_col % tab_width
+        } else {
+            1
+        }
     }
 
     /// Translate (line, grapheme column) to absolute char offset.
@@ -114,7 +435,8 @@ impl Buffer {
 
         let mut chars = 0;
 
-        for (i, g) in self.visible_line_content(line).graphemes(true).enumerate() {
+        let content: Cow<str> = self.visible_line_slice(line).into();
+        for (i, g) in content.graphemes(true).enumerate() {
             if i == col {
                 break;
             }
@@ -125,6 +447,37 @@ impl Buffer {
         self.content.line_to_char(line) + chars
     }
 
+    /// Translate an absolute char offset to a (line, grapheme column) position.
+    pub fn offset_to_position(&self, offset: usize) -> TextPosition {
+        self.validate_offset(offset);
+
+        let line = self.content.char_to_line(offset);
+        let line_start = self.content.line_to_char(line);
+        let col = self
+            .content
+            .slice(line_start..offset)
+            .to_string()
+            .graphemes(true)
+            .count();
+
+        TextPosition::new(line, col, offset)
+    }
+
+    /// The char at `offset`, `None` at or past the end of the buffer.
+    pub fn char_at(&self, offset: usize) -> Option<char> {
+        self.content.get_char(offset)
+    }
+
+    /// The grapheme starting at char `offset`, `None` at or past the end of the buffer.
+    pub fn grapheme_at(&self, offset: usize) -> Option<Cow<'_, str>> {
+        if offset >= self.content.len_chars() {
+            return None;
+        }
+
+        let end = self.next_grapheme_offset(offset);
+        Some(self.content.slice(offset..end).into())
+    }
+
     /// Given a char offset, return the previous grapheme boundary.
     pub fn prev_grapheme_offset(&self, offset: usize) -> usize {
         self.validate_offset(offset);
@@ -164,16 +517,19 @@ impl Buffer {
     }
 
     pub fn insert_char(&mut self, mc: &mut MultiCursor, c: char) {
+        self.fill_virtual_space(mc);
+
         multi_cursor_operation!(mc, ascending, idx => {
             let pos = mc.cursors[idx].position();
             self.validate_position(&pos);
 
             // Insert character at current position.
-            self.content.insert_char(pos.offset, c);
+            self.replace_chars(pos.offset..pos.offset, c.encode_utf8(&mut [0; 4]));
+            self.modified = true;
 
-            // Move this cursor to the position after the inserted character.
-            let new_pos = TextPosition::new(pos.line, pos.col + 1, pos.offset + 1);
-            self.validate_position(&new_pos);
+            // Move this cursor to the position after the inserted character. The column comes from
+            // the rope, a combining mark joins the grapheme before it rather than taking a column.
+            let new_pos = self.offset_to_position(pos.offset + 1);
             mc.cursors[idx].move_to(new_pos, MoveOpts { anchor: None, update_preferred_col: true}, self);
 
             // Update positions of all other cursors affected by this insertion.
@@ -182,29 +538,19 @@ impl Buffer {
     }
 
     pub fn insert_text(&mut self, mc: &mut MultiCursor, s: &str) {
+        self.fill_virtual_space(mc);
+
         multi_cursor_operation!(mc, ascending, idx => {
             let pos = mc.cursors[idx].position();
             self.validate_position(&pos);
 
             // Insert text at current position.
-            self.content.insert(pos.offset, s);
+            self.replace_chars(pos.offset..pos.offset, s);
+            self.modified = true;
             let char_count = s.chars().count();
 
-            // Calculate new position for this cursor.
-            let new_pos = if s.contains('\n') {
-                let new_offset = pos.offset + char_count;
-                self.validate_offset(new_offset);
-                let new_line = self.content.char_to_line(new_offset);
-                let line_start = self.content.line_to_char(new_line);
-                TextPosition::new(new_line, new_offset - line_start, new_offset)
-            } else {
-                TextPosition::new(
-                    pos.line,
-                    pos.col + s.graphemes(true).count(),
-                    pos.offset + char_count,
-                )
-            };
-            self.validate_position(&new_pos);
+            // After the inserted text, which may have merged with the grapheme before it.
+            let new_pos = self.offset_to_position(pos.offset + char_count);
             mc.cursors[idx].move_to(new_pos, MoveOpts { anchor: None, update_preferred_col: true}, self);
 
             // Update positions of all other cursors affected by this insertion.
@@ -212,6 +558,22 @@ impl Buffer {
         });
     }
 
+    /// Pads the lines of cursors sitting in virtual space (see `Cursor::virtual_col`) with spaces
+    /// up to the cursor, so text typed there lands in the cursor's column.
+    fn fill_virtual_space(&mut self, mc: &mut MultiCursor) {
+        let edits: Vec<Edit> = mc
+            .cursors
+            .iter()
+            .filter(|cursor| cursor.virtual_cols() > 0)
+            .map(|cursor| {
+                let offset = cursor.position().offset;
+                (offset..offset, " ".repeat(cursor.virtual_cols()))
+            })
+            .collect();
+
+        self.apply_edits(&edits, mc);
+    }
+
     pub fn backspace(&mut self, mc: &mut MultiCursor) {
         multi_cursor_operation!(mc, descending, idx => {
             let pos = mc.cursors[idx].position();
@@ -220,11 +582,14 @@ impl Buffer {
                 continue; // We can't backspace at the beginning of the buffer.
             }
 
-            let start = self.prev_grapheme_offset(pos.offset);
+            let start = self
+                .soft_tab_start(pos)
+                .unwrap_or_else(|| self.prev_grapheme_offset(pos.offset));
             let deleted_len = pos.offset - start;
 
             // Actually perform the deletion.
-            self.content.remove(start..pos.offset);
+            self.replace_chars(start..pos.offset, "");
+            self.modified = true;
 
             // After deletion, the cursor should be at the start position.
             let new_offset = start;
@@ -245,8 +610,37 @@ impl Buffer {
             );
         });
 
-        // Ensure all positions are consistent.
+        // Ensure all positions are consistent. Deleting can also pile cursors onto one spot.
         mc.refresh_positions(self);
+        mc.merge_overlapping();
+    }
+
+    /// Where a backspace at `pos` should delete from when it's inside the line's indentation and
+    /// `soft_tab_stop` is on: the spaces back to the previous soft tab stop go at once.
+    /// Tabs are never split, the deletion stops at them.
+    fn soft_tab_start(&self, pos: TextPosition) -> Option<usize> {
+        let sts = self.options.soft_tab_stop;
+        if !self.options.expand_tab || sts == 0 || pos.col == 0 {
+            return None;
+        }
+
+        let line_start = self.content.line_to_char(pos.line);
+        let before = self.content.slice(line_start..pos.offset);
+        if !before.chars().all(|c| matches!(c, ' ' | '\t')) || before.char(before.len_chars() - 1) != ' ' {
+            return None;
+        }
+
+        let width = self.options.indent_width(before.chars());
+        let target = (width - 1) / sts * sts;
+
+        let mut start = pos.offset;
+        let mut col = width;
+        while col > target && start > line_start && self.char_at(start - 1) == Some(' ') {
+            start -= 1;
+            col -= 1;
+        }
+
+        Some(start)
     }
 
     pub fn delete(&mut self, mc: &mut MultiCursor) {
@@ -256,7 +650,8 @@ impl Buffer {
             let deleted_len = end - pos.offset; // Length of the deleted grapheme.
 
             // Perform the deletion.
-            self.content.remove(pos.offset..end);
+            self.replace_chars(pos.offset..end, "");
+            self.modified |= deleted_len > 0;
 
             // Update positions of all other cursors affected by this deletion.
             self.update_cursors_after_modification(
@@ -267,46 +662,654 @@ impl Buffer {
             );
         });
 
-        // Ensure all positions are consistent.
+        // Ensure all positions are consistent. Deleting can also pile cursors onto one spot.
         mc.refresh_positions(self);
+        mc.merge_overlapping();
+    }
+
+    /// Text of `cursor`'s selection, which includes the grapheme under its end like
+    /// `delete_selection` does.
+    pub fn selection_text(&self, cursor: &Cursor) -> String {
+        let (start, end) = cursor.selection_range(self);
+        let end = self.next_grapheme_offset(end.offset);
+        self.content.slice(start.offset..end).to_string()
+    }
+
+    /// The chars a Visual block covers on each of its `lines`, its grapheme `cols` cut short at
+    /// the end of the line. Lines too short to reach the block get an empty range.
+    pub fn block_ranges(&self, lines: Range<usize>, cols: Range<usize>) -> Vec<Range<usize>> {
+        lines
+            .map(|line| {
+                let len = self.grapheme_len(line);
+                let offset = |col: usize| self.grapheme_col_to_offset(line, col.min(len));
+                offset(cols.start)..offset(cols.end)
+            })
+            .collect()
+    }
+
+    /// The text delimited by `open` and `close` around `offset`, for text objects like `i{` or
+    /// `a"`. Brackets nest and may span lines, quotes (`open == close`) only pair up within the
+    /// cursor's line. `inner` leaves the delimiters out, along with the line breaks that put them
+    /// on lines of their own, so `di{` empties a block but keeps its braces where they are.
+    pub fn find_enclosing_pair(&self, offset: usize, open: char, close: char, inner: bool) -> Option<Range<usize>> {
+        let (start, end) = if open == close {
+            self.enclosing_quotes(offset, open)?
+        } else {
+            self.enclosing_brackets(offset, open, close)?
+        };
+
+        if !inner {
+            return Some(start..end + 1);
+        }
+
+        let mut inner_start = start + 1;
+        let mut inner_end = end;
+        if open != close && self.char_at(inner_start) == Some('\n') {
+            inner_start += 1;
+            // The closing bracket keeps its indentation, but not the line break before it.
+            let close_line = self.content.line_to_char(self.content.char_to_line(end));
+            if close_line >= inner_start && self.content.slice(close_line..end).chars().all(char::is_whitespace) {
+                inner_end = close_line;
+            }
+        }
+
+        Some(inner_start..inner_end.max(inner_start))
+    }
+
+    /// `iw`/`aw` (`iW`/`aW` with `big_word`) at `offset`: the run of word, punctuation or
+    /// whitespace characters it's on, within its line. `aw` adds the whitespace after a word, or
+    /// the whitespace before it if there's none after (unless that's the indentation). On
+    /// whitespace it adds the word after it.
+    pub fn word_object(&self, offset: usize, big_word: bool, inner: bool) -> Option<Range<usize>> {
+        let line = self.content.char_to_line(offset);
+        let line_start = self.content.line_to_char(line);
+        let line_end = line_start + self.visible_line_slice(line).len_chars();
+        if offset >= line_end {
+            return None;
+        }
+
+        let class_at = |off: usize| get_char_class(self.content.char(off), big_word);
+        let run_end = |from: usize| {
+            let class = class_at(from);
+            (from..line_end).find(|&off| class_at(off) != class).unwrap_or(line_end)
+        };
+        let run_start = |from: usize| {
+            let class = class_at(from);
+            (line_start..from).rev().find(|&off| class_at(off) != class).map_or(line_start, |off| off + 1)
+        };
+
+        let (mut start, mut end) = (run_start(offset), run_end(offset));
+        if inner {
+            return Some(start..end);
+        }
+
+        if class_at(offset) == CharClass::Whitespace {
+            if end < line_end {
+                end = run_end(end);
+            }
+        } else if end < line_end && class_at(end) == CharClass::Whitespace {
+            end = run_end(end);
+        } else if start > line_start && class_at(start - 1) == CharClass::Whitespace {
+            let before = run_start(start - 1);
+            if before > line_start {
+                start = before;
+            }
+        }
+
+        Some(start..end)
+    }
+
+    /// Vim's `%`: offset of the bracket matching the first one of `pairs` at or after `offset`
+    /// on its line. `None` when there's no bracket or it has no match. Pairs must be made of two
+    /// distinct characters.
+    pub fn matching_pair(&self, offset: usize, pairs: &[(char, char)]) -> Option<usize> {
+        let line = self.content.char_to_line(offset);
+        let line_end = self.content.line_to_char(line) + self.visible_line_slice(line).len_chars();
+
+        let (at, open, close) = (offset..line_end).find_map(|i| {
+            let c = self.char_at(i)?;
+            let &(open, close) = pairs.iter().find(|&&(open, close)| c == open || c == close)?;
+            Some((i, open, close))
+        })?;
+
+        let (start, end) = self.enclosing_brackets(at, open, close)?;
+        Some(if at == start { end } else { start })
+    }
+
+    /// Vim's `f`/`F`: offset of the `count`th `ch` after `offset` (before it unless `forward`),
+    /// on its line only.
+    pub fn find_char(&self, offset: usize, ch: char, forward: bool, count: usize) -> Option<usize> {
+        let line = self.content.char_to_line(offset);
+        let line_start = self.content.line_to_char(line);
+        let line_end = line_start + self.visible_line_slice(line).len_chars();
+
+        let is_match = |&i: &usize| self.char_at(i) == Some(ch);
+        let nth = count.max(1) - 1;
+        match forward {
+            true => (offset + 1..line_end).filter(is_match).nth(nth),
+            false => (line_start..offset).rev().filter(is_match).nth(nth),
+        }
+    }
+
+    /// Offsets of the unmatched `open` before `offset` (or on it) and the `close` matching it.
+    fn enclosing_brackets(&self, offset: usize, open: char, close: char) -> Option<(usize, usize)> {
+        let len = self.content.len_chars();
+        let start = if self.char_at(offset) == Some(open) {
+            offset
+        } else {
+            // A `close` right under the cursor is the one we're after, so it isn't counted.
+            let mut depth = 0;
+            let mut i = offset.min(len);
+            loop {
+                i = i.checked_sub(1)?;
+                match self.char_at(i)? {
+                    c if c == close => depth += 1,
+                    c if c == open && depth == 0 => break i,
+                    c if c == open => depth -= 1,
+                    _ => {}
+                }
+            }
+        };
+
+        let mut depth = 0;
+        for (i, c) in self.content.chars_at(start + 1).enumerate() {
+            match c {
+                c if c == open => depth += 1,
+                c if c == close && depth == 0 => return Some((start, start + 1 + i)),
+                c if c == close => depth -= 1,
+                _ => {}
+            }
+        }
+        None
+    }
+
+    /// Offsets of the quotes around `offset` on its line. Quotes pair up from the start of the
+    /// line, and a cursor before the first pair uses that one.
+    fn enclosing_quotes(&self, offset: usize, quote: char) -> Option<(usize, usize)> {
+        let line = self.content.char_to_line(offset);
+        let line_start = self.content.line_to_char(line);
+        let quotes: Vec<usize> = self
+            .visible_line_slice(line)
+            .chars()
+            .enumerate()
+            .filter(|&(_, c)| c == quote)
+            .map(|(col, _)| line_start + col)
+            .collect();
+
+        quotes
+            .chunks_exact(2)
+            .find(|pair| offset <= pair[1])
+            .map(|pair| (pair[0], pair[1]))
+    }
+
+    /// Vim's `p`: puts `text` after the grapheme under each cursor (`P`, `before`: at the cursor),
+    /// or (`over_selection`) in place of its selection. Each cursor lands on the last pasted
+    /// character.
+    pub fn paste(&mut self, mc: &mut MultiCursor, text: &str, before: bool, over_selection: bool) {
+        if text.is_empty() {
+            return;
+        }
+
+        if !over_selection {
+            // Step past the grapheme under each cursor, empty lines have none. This also drops
+            // the selections word motions leave behind.
+            for cursor in &mut mc.cursors {
+                let pos = cursor.position();
+                let offset = if !before && pos.col < self.grapheme_len(pos.line) {
+                    self.next_grapheme_offset(pos.offset)
+                } else {
+                    pos.offset
+                };
+                let dest = self.offset_to_position(offset);
+                cursor.move_to(dest, MoveOpts { anchor: None, update_preferred_col: false }, self);
+            }
+        }
+
+        self.replace_all_selections_text(mc, text);
+
+        for cursor in &mut mc.cursors {
+            let last = self.offset_to_position(cursor.position().offset - 1);
+            cursor.move_to(last, MoveOpts { anchor: None, update_preferred_col: true }, self);
+        }
+    }
+
+    /// `p` with whole lines (see `Registers::is_linewise`): `text` goes below each cursor's line
+    /// (`P`, `above` it), and the cursors to the first non-blank of the first line pasted.
+    pub fn paste_lines(&mut self, mc: &mut MultiCursor, text: &str, above: bool) {
+        if text.is_empty() {
+            return;
+        }
+
+        let mut lines: Vec<usize> = mc.cursors.iter().map(|cursor| cursor.position().line).collect();
+        lines.sort_unstable();
+        lines.dedup();
+
+        let edits: Vec<Edit> = lines
+            .iter()
+            .map(|&line| match self.content.get_line(line + 1) {
+                _ if above => {
+                    let start = self.content.line_to_char(line);
+                    (start..start, text.to_string())
+                }
+                Some(_) => {
+                    let start = self.content.line_to_char(line + 1);
+                    (start..start, text.to_string())
+                }
+                // The last line has no line break to paste after, it gets one before the text.
+                None => {
+                    let end = self.content.len_chars();
+                    (end..end, format!("\n{}", text.strip_suffix('\n').unwrap_or(text)))
+                }
+            })
+            .collect();
+        self.apply_edits(&edits, mc);
+
+        // Pasting above pushes the cursor's own line down.
+        let pasted_lines = text.lines().count();
+        for cursor in &mut mc.cursors {
+            let line = cursor.position().line;
+            let line = if above { line - pasted_lines } else { line + 1 };
+            cursor.move_to_line(self, line, LineColumn::FirstNonBlank, &EditorMode::Normal);
+        }
     }
 
     pub fn delete_selection(&mut self, mc: &mut MultiCursor) {
-        // Right to left so later deletions don't invalidate earlier offsets.
-        multi_cursor_operation!(mc, descending, idx => {
-            let (start, end) = mc.cursors[idx].get_selection_range();
-            
+        let mut ranges: Vec<Range<usize>> = Vec::with_capacity(mc.cursors.len());
+
+        for cursor in &mc.cursors {
+            let (start, end) = cursor.selection_range(self);
+
             self.validate_position(&start);
             self.validate_position(&end);
 
-            let del_start = start.offset;
-            let del_end   = self.next_grapheme_offset(end.offset);
-            
-            self.content.remove(del_start .. del_end);
-            
-            let mut new_pos = start;
-            new_pos.offset  = del_start;
-            new_pos.line    = self.content.char_to_line(del_start);
-            let line_start  = self.content.line_to_char(new_pos.line);
-            new_pos.col     = del_start - line_start;
-            
-            // Collapse selection at start.
-            mc.cursors[idx].move_to(
+            // The selection is inclusive, so the grapheme under `end` goes too.
+            let del_end = self.next_grapheme_offset(end.offset);
+            ranges.push(start.offset..del_end);
+        }
+
+        // Selections can overlap, those go as one. Every cursor collapses at the start of its (now
+        // removed) selection.
+        let edits: Vec<Edit> = merge_ranges(ranges).into_iter().map(|range| (range, String::new())).collect();
+        self.apply_edits(&edits, mc);
+    }
+
+    /// Type `text` over every cursor at once: selections (inclusive, like `delete_selection`) are
+    /// replaced by it, cursors without one just insert it. Each cursor ends up after its copy.
+    pub fn replace_all_selections_text(&mut self, mc: &mut MultiCursor, text: &str) {
+        let ranges: Vec<Range<usize>> = mc
+            .cursors
+            .iter()
+            .map(|cursor| {
+                if cursor.has_selection() {
+                    let (start, end) = cursor.selection_range(self);
+                    start.offset..self.next_grapheme_offset(end.offset)
+                } else {
+                    let offset = cursor.position().offset;
+                    offset..offset
+                }
+            })
+            .collect();
+        // Selections can overlap, `text` goes over those once.
+        let merged = merge_ranges(ranges.clone());
+
+        // Where the text typed over each range ends, accounting for the edits before it.
+        let text_len = text.chars().count() as isize;
+        let mut shift = 0;
+        let mut ends = Vec::with_capacity(merged.len());
+        for range in &merged {
+            ends.push((range.start as isize + shift + text_len) as usize);
+            shift += text_len - range.len() as isize;
+        }
+        // Each cursor lands after the text typed over the range it's part of.
+        let targets: Vec<usize> = ranges
+            .iter()
+            .map(|range| {
+                let i = merged.iter().position(|m| m.start <= range.start && range.end <= m.end);
+                ends[i.expect("every range is part of a merged one")]
+            })
+            .collect();
+
+        let edits: Vec<Edit> = merged.into_iter().map(|range| (range, text.to_string())).collect();
+        self.apply_edits(&edits, &mut MultiCursor::new());
+
+        for (cursor, offset) in mc.cursors.iter_mut().zip(targets) {
+            cursor.move_to(
+                self.offset_to_position(offset),
+                MoveOpts { anchor: None, update_preferred_col: true },
+                self,
+            );
+        }
+        mc.merge_overlapping();
+    }
+
+    /// Shift the line under each cursor one `shift_width` to the right (`>>`) or left (`<<`).
+    ///
+    /// The existing leading whitespace is rebuilt from its display width, so mixed tabs and spaces
+    /// are normalized according to `expand_tab`. Blank lines are never indented.
+    pub fn shift_lines(&mut self, mc: &mut MultiCursor, right: bool) {
+        let mut lines: Vec<usize> = mc.cursors.iter().map(|c| c.position().line).collect();
+        lines.sort_unstable();
+        lines.dedup();
+
+        let edits: Vec<Edit> = lines
+            .into_iter()
+            .filter_map(|line| {
+                let (indent_chars, indent) = self.line_indent(line);
+
+                if right && indent_chars == self.visible_line_slice(line).len_chars() {
+                    return None;
+                }
+
+                let width = self.options.indent_width(indent.chars());
+                let new_width = if right {
+                    width + self.options.shift_width
+                } else {
+                    width.saturating_sub(self.options.shift_width)
+                };
+
+                let line_start = self.content.line_to_char(line);
+                Some((line_start..line_start + indent_chars, self.options.indent_string(new_width)))
+            })
+            .collect();
+
+        self.apply_edits(&edits, mc);
+    }
+
+    /// Join `count` lines (at least 2) starting at each cursor's line, like Vim's `J`.
+    ///
+    /// Unless `keep_whitespace` (`gJ`) is set, the leading whitespace of every joined line is
+    /// replaced by a single space, which is left out when the line ends in whitespace already,
+    /// the joined line is blank, or it starts with `)`.
+    /// Each cursor lands on the last join: the inserted space, or the first joined character.
+    pub fn join_lines(&mut self, mc: &mut MultiCursor, count: usize, keep_whitespace: bool) {
+        let last_line = self.content.len_lines() - 1;
+        let primary_line = mc.position().line;
+
+        let mut lines: Vec<usize> = mc.cursors.iter().map(|c| c.position().line).collect();
+        lines.sort_unstable();
+        lines.dedup();
+
+        // Each group is (first line, edits), groups never share lines.
+        let mut groups: Vec<(usize, Vec<Edit>)> = Vec::new();
+        let mut next_free = 0;
+        for line in lines {
+            if line < next_free {
+                continue;
+            }
+
+            let end = (line + count.max(2) - 1).min(last_line);
+            let edits: Vec<_> = (line..end).map(|l| self.join_edit(l, keep_whitespace)).collect();
+            next_free = end + 1;
+            groups.push((line, edits));
+        }
+
+        // Where the last join of each group ends up once every edit is applied.
+        let mut delta: isize = 0;
+        let mut targets = Vec::with_capacity(groups.len());
+        for (line, edits) in &groups {
+            let mut target = None;
+            for (range, text) in edits {
+                target = Some((range.start as isize + delta) as usize);
+                delta += text.chars().count() as isize - range.len() as isize;
+            }
+            targets.push((*line, target));
+        }
+
+        let edits: Vec<_> = groups.into_iter().flat_map(|(_, edits)| edits).collect();
+        self.apply_edits(&edits, mc);
+
+        for cursor in &mut mc.cursors {
+            let line = cursor.position().line;
+            let Some(&(_, Some(target))) = targets.iter().find(|(l, _)| *l == line) else {
+                continue;
+            };
+
+            let dest = self.offset_to_position(target.min(self.content.len_chars()));
+            cursor.move_to(dest, MoveOpts { anchor: None, update_preferred_col: true }, self);
+        }
+
+        if let Some(idx) = mc.cursors.iter().position(|c| c.position().line == primary_line) {
+            mc.primary_index = idx;
+        }
+    }
+
+    /// The edit joining `line` with the one below it, see `join_lines`.
+    fn join_edit(&self, line: usize, keep_whitespace: bool) -> Edit {
+        let current = self.visible_line_slice(line);
+        let Range { start, end: next_start } = self.line_offset_range(line);
+        let start = start + current.len_chars();
+
+        if keep_whitespace {
+            return (start..next_start, String::new());
+        }
+
+        let (indent, _) = self.line_indent(line + 1);
+        let rest = self.visible_line_slice(line + 1).slice(indent..);
+
+        let ends_in_whitespace = current.chars().last().is_some_and(|c| matches!(c, ' ' | '\t'));
+        let space = if current.len_chars() == 0 || ends_in_whitespace || rest.len_chars() == 0 || rest.char(0) == ')' {
+            ""
+        } else {
+            " "
+        };
+
+        (start..next_start + indent, space.to_string())
+    }
+
+    /// Lines whose visible content matches `pattern`, or (with `invert`) doesn't.
+    /// The empty "line" after a trailing line break isn't one.
+    pub fn matching_lines(&self, pattern: &Regex, invert: bool) -> Vec<usize> {
+        (0..self.line_count())
+            .filter(|&line| {
+                let content: Cow<str> = self.visible_line_slice(line).into();
+                pattern.is_match(&content) != invert
+            })
+            .collect()
+    }
+
+    /// `lines` as they go in a register, each ending in a line break, the last line of the buffer
+    /// too.
+    pub fn lines_text(&self, lines: Range<usize>) -> String {
+        lines.map(|line| self.visible_line_content(line) + "\n").collect()
+    }
+
+    /// Number of lines as the user sees them, the empty one ropey reports after a trailing
+    /// line break doesn't count.
+    pub fn line_count(&self) -> usize {
+        let total_lines = self.content.len_lines();
+        if total_lines > 1 && self.visible_line_slice(total_lines - 1).len_chars() == 0 {
+            total_lines - 1
+        } else {
+            total_lines
+        }
+    }
+
+    /// The chars making up `line`, its line break included. The last line may not have one.
+    pub fn line_offset_range(&self, line: usize) -> Range<usize> {
+        let start = self.content.line_to_char(line);
+        match line + 1 < self.content.len_lines() {
+            true => start..self.content.line_to_char(line + 1),
+            false => start..self.content.len_chars(),
+        }
+    }
+
+    /// The chars making up whole `lines`, line breaks included. Lines at the end of the buffer
+    /// take the line break before them instead of the (missing) one after them.
+    pub fn lines_char_range(&self, lines: Range<usize>) -> Range<usize> {
+        if lines.end < self.content.len_lines() {
+            self.content.line_to_char(lines.start)..self.line_offset_range(lines.end - 1).end
+        } else if lines.start > 0 {
+            self.content.line_to_char(lines.start) - 1..self.content.len_chars()
+        } else {
+            0..self.content.len_chars()
+        }
+    }
+
+    /// Delete the text from `start` up to, but not including, `end`, whatever the cursors have
+    /// selected. The two can come in either order.
+    /// Cursors are fixed up like `apply_edits` does: those inside the range end up at its start.
+    pub fn delete_range(&mut self, start: TextPosition, end: TextPosition, mc: &mut MultiCursor) {
+        self.validate_position(&start);
+        self.validate_position(&end);
+
+        let range = start.offset.min(end.offset)..start.offset.max(end.offset);
+        self.apply_edits(&[(range, String::new())], mc);
+    }
+
+    /// Delete whole `lines`, all in a single `apply_edits` pass.
+    /// Runs of adjacent lines become one edit, a run at the end of the buffer takes the line
+    /// break before it instead of the (missing) one after it.
+    pub fn delete_lines(&mut self, lines: &[usize], mc: &mut MultiCursor) {
+        let mut lines = lines.to_vec();
+        lines.sort_unstable();
+        lines.dedup();
+
+        let mut runs: Vec<Range<usize>> = Vec::new();
+        for line in lines {
+            match runs.last_mut() {
+                Some(run) if run.end == line => run.end += 1,
+                _ => runs.push(line..line + 1),
+            }
+        }
+
+        let edits: Vec<Edit> = runs
+            .into_iter()
+            .map(|run| (self.lines_char_range(run), String::new()))
+            .collect();
+
+        self.apply_edits(&edits, mc);
+    }
+
+    /// Replaces the chars in `range` with `text`, keeping track of the bytes that changed.
+    /// Every edit to the content goes through here.
+    fn replace_chars(&mut self, range: Range<usize>, text: &str) {
+        if range.is_empty() && text.is_empty() {
+            return;
+        }
+
+        let old = self.content.char_to_byte(range.start)..self.content.char_to_byte(range.end);
+        self.content.remove(range.clone());
+        self.content.insert(range.start, text);
+        self.mark_dirty(old, text.len());
+    }
+
+    /// Grows the dirty range to cover the `old` bytes having been replaced by `new_len` others.
+    fn mark_dirty(&mut self, old: Range<usize>, new_len: usize) {
+        let new_end = old.start + new_len;
+        let dirty = match self.dirty.take() {
+            None => old.start..new_end,
+            Some(dirty) => {
+                // The end of what was dirty, where this edit moved it.
+                let end = match dirty.end {
+                    end if end <= old.start => end,
+                    end if end >= old.end => end - old.end + new_end,
+                    _ => new_end,
+                };
+                dirty.start.min(old.start)..end.max(new_end)
+            }
+        };
+        self.dirty = Some(dirty);
+    }
+
+    /// The bytes changed since the last call, as a single span of the current content, so a
+    /// syntax tree only has that much to parse again. `None` if nothing changed.
+    /// NOTE: Edits made straight to `content` aren't seen.
+    pub fn take_dirty_range(&mut self) -> Option<Range<usize>> {
+        self.dirty.take()
+    }
+
+    /// Apply several non-overlapping char-range replacements in a single pass.
+    ///
+    /// Edits are applied right to left so earlier offsets stay valid, then every cursor is mapped
+    /// through all of them once:
+    /// - Cursors before an edit are untouched.
+    /// - Cursors at or after the end of an edit shift by the edit's length difference.
+    /// - Cursors inside a replaced range collapse to its start.
+    ///
+    /// Selections are collapsed in the process.
+    pub fn apply_edits(&mut self, edits: &[Edit], mc: &mut MultiCursor) {
+        if edits.is_empty() {
+            return;
+        }
+
+        let mut sorted: Vec<&Edit> = edits.iter().collect();
+        sorted.sort_by_key(|(range, _)| range.start);
+
+        for pair in sorted.windows(2) {
+            assert!(
+                pair[0].0.end <= pair[1].0.start,
+                "Edits {:?} and {:?} overlap",
+                pair[0].0,
+                pair[1].0
+            );
+        }
+
+        // Map the cursors against the original offsets before touching the rope.
+        let new_offsets: Vec<usize> = mc
+            .cursors
+            .iter()
+            .map(|cursor| {
+                let offset = cursor.position().offset;
+                let mut shift: isize = 0;
+
+                for (range, text) in &sorted {
+                    if offset >= range.end {
+                        shift += text.chars().count() as isize - range.len() as isize;
+                    } else if offset > range.start {
+                        return (range.start as isize + shift) as usize;
+                    } else {
+                        break;
+                    }
+                }
+
+                (offset as isize + shift) as usize
+            })
+            .collect();
+
+        // Right to left so later edits don't invalidate earlier offsets.
+        for (range, text) in sorted.iter().rev() {
+            self.validate_offset(range.end);
+            assert!(range.start <= range.end, "Invalid edit range {range:?}");
+
+            self.replace_chars(range.clone(), text);
+            self.modified |= !range.is_empty() || !text.is_empty();
+        }
+
+        for (cursor, offset) in mc.cursors.iter_mut().zip(new_offsets) {
+            let new_pos = self.offset_to_position(offset);
+            cursor.move_to(
                 new_pos,
                 MoveOpts { anchor: None, update_preferred_col: false },
                 self,
             );
+        }
 
-            // Shift the other cursors.
-            self.update_cursors_after_modification(
-                mc,
-                del_start,
-                -((del_end - del_start) as isize),
-                idx
-            );
-        });
+        mc.merge_overlapping();
+    }
 
-        mc.refresh_positions(self);
+    /// Vim's `o` (`O` with `above`): opens a line below (above) each cursor's line and moves the
+    /// cursor onto it, after the indentation copied from the cursor's line if `auto_indent` is set.
+    pub fn open_line(&mut self, mc: &mut MultiCursor, above: bool) {
+        multi_cursor_operation!(mc, ascending, idx => {
+            let pos = mc.cursors[idx].position();
+            let (indent_len, indent) = match self.options.auto_indent {
+                true => self.line_indent(pos.line),
+                false => (0, String::new()),
+            };
+
+            let line_start = self.content.line_to_char(pos.line);
+            let (offset, text, line) = match above {
+                true => (line_start, format!("{indent}\n"), pos.line),
+                false => (line_start + self.visible_line_slice(pos.line).len_chars(), format!("\n{indent}"), pos.line + 1),
+            };
+            self.replace_chars(offset..offset, &text);
+            self.modified = true;
+
+            let new_pos = TextPosition::new(line, indent_len, self.content.line_to_char(line) + indent_len);
+            mc.cursors[idx].move_to(new_pos, MoveOpts { anchor: None, update_preferred_col: true }, self);
+
+            self.update_cursors_after_modification(mc, offset, text.chars().count() as isize, idx);
+        });
     }
 
     pub fn insert_newline(&mut self, multi_cursor: &mut crate::MultiCursor) {
@@ -314,7 +1317,8 @@ impl Buffer {
             let pos = multi_cursor.cursors[idx].position();
 
             // Insert newline at current position.
-            self.content.insert_char(pos.offset, '\n');
+            self.replace_chars(pos.offset..pos.offset, "\n");
+            self.modified = true;
 
             // Move this cursor to the start of the new line.
             let new_line = pos.line + 1;
@@ -354,23 +1358,10 @@ impl Buffer {
                 };
 
                 if should_update {
-                    let new_offset = if offset_delta > 0 {
-                        cursor_pos.offset + offset_delta as usize
-                    } else {
-                        cursor_pos.offset - (-offset_delta) as usize
-                    };
-
-                    let mut updated_pos = TextPosition {
-                        offset: new_offset,
-                        line: cursor_pos.line,
-                        col: cursor_pos.col,
-                    };
-
-                    // Recalculate line and column based on new offset.
-                    updated_pos.line = self.content.char_to_line(updated_pos.offset);
-                    let line_start = self.content.line_to_char(updated_pos.line);
-                    updated_pos.col = updated_pos.offset - line_start;
+                    let new_offset = cursor_pos.offset.saturating_add_signed(offset_delta);
 
+                    // Recalculate line and (grapheme) column based on new offset.
+                    let updated_pos = self.offset_to_position(new_offset);
                     self.validate_position(&updated_pos);
                     cursor.move_to(
                         updated_pos,
@@ -417,3 +1408,648 @@ impl Buffer {
         );
     }
 }
+
+fn cursor_offsets(mc: &MultiCursor) -> Vec<usize> {
+    mc.cursors.iter().map(|cursor| cursor.position().offset).collect()
+}
+
+/// `ranges` sorted, with the ones that overlap or touch joined into one.
+fn merge_ranges(mut ranges: Vec<Range<usize>>) -> Vec<Range<usize>> {
+    ranges.sort_by_key(|range| range.start);
+
+    let mut merged: Vec<Range<usize>> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cursor_at(buffer: &Buffer, offset: usize) -> MultiCursor {
+        let mut mc = MultiCursor::new();
+        mc.cursors[0].move_to(
+            buffer.offset_to_position(offset),
+            MoveOpts { anchor: None, update_preferred_col: true },
+            buffer,
+        );
+        mc
+    }
+
+    #[test]
+    fn visible_line_slice_matches_owned_content() {
+        let buffer = Buffer::new("first\r\nsecond\n\nlast", "t");
+
+        for line in 0..buffer.content.len_lines() {
+            let slice = buffer.visible_line_slice(line);
+            assert_eq!(slice.to_string(), buffer.visible_line_content(line), "line {line}");
+            assert!(!slice.chars().any(|c| c == '\n' || c == '\r'));
+        }
+
+        assert_eq!(buffer.visible_line_slice(0), "first");
+        assert_eq!(buffer.visible_line_slice(2), "");
+        assert_eq!(buffer.visible_line_slice(3), "last"); // Last line, no trailing newline.
+    }
+
+    #[test]
+    fn edits_mark_buffer_modified() {
+        let mut buffer = Buffer::new("foo", "t");
+        let mut mc = cursor_at(&buffer, 3);
+        assert!(!buffer.modified);
+
+        // Nothing to delete at the end of the buffer.
+        buffer.delete(&mut mc);
+        buffer.apply_edits(&[(1..1, String::new())], &mut mc);
+        assert!(!buffer.modified);
+
+        buffer.insert_char(&mut mc, '!');
+        assert!(buffer.modified);
+    }
+
+    #[test]
+    fn delete_matching_lines() {
+        let mut buffer = Buffer::new("keep 1\ndrop 1\ndrop 2\nkeep 2\ndrop 3", "t");
+        assert_eq!(Buffer::new("a\n", "t").matching_lines(&Regex::new("^$").unwrap(), false), vec![]);
+        let mut mc = cursor_at(&buffer, 0);
+
+        let lines = buffer.matching_lines(&Regex::new("^drop").unwrap(), false);
+        assert_eq!(lines, vec![1, 2, 4]);
+
+        buffer.delete_lines(&lines, &mut mc);
+        assert_eq!(buffer.content.to_string(), "keep 1\nkeep 2");
+
+        let lines = buffer.matching_lines(&Regex::new("1").unwrap(), true);
+        assert_eq!(lines, vec![1]);
+
+        buffer.delete_lines(&[0, 1], &mut mc);
+        assert_eq!(buffer.content.to_string(), "");
+    }
+
+    #[test]
+    fn display_columns_expand_tabs() {
+        let mut buffer = Buffer::new("\tab\tc\nplain", "t");
+        buffer.options.tab_width = 4;
+
+        let display: Vec<_> = (0..=5).map(|col| buffer.display_col(0, col)).collect();
+        assert_eq!(display, vec![0, 4, 5, 6, 8, 9]);
+        assert_eq!(buffer.display_col(0, 7), 11); // Virtual space.
+
+        // Every cell of a tab maps back to it.
+        let cols: Vec<_> = (0..10).map(|d| buffer.col_at_display(0, d)).collect();
+        assert_eq!(cols, vec![0, 0, 0, 0, 1, 2, 3, 3, 4, 5]);
+
+        for col in 0..=5 {
+            assert_eq!(buffer.col_at_display(0, buffer.display_col(0, col)), col);
+            assert_eq!(buffer.col_at_display(1, buffer.display_col(1, col)), col);
+        }
+    }
+
+    #[test]
+    fn line_indent_returns_leading_whitespace() {
+        let buffer = Buffer::new("    spaces\n\t\ttabs\n \t mixed\nnone\n   \n", "t");
+
+        assert_eq!(buffer.line_indent(0), (4, "    ".to_string()));
+        assert_eq!(buffer.line_indent(1), (2, "\t\t".to_string()));
+        assert_eq!(buffer.line_indent(2), (3, " \t ".to_string()));
+        assert_eq!(buffer.line_indent(3), (0, String::new()));
+        assert_eq!(buffer.line_indent(4), (3, "   ".to_string())); // Whitespace-only.
+        assert_eq!(buffer.line_indent(5), (0, String::new())); // Empty last line.
+
+        // A trailing newline leaves an empty last line.
+        let buffer = Buffer::new("only\n", "t");
+        assert_eq!(buffer.visible_line_slice(1), "");
+    }
+
+    #[test]
+    fn apply_edits_replaces_several_ranges_at_once() {
+        let mut buffer = Buffer::new("foo bar baz\nqux\n", "t");
+        let mut mc = cursor_at(&buffer, 12); // 'q'.
+        mc.add_cursor(buffer.offset_to_position(4), &buffer); // 'b' of "bar".
+
+        // Given in arbitrary order, offsets refer to the original text.
+        buffer.apply_edits(
+            &[
+                (8..11, "quux".to_string()),  // baz -> quux
+                (0..3, "f".to_string()),      // foo -> f
+                (12..12, ">> ".to_string()), // Pure insertion at the start of line 1.
+            ],
+            &mut mc,
+        );
+
+        assert_eq!(buffer.content.to_string(), "f bar quux\n>> qux\n");
+
+        let positions: Vec<_> = mc.all_cursors().iter().map(|c| c.position()).collect();
+        assert_eq!(positions[0], TextPosition::new(0, 2, 2)); // Still on 'b'.
+        assert_eq!(positions[1], TextPosition::new(1, 3, 14)); // Still on 'q', after the insertion.
+    }
+
+    #[test]
+    fn delete_range_spans_lines_and_moves_cursors() {
+        let mut buffer = Buffer::new("one\ntwo\nthree\n", "t");
+        let mut mc = cursor_at(&buffer, 1);
+        for offset in [5, 12] {
+            mc.add_cursor(buffer.offset_to_position(offset), &buffer);
+        }
+
+        // From `n` in `one` to `r` in `three`, which stays.
+        let (start, end) = (buffer.offset_to_position(1), buffer.offset_to_position(10));
+        buffer.delete_range(end, start, &mut mc);
+        assert_eq!(buffer.content.to_string(), "oree\n");
+        assert!(buffer.modified);
+
+        // The cursors at the start and inside pile up there, the one after follows its text.
+        let positions: Vec<TextPosition> = mc.all_cursors().iter().map(|cursor| cursor.position()).collect();
+        assert_eq!(positions, [TextPosition::new(0, 1, 1), TextPosition::new(0, 3, 3)]);
+    }
+
+    #[test]
+    fn apply_edits_collapses_cursors_inside_replaced_ranges() {
+        let mut buffer = Buffer::new("hello world", "t");
+        let mut mc = cursor_at(&buffer, 8); // 'r'.
+
+        buffer.apply_edits(&[(6..11, "there".to_string())], &mut mc);
+
+        assert_eq!(buffer.content.to_string(), "hello there");
+        assert_eq!(mc.position(), TextPosition::new(0, 6, 6));
+        assert!(!mc.primary().has_selection());
+    }
+
+    #[test]
+    fn shift_lines_indents_and_dedents_by_shift_width() {
+        let mut buffer = Buffer::new("foo\n\n  bar\n", "t");
+        let mut mc = cursor_at(&buffer, 1); // 'o'.
+        mc.add_cursor(buffer.offset_to_position(4), &buffer); // Empty line.
+        mc.add_cursor(buffer.offset_to_position(7), &buffer); // 'b'.
+
+        buffer.shift_lines(&mut mc, true);
+        assert_eq!(buffer.content.to_string(), "    foo\n\n      bar\n");
+        // Cursors stay on the same characters.
+        assert_eq!(buffer.content.char(mc.all_cursors()[0].position().offset), 'o');
+        assert_eq!(buffer.content.char(mc.all_cursors()[2].position().offset), 'b');
+
+        buffer.shift_lines(&mut mc, false);
+        buffer.shift_lines(&mut mc, false);
+        assert_eq!(buffer.content.to_string(), "foo\n\nbar\n");
+    }
+
+    #[test]
+    fn shift_lines_with_tabs() {
+        let mut buffer = Buffer::new("\t  foo", "t");
+        buffer.options.expand_tab = false;
+        let mut mc = MultiCursor::new();
+
+        // 6 columns of mixed indent become 10, i.e. two tabs and two spaces.
+        buffer.shift_lines(&mut mc, true);
+        assert_eq!(buffer.content.to_string(), "\t\t  foo");
+
+        buffer.shift_lines(&mut mc, false);
+        buffer.shift_lines(&mut mc, false);
+        assert_eq!(buffer.content.to_string(), "  foo");
+    }
+
+    #[test]
+    fn backspace_removes_a_soft_tab_stop_of_indent() {
+        let mut buffer = Buffer::new("        foo", "t");
+        buffer.options.soft_tab_stop = 4;
+        let mut mc = cursor_at(&buffer, 8); // 'f'.
+
+        buffer.backspace(&mut mc);
+        assert_eq!(buffer.content.to_string(), "    foo");
+        assert_eq!(mc.position(), TextPosition::new(0, 4, 4));
+
+        buffer.backspace(&mut mc);
+        assert_eq!(buffer.content.to_string(), "foo");
+        assert_eq!(mc.position(), TextPosition::new(0, 0, 0));
+    }
+
+    #[test]
+    fn soft_tab_backspace_aligns_to_tab_stop() {
+        // Misaligned indent: 6 columns go back to 4.
+        let mut buffer = Buffer::new("      foo", "t");
+        buffer.options.soft_tab_stop = 4;
+        let mut mc = cursor_at(&buffer, 6);
+        buffer.backspace(&mut mc);
+        assert_eq!(buffer.content.to_string(), "    foo");
+
+        // Mixed tabs and spaces: only the spaces after the tab go.
+        let mut buffer = Buffer::new("\t  foo", "t");
+        buffer.options.soft_tab_stop = 4;
+        let mut mc = cursor_at(&buffer, 3);
+        buffer.backspace(&mut mc);
+        assert_eq!(buffer.content.to_string(), "\tfoo");
+
+        // Outside of the indentation it's a regular backspace.
+        let mut buffer = Buffer::new("    a   b", "t");
+        buffer.options.soft_tab_stop = 4;
+        let mut mc = cursor_at(&buffer, 8);
+        buffer.backspace(&mut mc);
+        assert_eq!(buffer.content.to_string(), "    a  b");
+
+        // And it's off without `soft_tab_stop`.
+        let mut buffer = Buffer::new("    foo", "t");
+        let mut mc = cursor_at(&buffer, 4);
+        buffer.backspace(&mut mc);
+        assert_eq!(buffer.content.to_string(), "   foo");
+    }
+
+    #[test]
+    fn join_lines_collapses_indent_into_one_space() {
+        let mut buffer = Buffer::new("fn foo(\n    a,\n)\n", "t");
+        let mut mc = MultiCursor::new();
+
+        buffer.join_lines(&mut mc, 2, false);
+        assert_eq!(buffer.content.to_string(), "fn foo( a,\n)\n");
+        assert_eq!(mc.position(), TextPosition::new(0, 7, 7)); // The inserted space.
+
+        // No space before a closing paren.
+        buffer.join_lines(&mut mc, 2, false);
+        assert_eq!(buffer.content.to_string(), "fn foo( a,)\n");
+        assert_eq!(mc.position(), TextPosition::new(0, 10, 10));
+
+        // Nor when joining onto an empty line.
+        let mut buffer = Buffer::new("\n    foo\n", "t");
+        let mut mc = MultiCursor::new();
+        buffer.join_lines(&mut mc, 2, false);
+        assert_eq!(buffer.content.to_string(), "foo\n");
+        assert_eq!(mc.position(), TextPosition::new(0, 0, 0));
+    }
+
+    #[test]
+    fn raw_join_keeps_whitespace() {
+        let mut buffer = Buffer::new("foo\n  bar\nbaz\nqux", "t");
+        let mut mc = MultiCursor::new();
+
+        buffer.join_lines(&mut mc, 2, true);
+        assert_eq!(buffer.content.to_string(), "foo  bar\nbaz\nqux");
+        assert_eq!(mc.position(), TextPosition::new(0, 3, 3)); // First joined character.
+
+        // A count joins several lines at once.
+        buffer.join_lines(&mut mc, 3, true);
+        assert_eq!(buffer.content.to_string(), "foo  barbazqux");
+        assert_eq!(mc.position(), TextPosition::new(0, 11, 11));
+
+        // Nothing left to join.
+        buffer.join_lines(&mut mc, 2, true);
+        assert_eq!(buffer.content.to_string(), "foo  barbazqux");
+    }
+
+    #[test]
+    fn typing_replaces_every_selection() {
+        let mut buffer = Buffer::new("let foo = foo + 1;\n", "t");
+        let mut mc = cursor_at(&buffer, 4);
+        mc.add_cursor(buffer.offset_to_position(10), &buffer);
+        // Select both "foo"s.
+        for cursor in mc.all_cursors_mut() {
+            let anchor = cursor.position();
+            let end = buffer.offset_to_position(anchor.offset + 2);
+            cursor.move_to(end, MoveOpts { anchor: Some(anchor), update_preferred_col: true }, &buffer);
+        }
+
+        buffer.replace_all_selections_text(&mut mc, "count");
+
+        assert_eq!(buffer.content.to_string(), "let count = count + 1;\n");
+        let offsets: Vec<_> = mc.all_cursors().iter().map(|c| c.position().offset).collect();
+        assert_eq!(offsets, [9, 17]);
+        assert!(mc.all_cursors().iter().all(|c| !c.has_selection()));
+    }
+
+    #[test]
+    fn overlapping_selections_are_deleted_as_one() {
+        let mut buffer = Buffer::new("hello world", "t");
+        let mut mc = cursor_at(&buffer, 0);
+        mc.add_cursor(buffer.offset_to_position(1), &buffer);
+        // `vll` at both: "hel" and "ell".
+        for cursor in mc.all_cursors_mut() {
+            let anchor = cursor.position();
+            let end = buffer.offset_to_position(anchor.offset + 2);
+            cursor.move_to(end, MoveOpts { anchor: Some(anchor), update_preferred_col: true }, &buffer);
+        }
+
+        buffer.delete_selection(&mut mc);
+        assert_eq!(buffer.content.to_string(), "o world");
+        assert!(mc.all_cursors().iter().all(|c| c.position().offset == 0 && !c.has_selection()));
+    }
+
+    #[test]
+    fn typing_over_overlapping_selections_types_once() {
+        let mut buffer = Buffer::new("hello world", "t");
+        let mut mc = cursor_at(&buffer, 0);
+        mc.add_cursor(buffer.offset_to_position(1), &buffer);
+        mc.add_cursor(buffer.offset_to_position(8), &buffer);
+        // "hel" and "ell" overlap, "rld" is apart.
+        for cursor in mc.all_cursors_mut() {
+            let anchor = cursor.position();
+            let end = buffer.offset_to_position(anchor.offset + 2);
+            cursor.move_to(end, MoveOpts { anchor: Some(anchor), update_preferred_col: true }, &buffer);
+        }
+
+        buffer.replace_all_selections_text(&mut mc, "X");
+        assert_eq!(buffer.content.to_string(), "Xo woX");
+        let offsets: Vec<_> = mc.all_cursors().iter().map(|c| c.position().offset).collect();
+        assert_eq!(offsets, [1, 6]);
+    }
+
+    #[test]
+    #[should_panic(expected = "overlap")]
+    fn apply_edits_rejects_overlapping_ranges() {
+        let mut buffer = Buffer::new("hello world", "t");
+        let mut mc = MultiCursor::new();
+        buffer.apply_edits(&[(0..5, String::new()), (3..7, String::new())], &mut mc);
+    }
+
+    #[test]
+    fn byte_order_mark_is_hidden_and_saved_back() {
+        let buffer = Buffer::new("\u{feff}fn main() {}\n", "main.rs");
+        assert!(buffer.bom);
+        assert_eq!(buffer.visible_line_content(0), "fn main() {}");
+        assert_eq!(buffer.grapheme_len(0), 12);
+
+        let mut saved = Vec::new();
+        buffer.write_to(&mut saved).unwrap();
+        assert_eq!(saved, b"\xef\xbb\xbffn main() {}\n");
+
+        let buffer = Buffer::new("plain\n", "t");
+        let mut saved = Vec::new();
+        buffer.write_to(&mut saved).unwrap();
+        assert!(!buffer.bom);
+        assert_eq!(saved, b"plain\n");
+    }
+
+    #[test]
+    fn saving_applies_line_endings_and_whitespace_options() {
+        let saved = |buffer: &Buffer| {
+            let mut bytes = Vec::new();
+            buffer.write_to(&mut bytes).unwrap();
+            String::from_utf8(bytes).unwrap()
+        };
+        let mut buffer = Buffer::new("a  \n\tb\t\n\nc", "t");
+        assert_eq!(saved(&buffer), "a  \n\tb\t\n\nc");
+
+        buffer.options.trim_trailing_whitespace = true;
+        buffer.options.insert_final_newline = Some(true);
+        assert_eq!(saved(&buffer), "a\n\tb\n\nc\n");
+
+        buffer.options.end_of_line = Some(LineEnding::CrLf);
+        assert_eq!(saved(&buffer), "a\r\n\tb\r\n\r\nc\r\n");
+
+        let mut buffer = Buffer::new("a\r\nb\r\n", "t");
+        buffer.options.insert_final_newline = Some(false);
+        assert_eq!(saved(&buffer), "a\r\nb");
+    }
+
+    #[test]
+    fn crlf_files_keep_their_line_endings_through_edits() {
+        assert_eq!(Buffer::new("a\nb\r\n", "t").line_ending, LineEnding::Lf);
+        assert_eq!(Buffer::new("a\rb", "t").line_ending, LineEnding::Cr);
+        assert_eq!(Buffer::new("a", "t").line_ending, LineEnding::Lf);
+
+        let mut buffer = Buffer::new("one\r\ntwo\r\n", "t");
+        let mut mc = MultiCursor::new();
+        assert_eq!(buffer.line_ending, LineEnding::CrLf);
+        // The `\r` isn't a column of its own.
+        assert_eq!(buffer.grapheme_len(0), 3);
+
+        buffer.insert_text(&mut mc, "zero\n");
+        assert_eq!(mc.position(), TextPosition::new(1, 0, 5));
+
+        let mut bytes = Vec::new();
+        buffer.write_to(&mut bytes).unwrap();
+        assert_eq!(String::from_utf8(bytes).unwrap(), "zero\r\none\r\ntwo\r\n");
+    }
+
+    #[test]
+    fn loading_keeps_the_file_exactly_as_it_is() {
+        let path = std::env::temp_dir().join(format!("atlas-load-{}.txt", std::process::id()));
+
+        // An empty file still has a line to put the cursor on.
+        fs::write(&path, "").unwrap();
+        let buffer = Buffer::from_path(&path).unwrap();
+        assert_eq!(buffer.name, path.file_name().unwrap().to_string_lossy());
+        assert_eq!(buffer.file_path.as_deref(), Some(path.as_path()));
+        assert_eq!(buffer.line_count(), 1);
+        buffer.validate_position(&TextPosition::new(0, 0, 0));
+
+        // No newline at the end, and none added by saving.
+        fs::write(&path, "\u{feff}one\ntwo").unwrap();
+        let mut buffer = Buffer::from_path(&path).unwrap();
+        assert_eq!(buffer.content.to_string(), "one\ntwo");
+        buffer.modified = true;
+        buffer.save().unwrap();
+        let saved = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(saved, "\u{feff}one\ntwo".as_bytes());
+
+        assert!(Buffer::from_path(&path).is_err());
+    }
+
+    #[test]
+    fn only_modified_files_are_autosaved() {
+        let mut scratch = Buffer::new("", "scratch");
+        scratch.modified = true;
+        assert!(!scratch.needs_autosave());
+
+        let mut file = Buffer::new("", "notes.md");
+        file.file_path = Some(PathBuf::from("notes.md"));
+        assert!(!file.needs_autosave());
+        file.modified = true;
+        assert!(file.needs_autosave());
+    }
+
+    #[test]
+    fn saving_writes_the_file_and_clears_modified() {
+        let path = std::env::temp_dir().join(format!("atlas-save-{}.txt", std::process::id()));
+        fs::write(&path, "old\n").unwrap();
+
+        let mut buffer = Buffer::from_path(&path).unwrap();
+        let mut mc = MultiCursor::new();
+        buffer.insert_text(&mut mc, "new ");
+        assert!(buffer.modified);
+
+        buffer.save().unwrap();
+        let saved = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(saved, "new old\n");
+        assert!(!buffer.modified);
+
+        // Nothing's left behind next to it.
+        let temp = path.with_file_name(format!(".{}.atlas-save", path.file_name().unwrap().to_string_lossy()));
+        assert!(!temp.exists());
+
+        let mut scratch = Buffer::new("", "scratch");
+        assert!(scratch.save().is_err());
+
+        // A failed save leaves the buffer modified.
+        buffer.file_path = Some(path.join("missing").join("file.txt"));
+        buffer.modified = true;
+        assert!(buffer.save().is_err());
+        assert!(buffer.modified);
+    }
+
+    #[test]
+    fn detects_the_indentation_of_a_file() {
+        let spaces = "fn main() {\n  if x {\n    y();\n\n    z();\n  }\n}\n";
+        assert_eq!(Buffer::new(spaces, "t").detect_indent(), Some(IndentStyle::Spaces(2)));
+
+        let tabs = "/*\n * C comment\n */\nint main() {\n\tif (x) {\n\t\ty();\n\t}\n}\n";
+        assert_eq!(Buffer::new(tabs, "t").detect_indent(), Some(IndentStyle::Tabs));
+
+        assert_eq!(Buffer::new("a\nb\n", "t").detect_indent(), None);
+        assert_eq!(Buffer::new("a\n\tb\n    c\n", "t").detect_indent(), None);
+
+        // Loading a file overrides the filetype defaults.
+        let path = std::env::temp_dir().join(format!("atlas-indent-{}.go", std::process::id()));
+        fs::write(&path, spaces).unwrap();
+        let buffer = Buffer::from_path(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(buffer.options.expand_tab);
+        assert_eq!(buffer.options.shift_width, 2);
+    }
+
+    #[test]
+    fn enclosing_pairs_nest_and_quotes_stay_on_their_line() {
+        let buffer = Buffer::new("f(a, (b), c)\nsay(\"hi\", \"you\")\n\"x\n", "t");
+
+        // On `c`, the inner parens are skipped. On the inner `(`, that pair is used.
+        assert_eq!(buffer.find_enclosing_pair(10, '(', ')', true), Some(2..11));
+        assert_eq!(buffer.find_enclosing_pair(5, '(', ')', false), Some(5..8));
+        assert_eq!(buffer.find_enclosing_pair(0, '[', ']', true), None);
+
+        // Second line: `say("hi", "you")`, the cursor on `y` of `you`.
+        let line_start = buffer.content.line_to_char(1);
+        assert_eq!(buffer.find_enclosing_pair(line_start + 11, '"', '"', true), Some(line_start + 11..line_start + 14));
+        // Before the first pair, that one is used.
+        assert_eq!(buffer.find_enclosing_pair(line_start, '"', '"', false), Some(line_start + 4..line_start + 8));
+        // A lone quote doesn't pair with one on another line.
+        assert_eq!(buffer.find_enclosing_pair(buffer.content.line_to_char(2), '"', '"', true), None);
+    }
+
+    #[test]
+    fn matching_pairs_use_the_given_pairs() {
+        let defaults = [('(', ')'), ('[', ']'), ('{', '}')];
+        let buffer = Buffer::new("let v: Vec<Option<u8>> = f(a[0]);\n", "t");
+
+        // From the start of the line, the first bracket is the `(` of the call.
+        assert_eq!(buffer.matching_pair(0, &defaults), Some(31));
+        assert_eq!(buffer.matching_pair(31, &defaults), Some(26));
+        assert_eq!(buffer.matching_pair(28, &defaults), Some(30));
+        // Angle brackets only match once they're configured.
+        let with_angles = [defaults.as_slice(), &[('<', '>')]].concat();
+        assert_eq!(buffer.matching_pair(0, &with_angles), Some(21));
+        assert_eq!(buffer.matching_pair(20, &with_angles), Some(17));
+        // Nothing after the cursor on its line.
+        assert_eq!(buffer.matching_pair(32, &defaults), None);
+    }
+
+    #[test]
+    fn char_and_grapheme_accessors_stop_at_the_end() {
+        let buffer = Buffer::new("ae\u{301}\n", "t");
+
+        assert_eq!(buffer.char_at(0), Some('a'));
+        assert_eq!(buffer.grapheme_at(1).as_deref(), Some("e\u{301}"));
+        assert_eq!(buffer.char_at(3), Some('\n'));
+
+        let len = buffer.content.len_chars();
+        assert_eq!(buffer.char_at(len), None);
+        assert_eq!(buffer.grapheme_at(len), None);
+        assert_eq!(buffer.char_at(len + 5), None);
+        assert_eq!(buffer.grapheme_at(len + 5), None);
+    }
+
+    #[test]
+    fn combining_marks_join_the_grapheme_before_them() {
+        let mut buffer = Buffer::new("", "t");
+        let mut mc = MultiCursor::new();
+
+        buffer.insert_char(&mut mc, 'e');
+        buffer.insert_char(&mut mc, '\u{301}');
+        assert_eq!((mc.position().col, mc.position().offset), (1, 2));
+
+        buffer.insert_text(&mut mc, "\u{301}x");
+        assert_eq!((mc.position().col, mc.position().offset), (2, 4));
+
+        // Columns after a newline count graphemes too.
+        buffer.insert_text(&mut mc, "\ne\u{301}");
+        assert_eq!((mc.position().line, mc.position().col, mc.position().offset), (1, 1, 7));
+    }
+
+    #[test]
+    fn cursors_on_the_same_spot_type_once() {
+        let mut buffer = Buffer::new("ab\n", "t");
+        let mut mc = cursor_at(&buffer, 1);
+        let mut twin = mc.cursors[0].clone();
+        twin.move_to(buffer.offset_to_position(1), MoveOpts { anchor: None, update_preferred_col: true }, &buffer);
+        mc.cursors.push(twin);
+
+        buffer.insert_char(&mut mc, 'x');
+        assert_eq!(buffer.content.to_string(), "axb\n");
+        assert_eq!(mc.all_cursors().len(), 1);
+        assert_eq!(mc.position().offset, 2);
+
+        // Backspacing two adjacent cursors leaves both at the start of the line.
+        let mut buffer = Buffer::new("ab\n", "t");
+        let mut mc = cursor_at(&buffer, 1);
+        mc.add_cursor(buffer.offset_to_position(2), &buffer);
+        buffer.backspace(&mut mc);
+        buffer.insert_text(&mut mc, "yz");
+        assert_eq!(buffer.content.to_string(), "yz\n");
+        assert_eq!(mc.position().offset, 2);
+    }
+
+    #[test]
+    fn adjacent_cursors_each_type() {
+        let mut buffer = Buffer::new("ab\n", "t");
+        let mut mc = cursor_at(&buffer, 0);
+        mc.add_cursor(buffer.offset_to_position(1), &buffer);
+
+        buffer.insert_char(&mut mc, 'x');
+        assert_eq!(buffer.content.to_string(), "xaxb\n");
+        let offsets: Vec<_> = mc.all_cursors().iter().map(|c| c.position().offset).collect();
+        assert_eq!(offsets, [1, 3]);
+
+        buffer.insert_newline(&mut mc);
+        assert_eq!(buffer.content.to_string(), "x\nax\nb\n");
+    }
+
+    #[test]
+    fn line_offset_ranges_take_the_line_break_when_there_is_one() {
+        let buffer = Buffer::new("one\ntwo\nend", "t");
+        assert_eq!(buffer.line_offset_range(0), 0..4);
+        assert_eq!(buffer.line_offset_range(1), 4..8);
+        assert_eq!(buffer.line_offset_range(2), 8..11);
+
+        // After a trailing line break, ropey's empty last line.
+        let buffer = Buffer::new("one\n", "t");
+        assert_eq!(buffer.line_offset_range(0), 0..4);
+        assert_eq!(buffer.line_offset_range(1), 4..4);
+    }
+
+    #[test]
+    fn edits_add_up_to_one_dirty_range() {
+        let mut buffer = Buffer::new("hello world\n", "t");
+        let mut mc = cursor_at(&buffer, 6);
+
+        buffer.insert_text(&mut mc, "big ");
+        buffer.apply_edits(&[(0..1, "J".to_string())], &mut mc);
+        assert_eq!(buffer.content.to_string(), "Jello big world\n");
+        assert_eq!(buffer.take_dirty_range(), Some(0..10));
+        assert_eq!(buffer.take_dirty_range(), None);
+
+        // Bytes, not chars, and a deletion before what's dirty moves it along.
+        buffer.insert_char(&mut mc, 'é');
+        assert_eq!(buffer.take_dirty_range(), Some(10..12));
+        buffer.insert_char(&mut mc, 'é');
+        buffer.apply_edits(&[(0..6, String::new())], &mut mc);
+        assert_eq!(buffer.content.to_string(), "big ééworld\n");
+        assert_eq!(buffer.take_dirty_range(), Some(0..8));
+
+        // Undoing changes it all.
+        buffer.checkpoint(&mc);
+        buffer.travel_history(-1, &mut mc);
+        assert_eq!(buffer.take_dirty_range(), Some(0..buffer.content.len_bytes()));
+    }
+}
@@ -0,0 +1,247 @@
+//! `.editorconfig` support: the files from a file's directory up to the one marked `root`, read
+//! into buffer-local options. See https://editorconfig.org.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{self, Path},
+};
+
+use regex::Regex;
+
+use crate::{options::LineEnding, BufferOptions};
+
+const FILE_NAME: &str = ".editorconfig";
+
+/// Sets the options `.editorconfig` files have for the file at `path`. Closer files win over
+/// those further up, and later sections over earlier ones. Files that can't be read are skipped.
+pub fn apply(options: &mut BufferOptions, path: &Path) {
+    let properties = properties_for(path);
+
+    match properties.get("indent_style").map(String::as_str) {
+        Some("tab") => options.expand_tab = false,
+        Some("space") => options.expand_tab = true,
+        _ => {}
+    }
+
+    let number = |key: &str| properties.get(key).and_then(|value| value.parse().ok()).filter(|&n: &usize| n > 0);
+    let indent_size = number("indent_size");
+    if let Some(width) = number("tab_width").or(indent_size) {
+        options.tab_width = width;
+    }
+    match properties.get("indent_size").map(String::as_str) {
+        Some("tab") => options.shift_width = options.tab_width,
+        _ => options.shift_width = indent_size.unwrap_or(options.shift_width),
+    }
+
+    match properties.get("end_of_line").map(String::as_str) {
+        Some("lf") => options.end_of_line = Some(LineEnding::Lf),
+        Some("crlf") => options.end_of_line = Some(LineEnding::CrLf),
+        Some("cr") => options.end_of_line = Some(LineEnding::Cr),
+        _ => {}
+    }
+
+    match properties.get("insert_final_newline").map(String::as_str) {
+        Some("true") => options.insert_final_newline = Some(true),
+        Some("false") => options.insert_final_newline = Some(false),
+        _ => {}
+    }
+
+    match properties.get("trim_trailing_whitespace").map(String::as_str) {
+        Some("true") => options.trim_trailing_whitespace = true,
+        Some("false") => options.trim_trailing_whitespace = false,
+        _ => {}
+    }
+}
+
+/// Every property set for `path`, names and values lowercased.
+fn properties_for(path: &Path) -> HashMap<String, String> {
+    let path = path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+
+    // Nearest first, stopping at `root = true`.
+    let mut files = Vec::new();
+    for dir in path.ancestors().skip(1) {
+        let Ok(content) = fs::read_to_string(dir.join(FILE_NAME)) else {
+            continue;
+        };
+        let file = parse(&content);
+        let root = file.root;
+        files.push((dir, file));
+        if root {
+            break;
+        }
+    }
+
+    let mut properties = HashMap::new();
+    for (dir, file) in files.into_iter().rev() {
+        let Ok(relative) = path.strip_prefix(dir) else {
+            continue;
+        };
+        let relative = relative.to_string_lossy().replace('\\', "/");
+
+        for section in file.sections.iter().filter(|section| section.matches(&relative)) {
+            properties.extend(section.properties.iter().cloned());
+        }
+    }
+
+    properties
+}
+
+#[derive(Debug, Default)]
+struct File {
+    root: bool,
+    sections: Vec<Section>,
+}
+
+#[derive(Debug)]
+struct Section {
+    /// The section's glob, `None` if it couldn't be made sense of.
+    pattern: Option<Regex>,
+    properties: Vec<(String, String)>,
+}
+
+impl Section {
+    fn matches(&self, relative_path: &str) -> bool {
+        self.pattern.as_ref().is_some_and(|pattern| pattern.is_match(relative_path))
+    }
+}
+
+fn parse(content: &str) -> File {
+    let mut file = File::default();
+
+    for line in content.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with(['#', ';']) {
+            continue;
+        }
+
+        if let Some(glob) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+            file.sections.push(Section {
+                pattern: glob_regex(glob),
+                properties: Vec::new(),
+            });
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let property = (key.trim().to_lowercase(), value.trim().to_lowercase());
+
+        match file.sections.last_mut() {
+            Some(section) => section.properties.push(property),
+            // Only `root` goes before the first section.
+            None => file.root |= property.0 == "root" && property.1 == "true",
+        }
+    }
+
+    file
+}
+
+/// A section glob as a regex over paths relative to the `.editorconfig`'s directory. Globs
+/// without a `/` match the file name in any directory below.
+/// NOTE: Numeric ranges (`{1..3}`) aren't supported.
+fn glob_regex(glob: &str) -> Option<Regex> {
+    let (glob, anchored) = match glob.strip_prefix('/') {
+        Some(glob) => (glob, true),
+        None => (glob, glob.contains('/')),
+    };
+
+    let mut regex = String::from(if anchored { "^" } else { "^(?:.*/)?" });
+    let mut chars = glob.chars().peekable();
+    let mut braces = 0;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                regex.push_str(".*");
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '[' => {
+                regex.push('[');
+                if chars.next_if_eq(&'!').is_some() {
+                    regex.push('^');
+                }
+                for c in chars.by_ref() {
+                    if c == ']' {
+                        break;
+                    }
+                    if c == '\\' || c == '[' {
+                        regex.push('\\');
+                    }
+                    regex.push(c);
+                }
+                regex.push(']');
+            }
+            '{' => {
+                braces += 1;
+                regex.push_str("(?:");
+            }
+            ',' if braces > 0 => regex.push('|'),
+            '}' if braces > 0 => {
+                braces -= 1;
+                regex.push(')');
+            }
+            '\\' => regex.push_str(&regex::escape(&chars.next()?.to_string())),
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+
+    Regex::new(&regex).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Buffer;
+
+    #[test]
+    fn globs_match_like_editorconfig() {
+        let matches = |glob: &str, path: &str| glob_regex(glob).is_some_and(|regex| regex.is_match(path));
+
+        assert!(matches("*", "src/main.rs"));
+        assert!(matches("*.rs", "src/main.rs"));
+        assert!(!matches("*.rs", "main.rsx"));
+        assert!(matches("*.{js,ts}", "app.ts"));
+        assert!(matches("Makefile", "sub/Makefile"));
+        assert!(matches("src/*.rs", "src/main.rs"));
+        assert!(!matches("src/*.rs", "other/src/main.rs"));
+        assert!(!matches("/*.rs", "src/main.rs"));
+        assert!(matches("lib/**.rs", "lib/a/b.rs"));
+        assert!(matches("[!a]?.md", "b1.md"));
+        assert!(!matches("[!a]?.md", "a1.md"));
+    }
+
+    #[test]
+    fn closer_files_and_later_sections_win() {
+        let root = std::env::temp_dir().join(format!("atlas-editorconfig-{}", std::process::id()));
+        let sub = root.join("sub");
+        fs::create_dir_all(&sub).unwrap();
+        fs::write(
+            root.join(FILE_NAME),
+            "root = true\n\n[*]\nindent_style = space\nindent_size = 2\ntrim_trailing_whitespace = true\n\
+             \n# Tabs for Go.\n[*.go]\nindent_style = tab\nindent_size = tab\ntab_width = 8\n",
+        )
+        .unwrap();
+        fs::write(sub.join(FILE_NAME), "[*.rs]\nindent_size = 4\nend_of_line = CRLF\ninsert_final_newline = true\n").unwrap();
+        fs::write(sub.join("main.rs"), "fn main() {}").unwrap();
+        fs::write(sub.join("main.go"), "package main").unwrap();
+
+        let rust = Buffer::from_path(sub.join("main.rs")).unwrap();
+        let go = Buffer::from_path(sub.join("main.go")).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        assert!(rust.options.expand_tab);
+        assert_eq!((rust.options.shift_width, rust.options.tab_width), (4, 4));
+        assert_eq!(rust.options.end_of_line, Some(LineEnding::CrLf));
+        assert_eq!(rust.options.insert_final_newline, Some(true));
+        assert!(rust.options.trim_trailing_whitespace);
+
+        // Over the filetype defaults, which only say Go uses tabs.
+        assert!(!go.options.expand_tab);
+        assert_eq!((go.options.shift_width, go.options.tab_width), (8, 8));
+        assert_eq!(go.options.end_of_line, None);
+    }
+}
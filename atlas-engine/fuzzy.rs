@@ -0,0 +1,118 @@
+//! Fuzzy matching, for picking a file by typing a few of the letters in its path.
+
+const MATCH: i64 = 16;
+const CONSECUTIVE: i64 = 12; // Right after the previous match.
+const WORD_START: i64 = 10; // After `_`, `-`, `.` or a space, or the capital in `camelCase`.
+const NAME_START: i64 = 14; // After a `/`, or the very first character.
+const GAP: i64 = 1; // For each character skipped between two matches.
+
+/// How well `query` matches `candidate`, higher being better. `None` unless every character of
+/// `query` appears in `candidate` in order. Case is ignored unless `query` has capitals.
+pub fn score(query: &str, candidate: &str) -> Option<i64> {
+    let smart_case = query.chars().any(char::is_uppercase);
+    let same = |q: char, c: char| match smart_case {
+        true => q == c,
+        false => q.to_lowercase().eq(c.to_lowercase()),
+    };
+    let chars: Vec<char> = candidate.chars().collect();
+
+    // `best[j]`: the best score with the query so far matched, its last character on `chars[j]`.
+    let mut best: Vec<Option<i64>> = vec![Some(0); chars.len()];
+    for (i, q) in query.chars().enumerate() {
+        let mut next = vec![None; chars.len()];
+        // The best of `best` before `j`, less the characters skipped since.
+        let mut before: Option<i64> = None;
+        for (j, &c) in chars.iter().enumerate() {
+            if same(q, c) {
+                let from = match i {
+                    0 => Some(0),
+                    _ => {
+                        let consecutive = j.checked_sub(1).and_then(|k| best[k]).map(|s| s + CONSECUTIVE);
+                        consecutive.max(before)
+                    }
+                };
+                next[j] = from.map(|s| s + MATCH + boundary_bonus(&chars, j));
+            }
+            before = before.map(|s| s - GAP).max(best[j]);
+        }
+        best = next;
+    }
+
+    match query.is_empty() {
+        true => Some(0),
+        false => best.into_iter().flatten().max(),
+    }
+}
+
+/// Indices of the `candidates` that match `query`, best first. Ties go to the shorter one, then
+/// to the one listed first.
+pub fn rank<S: AsRef<str>>(query: &str, candidates: &[S]) -> Vec<usize> {
+    let mut scored: Vec<(i64, usize, usize)> = candidates
+        .iter()
+        .enumerate()
+        .filter_map(|(i, candidate)| {
+            let candidate = candidate.as_ref();
+            Some((score(query, candidate)?, candidate.chars().count(), i))
+        })
+        .collect();
+
+    scored.sort_by_key(|&(score, len, i)| (std::cmp::Reverse(score), len, i));
+    scored.into_iter().map(|(_, _, i)| i).collect()
+}
+
+fn boundary_bonus(chars: &[char], j: usize) -> i64 {
+    let Some(&prev) = j.checked_sub(1).and_then(|k| chars.get(k)) else {
+        return NAME_START;
+    };
+
+    match prev {
+        '/' | '\\' => NAME_START,
+        '_' | '-' | '.' | ' ' => WORD_START,
+        _ if prev.is_lowercase() && chars[j].is_uppercase() => WORD_START,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranked<'a>(query: &str, candidates: &[&'a str]) -> Vec<&'a str> {
+        rank(query, candidates).into_iter().map(|i| candidates[i]).collect()
+    }
+
+    #[test]
+    fn only_characters_in_order_match() {
+        assert!(score("edr", "atlas-widgets/editor.rs").is_some());
+        assert_eq!(score("rde", "atlas-widgets/editor.rs"), None);
+        assert_eq!(score("editorx", "editor.rs"), None);
+        assert_eq!(score("", "anything"), Some(0));
+        // Capitals in the query make it case sensitive.
+        assert!(score("readme", "README.md").is_some());
+        assert_eq!(score("Readme", "README.md"), None);
+        assert!(score("RE", "README.md").is_some());
+    }
+
+    #[test]
+    fn file_names_and_runs_rank_first() {
+        let files = ["src/domain/lib.rs", "README.md", "src/main.rs", "examples/my_animation.rs"];
+        assert_eq!(ranked("main", &files), ["src/main.rs", "src/domain/lib.rs", "examples/my_animation.rs"]);
+
+        // The start of each word counts more than letters in the middle of one.
+        let files = ["atlas-engine/buffer.rs", "atlas-widgets/rows.rs", "atlas-keys/command.rs"];
+        assert_eq!(ranked("wr", &files), ["atlas-widgets/rows.rs"]);
+        let files = ["crates/kernel_tmp.rs", "crates/key_map.rs"];
+        assert_eq!(ranked("km", &files), ["crates/key_map.rs", "crates/kernel_tmp.rs"]);
+        let files = ["src/keymap.rs", "src/KeyMap.rs"];
+        assert_eq!(ranked("km", &files), ["src/KeyMap.rs", "src/keymap.rs"]);
+    }
+
+    #[test]
+    fn ties_go_to_the_shorter_path_then_the_first() {
+        let files = ["target/debug/main.rs", "src/main.rs", "lib/main.rs"];
+        assert_eq!(ranked("main.rs", &files), ["src/main.rs", "lib/main.rs", "target/debug/main.rs"]);
+
+        // With nothing typed everything matches, in the same order.
+        assert_eq!(ranked("", &["b", "a"]), ["b", "a"]);
+    }
+}
@@ -0,0 +1,96 @@
+//! Undo states of a buffer, kept as whole snapshots of its content. Ropes share the chunks they
+//! have in common, so keeping many of them around is cheap.
+
+use ropey::Rope;
+
+/// A line of states, like Vim's undo tree without the branches: recording after going back drops
+/// the states that were ahead.
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    states: Vec<State>,
+    current: usize,
+}
+
+/// A state, with the cursor offsets from before and after the change that led to it.
+#[derive(Debug, Clone, Default)]
+struct State {
+    content: Rope,
+    before: Vec<usize>,
+    after: Vec<usize>,
+}
+
+impl History {
+    pub fn new(content: &Rope) -> Self {
+        Self {
+            states: vec![State { content: content.clone(), ..State::default() }],
+            current: 0,
+        }
+    }
+
+    /// Records `content` as the state after the current one, unless it didn't change. `before` and
+    /// `after` are where the cursors were around the change, for undoing and redoing it.
+    pub fn record(&mut self, content: &Rope, before: Vec<usize>, after: Vec<usize>) {
+        if self.states.get(self.current).is_some_and(|state| state.content == *content) {
+            return;
+        }
+
+        self.states.truncate(self.current + 1);
+        self.states.push(State { content: content.clone(), before, after });
+        self.current = self.states.len() - 1;
+    }
+
+    /// Moves `steps` states back (forward if positive), stopping at either end, and returns the
+    /// new current state with the cursors of the last change undone or redone. `None` if there
+    /// was nowhere to go.
+    pub fn travel(&mut self, steps: isize) -> Option<(&Rope, &[usize])> {
+        let target = self
+            .current
+            .saturating_add_signed(steps)
+            .min(self.states.len().saturating_sub(1));
+
+        if target == self.current {
+            return None;
+        }
+
+        // Going back undoes the change after the target, going forward redoes the one into it.
+        let cursors = match target < self.current {
+            true => &self.states[target + 1].before,
+            false => &self.states[target].after,
+        };
+        self.current = target;
+        Some((&self.states[target].content, cursors))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recording_after_going_back_drops_what_was_ahead() {
+        let mut history = History::new(&Rope::from_str("a"));
+        history.record(&Rope::from_str("ab"), vec![1], vec![2]);
+        history.record(&Rope::from_str("ab"), vec![5], vec![5]);
+        history.record(&Rope::from_str("abc"), vec![2], vec![3]);
+
+        let content = |state: Option<(&Rope, &[usize])>| state.map(|(content, _)| content.to_string());
+        assert_eq!(content(history.travel(-5)).as_deref(), Some("a"));
+        assert_eq!(history.travel(-1), None);
+        assert_eq!(content(history.travel(1)).as_deref(), Some("ab"));
+
+        history.record(&Rope::from_str("abd"), vec![2], vec![3]);
+        assert_eq!(history.travel(1), None);
+        assert_eq!(content(history.travel(-1)).as_deref(), Some("ab"));
+    }
+
+    #[test]
+    fn undoing_gives_the_cursors_from_before_a_change_and_redoing_those_after() {
+        let mut history = History::new(&Rope::from_str(""));
+        history.record(&Rope::from_str("a"), vec![0], vec![1]);
+        history.record(&Rope::from_str("ab"), vec![1, 3], vec![2, 4]);
+
+        assert_eq!(history.travel(-2).map(|(_, cursors)| cursors), Some(&[0][..]));
+        assert_eq!(history.travel(1).map(|(_, cursors)| cursors), Some(&[1][..]));
+        assert_eq!(history.travel(1).map(|(_, cursors)| cursors), Some(&[2, 4][..]));
+    }
+}
@@ -1,17 +1,43 @@
 pub mod buffer;
 pub mod cursor;
+pub mod editorconfig;
+pub mod fuzzy;
+pub mod history;
 pub mod multi_cursor;
+pub mod options;
+pub mod registers;
+pub mod search;
+pub mod wrap;
 
 pub use buffer::Buffer;
-pub use cursor::{Cursor, TextPosition};
+pub use cursor::{CharSearch, Cursor, CursorSnapshot, LineColumn, TextPosition};
 use iced::widget::pane_grid::{self, Pane};
+use std::path::PathBuf;
 pub use multi_cursor::MultiCursor;
+pub use options::{BufferOptions, IndentStyle, LineEnding};
+pub use registers::{RegisterKind, Registers};
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum EditorMode {
     Normal,
     Insert,
-    Visual
+    Visual,
+    VisualLine,
+    VisualBlock,
+    Command, // Typing a `:` command line.
+}
+
+impl EditorMode {
+    /// Any Visual mode, where moving extends the selection.
+    pub fn is_visual(&self) -> bool {
+        matches!(self, EditorMode::Visual | EditorMode::VisualLine | EditorMode::VisualBlock)
+    }
+}
+
+/// Something that wants to be woken up periodically, see `Message::Tick`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Timer {
+    Autosave,
 }
 
 #[derive(Debug, Clone)]
@@ -22,6 +48,27 @@ pub enum Message {
     PaneClicked(Pane),
     Dragged(pane_grid::DragEvent),
     Resized(pane_grid::ResizeEvent),
-    CloseSplit,
+    /// Close the active pane. Unless `force`d, a modified buffer's last window stays open.
+    CloseSplit { force: bool },
+    /// A file finished loading in the background, into the given pane.
+    FileLoaded(Pane, Result<Box<Buffer>, String>),
+    /// Save modified buffers that have a file, see `Config::autosave`.
+    Autosave,
+    Tick(Timer),
+    /// `:ls`, listed in the active pane.
+    ListBuffers,
+    /// `:b {N}`, buffer `N` of `:ls` into the active pane.
+    SwitchBuffer(usize),
+    /// Open the file finder over the panes.
+    OpenFinder,
+    /// The files under the working directory, listed for the finder in the background.
+    FinderFiles(Vec<PathBuf>),
+    /// What's typed into the finder changed.
+    FinderQuery(String),
+    /// Move the finder's selection down by this many files, up when negative.
+    FinderSelect(isize),
+    /// Open the finder's selection in the active pane.
+    FinderOpen,
+    CloseFinder,
     Quit,
 }
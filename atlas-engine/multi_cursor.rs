@@ -1,4 +1,6 @@
-use crate::{cursor::MoveOpts, Buffer, Cursor, EditorMode, TextPosition};
+use std::ops::Range;
+
+use crate::{cursor::{LineColumn, MoveOpts}, Buffer, CharSearch, Cursor, CursorSnapshot, EditorMode, TextPosition};
 
 /// A collection of `Cursor` objects that are moved/edited together.
 ///
@@ -81,11 +83,36 @@ impl MultiCursor {
         &mut self.cursors[self.primary_index]
     }
 
+    /// Every cursor's state, in order, the primary one at `primary_index`.
+    pub fn snapshot(&self) -> Vec<CursorSnapshot> {
+        self.cursors.iter().map(Cursor::snapshot).collect()
+    }
+
     /// Convenience helper – current position of the *primary* cursor.
     pub fn position(&self) -> TextPosition {
         self.primary().position()
     }
 
+    /// Whether the *primary* cursor is at the end of its line, see `Cursor::is_at_line_end`.
+    pub fn is_at_line_end(&self, buffer: &Buffer, mode: &EditorMode) -> bool {
+        self.primary().is_at_line_end(buffer, mode)
+    }
+
+    /// Whether the *primary* cursor is at the start of its line.
+    pub fn is_at_line_start(&self) -> bool {
+        self.primary().is_at_line_start()
+    }
+
+    /// Whether the *primary* cursor is at the beginning of the buffer.
+    pub fn is_at_buffer_start(&self) -> bool {
+        self.primary().is_at_buffer_start()
+    }
+
+    /// Whether the *primary* cursor is at the end of the buffer, see `Cursor::is_at_buffer_end`.
+    pub fn is_at_buffer_end(&self, buffer: &Buffer, mode: &EditorMode) -> bool {
+        self.primary().is_at_buffer_end(buffer, mode)
+    }
+
     //
     // Cursor manipulation helpers
     //
@@ -134,6 +161,13 @@ impl MultiCursor {
         }
     }
 
+    /// Drops every cursor's selection, the cursors stay where they are.
+    pub fn collapse_all_selections(&mut self) {
+        for cursor in &mut self.cursors {
+            cursor.collapse_selection();
+        }
+    }
+
     //
     // Movement helpers.
     // Broadcast to all cursors.
@@ -143,10 +177,78 @@ impl MultiCursor {
     generate_cursor_methods!(move_right(buffer: &Buffer, mode: &EditorMode));
     generate_cursor_methods!(move_up(buffer: &Buffer, mode: &EditorMode));
     generate_cursor_methods!(move_down(buffer: &Buffer, mode: &EditorMode));
+    generate_cursor_methods!(move_to_line_start(buffer: &Buffer, mode: &EditorMode));
+    generate_cursor_methods!(move_to_line_end(buffer: &Buffer, mode: &EditorMode));
+    generate_cursor_methods!(move_to_first_non_blank(buffer: &Buffer, mode: &EditorMode));
+    generate_cursor_methods!(find_char(buffer: &Buffer, search: CharSearch, count: usize, mode: &EditorMode));
+    generate_cursor_methods!(move_to_line(buffer: &Buffer, line: usize, column: LineColumn, mode: &EditorMode));
+    generate_cursor_methods!(move_to_display_col(buffer: &Buffer, display_col: usize, mode: &EditorMode));
+    generate_cursor_methods!(move_to_matching_pair(buffer: &Buffer, pairs: &[(char, char)], mode: &EditorMode));
     generate_cursor_methods!(move_word_forward(buffer: &Buffer, big_word: bool, mode: &EditorMode));
     generate_cursor_methods!(move_word_backward(buffer: &Buffer, big_word: bool, mode: &EditorMode));
     generate_cursor_methods!(move_word_end(buffer: &Buffer, big_word: bool, mode: &EditorMode));
 
+    generate_cursor_methods!(collapse_to_selection(buffer: &Buffer, end: bool));
+
+    /// Moves every cursor to a column of its line, picked by `col_for` from its position.
+    pub fn move_to_cols(&mut self, buffer: &Buffer, mode: &EditorMode, col_for: impl Fn(TextPosition) -> usize) {
+        for cursor in &mut self.cursors {
+            let col = col_for(cursor.position());
+            cursor.move_to_col(buffer, col, mode);
+        }
+        self.merge_overlapping();
+    }
+
+    /// Selects the char range `range_for` picks from each cursor's position, cursors it finds
+    /// nothing (or nothing but an empty range) for stay where they are.
+    pub fn select_ranges(&mut self, buffer: &Buffer, range_for: impl Fn(TextPosition) -> Option<Range<usize>>) {
+        for cursor in &mut self.cursors {
+            let Some(range) = range_for(cursor.position()).filter(|range| !range.is_empty()) else {
+                continue;
+            };
+
+            // Selections include the grapheme under their end.
+            let anchor = buffer.offset_to_position(range.start);
+            let active = buffer.offset_to_position(buffer.prev_grapheme_offset(range.end));
+            cursor.move_to(active, MoveOpts { anchor: Some(anchor), update_preferred_col: true }, buffer);
+        }
+        self.merge_overlapping();
+    }
+
+    /// Replaces the primary cursor's Visual block with one cursor per line, ready for Insert mode:
+    /// on the block's left edge (`I`, lines too short to reach it are skipped) or right after its
+    /// right edge (`A`, short lines get virtual space that's padded once something is typed).
+    pub fn block_insert(&mut self, buffer: &Buffer, end: bool) {
+        let (lines, cols) = self.primary().block();
+        let col = if end { cols.end } else { cols.start };
+        let reached: Vec<usize> = lines.clone().filter(|&line| end || col <= buffer.grapheme_len(line)).collect();
+
+        match reached.is_empty() {
+            // Every line is too short for `I`, fall back to the end of the first one.
+            true => self.insert_on_lines(buffer, &[lines.start], buffer.grapheme_len(lines.start)),
+            false => self.insert_on_lines(buffer, &reached, col),
+        }
+    }
+
+    /// Replaces the cursors with one on `col` of each of `lines`, for typing on all of them at
+    /// once. Lines too short to reach `col` get virtual space up to it. Without `lines` the
+    /// cursors stay as they are.
+    pub fn insert_on_lines(&mut self, buffer: &Buffer, lines: &[usize], col: usize) {
+        if lines.is_empty() {
+            return;
+        }
+
+        self.cursors = lines
+            .iter()
+            .map(|&line| {
+                let mut cursor = Cursor::new();
+                cursor.move_to_virtual_col(buffer, line, col, buffer.grapheme_len(line));
+                cursor
+            })
+            .collect();
+        self.primary_index = 0;
+    }
+
     generate_cursor_methods!(no_merge adjust_for_mode(buffer: &Buffer, mode: &EditorMode));
 
     /// After any mutation we call this function to ensure we do not have two
@@ -154,7 +256,7 @@ impl MultiCursor {
     /// first one and delete the others. The *primary* cursor is preserved
     /// regardless – we only update its index to the new position after the
     /// vector is compacted.
-    fn merge_overlapping(&mut self) {
+    pub(crate) fn merge_overlapping(&mut self) {
         if self.cursors.len() <= 1 {
             return;
         }
@@ -190,3 +292,69 @@ impl MultiCursor {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EditorMode;
+
+    #[test]
+    fn collapsing_keeps_every_cursor_in_place() {
+        let buffer = Buffer::new("one two\nthree four\n", "t");
+        let mut mc = MultiCursor::new();
+        mc.add_cursor(buffer.offset_to_position(8), &buffer);
+        mc.move_word_forward(&buffer, false, &EditorMode::Visual);
+        assert!(mc.all_cursors().iter().all(Cursor::has_selection));
+
+        let positions: Vec<TextPosition> = mc.all_cursors().iter().map(Cursor::position).collect();
+        mc.collapse_all_selections();
+        assert!(mc.all_cursors().iter().all(|cursor| !cursor.has_selection()));
+        assert_eq!(mc.all_cursors().iter().map(Cursor::position).collect::<Vec<_>>(), positions);
+    }
+
+    #[test]
+    fn snapshots_show_every_cursors_selection() {
+        let buffer = Buffer::new("one two\nthree four\n", "t");
+        let mut mc = MultiCursor::new();
+        mc.add_cursor(buffer.offset_to_position(8), &buffer);
+        mc.move_word_end(&buffer, false, &EditorMode::Visual);
+
+        let at = |offset| buffer.offset_to_position(offset);
+        assert_eq!(
+            mc.snapshot(),
+            [
+                CursorSnapshot { anchor: at(0), active: at(2), has_selection: true },
+                CursorSnapshot { anchor: at(8), active: at(12), has_selection: true },
+            ]
+        );
+
+        mc.collapse_all_selections();
+        assert_eq!(mc.snapshot()[1], CursorSnapshot { anchor: at(12), active: at(12), has_selection: false });
+    }
+
+    #[test]
+    fn vertical_moves_keep_each_cursors_column() {
+        let buffer = Buffer::new("abcdefgh\nabcdefgh\nab\nabcdefgh\nabcdefgh\n", "t");
+        let mut mc = MultiCursor::new();
+        mc.primary_mut().move_to_col(&buffer, 6, &EditorMode::Normal);
+        mc.add_cursor(buffer.offset_to_position(buffer.grapheme_col_to_offset(1, 3)), &buffer);
+
+        let columns = |mc: &MultiCursor| -> Vec<(usize, usize)> {
+            mc.all_cursors().iter().map(|c| (c.position().line, c.position().col)).collect()
+        };
+
+        // Over `ab` one at a time, each one clamped and then back to its own column.
+        mc.move_down(&buffer, &EditorMode::Normal);
+        assert_eq!(columns(&mc), [(1, 6), (2, 1)]);
+        mc.move_down(&buffer, &EditorMode::Normal);
+        assert_eq!(columns(&mc), [(2, 1), (3, 3)]);
+        mc.move_down(&buffer, &EditorMode::Normal);
+        assert_eq!(columns(&mc), [(3, 6), (4, 3)]);
+
+        mc.move_up(&buffer, &EditorMode::Normal);
+        mc.move_up(&buffer, &EditorMode::Normal);
+        assert_eq!(columns(&mc), [(1, 6), (2, 1)]);
+        mc.move_up(&buffer, &EditorMode::Normal);
+        assert_eq!(columns(&mc), [(0, 6), (1, 3)]);
+    }
+}
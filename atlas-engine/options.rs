@@ -0,0 +1,164 @@
+use std::path::Path;
+
+use ropey::Rope;
+
+/// How a file is indented, see `Buffer::detect_indent`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndentStyle {
+    Tabs,
+    /// Spaces, this many per level.
+    Spaces(usize),
+}
+
+/// What lines end with when a buffer is written out.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    CrLf,
+    Cr,
+}
+
+impl LineEnding {
+    /// The line ending `content` starts out with, going by its first line break. Files without
+    /// any get `Lf`.
+    pub fn detect(content: &Rope) -> Self {
+        let mut chars = content.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\n' => return LineEnding::Lf,
+                '\r' if chars.next() == Some('\n') => return LineEnding::CrLf,
+                '\r' => return LineEnding::Cr,
+                _ => {}
+            }
+        }
+
+        LineEnding::Lf
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+            LineEnding::Cr => "\r",
+        }
+    }
+}
+
+/// Buffer-local editing options.
+///
+/// These mirror Vim's buffer-local options, since two buffers open side by side can easily
+/// disagree on things like indentation.
+/// View options are `Option`s layered over the global config: `None` means "use the global value".
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BufferOptions {
+    /// Columns a single `>>`/`<<` shifts by.
+    pub shift_width: usize,
+    /// Columns a tab character occupies.
+    pub tab_width: usize,
+    /// Indent with spaces instead of tabs.
+    pub expand_tab: bool,
+    /// With `expand_tab`, backspace over indentation removes spaces up to the previous multiple
+    /// of this many columns. 0 disables it.
+    pub soft_tab_stop: usize,
+    /// Lines opened with `o`/`O` start with the indentation of the line they're opened from,
+    /// otherwise at column 0.
+    pub auto_indent: bool,
+    /// Line ending to write, `None` keeps the buffer's own.
+    pub end_of_line: Option<LineEnding>,
+    /// Writing adds a final newline if it's missing (`Some(true)`) or drops it (`Some(false)`).
+    pub insert_final_newline: Option<bool>,
+    /// Writing drops spaces and tabs at the end of lines.
+    pub trim_trailing_whitespace: bool,
+    /// Soft wrap long lines.
+    pub wrap: Option<bool>,
+    /// Show line numbers.
+    pub number: Option<bool>,
+    /// Show tabs and trailing whitespace.
+    pub list: Option<bool>,
+}
+
+impl Default for BufferOptions {
+    fn default() -> Self {
+        Self {
+            shift_width: 4,
+            tab_width: 4,
+            expand_tab: true,
+            soft_tab_stop: 0,
+            auto_indent: true,
+            end_of_line: None,
+            insert_final_newline: None,
+            trim_trailing_whitespace: false,
+            wrap: None,
+            number: None,
+            list: None,
+        }
+    }
+}
+
+impl BufferOptions {
+    /// Filetype defaults for a buffer called `name`, based on its extension.
+    pub fn for_file(name: &str) -> Self {
+        let mut options = Self::default();
+        let path = Path::new(name);
+
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("md" | "markdown" | "txt") => options.wrap = Some(true),
+            Some("go") => options.expand_tab = false,
+            _ => {}
+        }
+
+        if path.file_name().is_some_and(|file| file == "Makefile") {
+            options.expand_tab = false;
+        }
+
+        options
+    }
+
+    /// Indents new lines and shifts like a file indented with `style`.
+    pub fn set_indent_style(&mut self, style: IndentStyle) {
+        match style {
+            IndentStyle::Tabs => {
+                self.expand_tab = false;
+                self.shift_width = self.tab_width;
+            }
+            IndentStyle::Spaces(width) => {
+                self.expand_tab = true;
+                self.shift_width = width;
+            }
+        }
+    }
+
+    /// Display width of a run of leading whitespace, expanding tabs to the next tab stop.
+    pub fn indent_width(&self, indent: impl Iterator<Item = char>) -> usize {
+        indent.fold(0, |width, c| match c {
+            '\t' => (width / self.tab_width + 1) * self.tab_width,
+            _ => width + 1,
+        })
+    }
+
+    /// Whitespace producing an indent of `width` columns, honoring `expand_tab`.
+    pub fn indent_string(&self, width: usize) -> String {
+        if self.expand_tab {
+            " ".repeat(width)
+        } else {
+            let tabs = width / self.tab_width;
+            let spaces = width % self.tab_width;
+            format!("{}{}", "\t".repeat(tabs), " ".repeat(spaces))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filetype_defaults() {
+        assert_eq!(BufferOptions::for_file("notes.md").wrap, Some(true));
+        assert_eq!(BufferOptions::for_file("main.rs").wrap, None);
+        assert!(!BufferOptions::for_file("main.go").expand_tab);
+        assert!(!BufferOptions::for_file("src/Makefile").expand_tab);
+        assert_eq!(BufferOptions::for_file("Atlas"), BufferOptions::default());
+    }
+}
@@ -0,0 +1,52 @@
+use std::collections::HashMap;
+
+/// The unnamed register (`""`), which every yank, delete and paste uses unless told otherwise.
+pub const UNNAMED: char = '"';
+
+/// How a register's text goes back in when pasted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegisterKind {
+    Charwise,
+    /// Whole lines, each ending in a line break (`dd`, `yy`). They're pasted as lines of their own.
+    Linewise,
+}
+
+/// Where yanked and deleted text goes, Vim's registers, by name.
+///
+/// Only the unnamed register is written to for now.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Registers {
+    contents: HashMap<char, (String, RegisterKind)>,
+}
+
+impl Registers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The text in register `name` and how it was taken, `None` if nothing went there yet.
+    pub fn get(&self, name: char) -> Option<(&str, RegisterKind)> {
+        self.contents.get(&name).map(|(text, kind)| (text.as_str(), *kind))
+    }
+
+    pub fn set(&mut self, name: char, text: impl Into<String>, kind: RegisterKind) {
+        self.contents.insert(name, (text.into(), kind));
+    }
+
+    pub fn unnamed(&self) -> &str {
+        self.get(UNNAMED).map_or("", |(text, _)| text)
+    }
+
+    pub fn is_linewise(&self) -> bool {
+        self.get(UNNAMED).is_some_and(|(_, kind)| kind == RegisterKind::Linewise)
+    }
+
+    pub fn set_unnamed(&mut self, text: impl Into<String>) {
+        self.set(UNNAMED, text, RegisterKind::Charwise);
+    }
+
+    /// Like `set_unnamed`, for whole lines.
+    pub fn set_unnamed_lines(&mut self, text: impl Into<String>) {
+        self.set(UNNAMED, text, RegisterKind::Linewise);
+    }
+}
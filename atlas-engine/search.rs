@@ -0,0 +1,33 @@
+//! Search matches, as char ranges of a buffer.
+//! NOTE: There's no `/` yet, this only holds what highlighting its matches is going to need.
+//! Their colors go in the config once something draws them.
+
+use std::ops::Range;
+
+/// Index of the match the cursor at `offset` is on (Vim's `CurSearch`), the others being drawn
+/// as plain matches. An empty match is on the cursor right at its start.
+pub fn current_match(matches: &[Range<usize>], offset: usize) -> Option<usize> {
+    matches
+        .iter()
+        .position(|range| range.contains(&offset) || range.is_empty() && range.start == offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_current_match_is_the_one_under_the_cursor() {
+        let matches = [2..5, 5..7, 9..9, 12..14];
+
+        assert_eq!(current_match(&matches, 0), None);
+        assert_eq!(current_match(&matches, 2), Some(0));
+        assert_eq!(current_match(&matches, 4), Some(0));
+        assert_eq!(current_match(&matches, 5), Some(1));
+        assert_eq!(current_match(&matches, 7), None);
+        assert_eq!(current_match(&matches, 9), Some(2));
+        assert_eq!(current_match(&matches, 13), Some(3));
+        assert_eq!(current_match(&matches, 14), None);
+        assert_eq!(current_match(&[], 0), None);
+    }
+}
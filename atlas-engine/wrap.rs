@@ -0,0 +1,95 @@
+//! Soft wrap: splitting long buffer lines into several visual rows.
+
+use std::{borrow::Cow, ops::Range};
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::Buffer;
+
+/// Splits `line` into the grapheme column ranges of its visual rows when soft wrapping at `width`
+/// screen columns (tabs take several, see `Buffer::display_col`).
+/// Continuation rows are `indent` columns narrower, see `break_indent`.
+/// An empty line still takes up a single (empty) row.
+pub fn wrap_line(buffer: &Buffer, line: usize, width: usize, indent: usize) -> Vec<Range<usize>> {
+    let content: Cow<str> = buffer.visible_line_slice(line).into();
+    let first_width = width.max(1);
+    let mut width = first_width;
+
+    let mut rows = Vec::new();
+    let mut row_start = 0;
+    let mut row_width = 0;
+    let mut display = 0;
+    let mut len = 0;
+
+    for (col, g) in content.graphemes(true).enumerate() {
+        let w = buffer.grapheme_width(g, display);
+
+        // Always keep at least one grapheme per row, even when it doesn't fit.
+        if row_width + w > width && col > row_start {
+            rows.push(row_start..col);
+            row_start = col;
+            row_width = 0;
+            width = first_width.saturating_sub(indent).max(1);
+        }
+
+        row_width += w;
+        display += w;
+        len = col + 1;
+    }
+
+    rows.push(row_start..len);
+    rows
+}
+
+/// How far continuation rows of `line` are indented with `breakindent`: as much as the line
+/// itself, but at most half of `width` so there's still room for text.
+pub fn break_indent(buffer: &Buffer, line: usize, width: usize) -> usize {
+    let (_, indent) = buffer.line_indent(line);
+    buffer.options.indent_width(indent.chars()).min(width / 2)
+}
+
+/// Index of the row in `rows` holding column `col`.
+/// Columns past the end of the line (e.g. the Insert mode cursor) belong to the last row.
+pub fn row_for_col(rows: &[Range<usize>], col: usize) -> usize {
+    rows.iter()
+        .position(|row| col < row.end)
+        .unwrap_or(rows.len().saturating_sub(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wraps_long_lines_into_rows() {
+        let buffer = Buffer::new("abcdefghij\n\nxy", "t");
+
+        assert_eq!(wrap_line(&buffer, 0, 4, 0), [0..4, 4..8, 8..10]);
+        assert_eq!(wrap_line(&buffer, 1, 4, 0), vec![0..0; 1]);
+        assert_eq!(wrap_line(&buffer, 2, 4, 0), vec![0..2; 1]);
+
+        // Tabs count for their width.
+        let mut tabs = Buffer::new("\tab\tc", "t");
+        tabs.options.tab_width = 4;
+        assert_eq!(wrap_line(&tabs, 0, 6, 0), [0..3, 3..5]);
+
+        let rows = wrap_line(&buffer, 0, 5, 0);
+        assert_eq!(rows, [0..5, 5..10]);
+        assert_eq!(row_for_col(&rows, 4), 0);
+        assert_eq!(row_for_col(&rows, 5), 1);
+        assert_eq!(row_for_col(&rows, 10), 1); // One past the end.
+    }
+
+    #[test]
+    fn continuation_rows_leave_room_for_the_indent() {
+        let mut buffer = Buffer::new("  abcdefghij\n\tab\nabcdef", "t");
+        buffer.options.tab_width = 4;
+
+        assert_eq!(break_indent(&buffer, 0, 6), 2);
+        assert_eq!(wrap_line(&buffer, 0, 6, 2), [0..6, 6..10, 10..12]);
+
+        // Never more than half the width.
+        assert_eq!(break_indent(&buffer, 1, 6), 3);
+        assert_eq!(break_indent(&buffer, 2, 6), 0);
+    }
+}
@@ -0,0 +1,448 @@
+//! Parsing of `:` command lines.
+
+use std::ops::Range;
+
+/// A parsed `:` command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExCommand {
+    /// `:set` (global) or `:setlocal` (current buffer only).
+    Set {
+        local: bool,
+        settings: Vec<OptionSetting>,
+    },
+    /// `:g/pattern/command`, or `:v/pattern/command` (`invert`) for the lines that don't match.
+    Global {
+        pattern: String,
+        invert: bool,
+        command: String,
+    },
+    /// `:new`, or `:vnew` (`vertical`): an empty buffer in a new split.
+    New { vertical: bool },
+    /// `:q`, or `:q!` (`force`) to close even with unsaved changes.
+    Quit { force: bool },
+    /// `:earlier {N}`, `N` undo states back.
+    Earlier { steps: usize },
+    /// `:later {N}`, `N` undo states forward.
+    Later { steps: usize },
+    /// `:{range}y {register}`, the lines (the cursor's without a range) into a register, the
+    /// unnamed one if none is given.
+    Yank { range: Option<LineRange>, register: char },
+    /// `:ls`/`:buffers`, the list of open buffers.
+    Buffers,
+    /// `:b {N}`, buffer `N` of `:ls` in the current window.
+    Buffer { id: usize },
+}
+
+/// One end of a `:` range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Address {
+    /// `{N}`, 1-based like line numbers are shown.
+    Line(usize),
+    /// `.`, the cursor's line.
+    Current,
+    /// `$`.
+    Last,
+    /// `'<`, the first line of the last Visual selection.
+    VisualStart,
+    /// `'>`, its last line.
+    VisualEnd,
+}
+
+/// The lines a `:` command acts on, `{start},{end}`. `%` is `1,$`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineRange {
+    pub start: Address,
+    pub end: Address,
+}
+
+impl LineRange {
+    /// The 0-based lines covered, with the cursor on `current`, `last` the last line and `visual`
+    /// the first and last lines of the last Visual selection, if there was one. A backwards range
+    /// is turned around.
+    pub fn lines(&self, current: usize, last: usize, visual: Option<(usize, usize)>) -> Result<Range<usize>, String> {
+        let line = |address| match address {
+            // Like Vim, line 0 is the first line for commands that don't insert.
+            Address::Line(n) if n <= last + 1 => Ok(n.saturating_sub(1)),
+            Address::Line(_) => Err("E16: Invalid range".to_string()),
+            Address::Current => Ok(current),
+            Address::Last => Ok(last),
+            Address::VisualStart => visual.map(|(start, _)| start).ok_or_else(|| "E20: Mark not set".to_string()),
+            Address::VisualEnd => visual.map(|(_, end)| end).ok_or_else(|| "E20: Mark not set".to_string()),
+        };
+
+        let (start, end) = (line(self.start)?, line(self.end)?);
+        Ok(start.min(end)..start.max(end) + 1)
+    }
+}
+
+/// One `name`, `noname`, `name!` or `name=value` argument of `:set`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OptionSetting {
+    pub name: String,
+    pub value: OptionValue,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptionValue {
+    On,
+    Off,
+    Toggle,
+    Number(usize),
+}
+
+impl OptionValue {
+    /// The new value of a boolean option currently set to `current`.
+    pub fn as_bool(self, current: bool) -> Option<bool> {
+        match self {
+            OptionValue::On => Some(true),
+            OptionValue::Off => Some(false),
+            OptionValue::Toggle => Some(!current),
+            OptionValue::Number(_) => None,
+        }
+    }
+
+    pub fn as_number(self) -> Option<usize> {
+        match self {
+            OptionValue::Number(n) => Some(n),
+            _ => None,
+        }
+    }
+}
+
+/// Parses a command line (without the leading `:`).
+/// Errors are user-facing messages, worded like Vim's.
+pub fn parse_ex(line: &str) -> Result<ExCommand, String> {
+    let (range, line) = parse_range(line.trim())?;
+    let line = line.trim_start();
+
+    // The name is everything up to the first non-letter, `:g/foo/d` has no space after it.
+    let name_len = line
+        .find(|c: char| !c.is_ascii_alphabetic())
+        .unwrap_or(line.len());
+    let (name, args) = line.split_at(name_len);
+
+    // Only some commands take a range.
+    if range.is_some() && !matches!(name, "yank" | "ya" | "y") {
+        return Err("E481: No range allowed".to_string());
+    }
+
+    match name {
+        "yank" | "ya" | "y" => parse_yank(range, args),
+        "set" | "se" => parse_set(false, args),
+        "setlocal" | "setl" => parse_set(true, args),
+        "global" | "g" => parse_global(false, args),
+        "vglobal" | "v" => parse_global(true, args),
+        "new" => parse_new(false, args),
+        "vnew" | "vne" => parse_new(true, args),
+        "quit" | "q" => parse_quit(args),
+        "earlier" | "ea" => parse_steps(args).map(|steps| ExCommand::Earlier { steps }),
+        "later" | "lat" => parse_steps(args).map(|steps| ExCommand::Later { steps }),
+        "ls" | "buffers" | "files" => parse_buffers(args),
+        "buffer" | "b" => parse_buffer(args),
+        _ => Err(format!("E492: Not an editor command: {line}")),
+    }
+}
+
+/// The range in front of a command, if there's one, and what's left after it.
+fn parse_range(line: &str) -> Result<(Option<LineRange>, &str), String> {
+    if let Some(rest) = line.strip_prefix('%') {
+        return Ok((Some(LineRange { start: Address::Line(1), end: Address::Last }), rest));
+    }
+
+    let Some((start, rest)) = parse_address(line)? else {
+        return Ok((None, line));
+    };
+    let Some(rest) = rest.strip_prefix(',') else {
+        return Ok((Some(LineRange { start, end: start }), rest));
+    };
+    // A missing end is the cursor's line, like Vim.
+    let (end, rest) = parse_address(rest)?.unwrap_or((Address::Current, rest));
+
+    Ok((Some(LineRange { start, end }), rest))
+}
+
+fn parse_address(line: &str) -> Result<Option<(Address, &str)>, String> {
+    let digits = line.find(|c: char| !c.is_ascii_digit()).unwrap_or(line.len());
+    if digits > 0 {
+        let n = line[..digits].parse().map_err(|_| "E16: Invalid range".to_string())?;
+        return Ok(Some((Address::Line(n), &line[digits..])));
+    }
+
+    let mut chars = line.chars();
+    let address = match (chars.next(), chars.clone().next()) {
+        (Some('.'), _) => Address::Current,
+        (Some('$'), _) => Address::Last,
+        (Some('\''), Some('<')) => Address::VisualStart,
+        (Some('\''), Some('>')) => Address::VisualEnd,
+        // NOTE: There are no other marks yet.
+        (Some('\''), _) => return Err("E20: Mark not set".to_string()),
+        _ => return Ok(None),
+    };
+    let len = if line.starts_with('\'') { 2 } else { 1 };
+
+    Ok(Some((address, &line[len..])))
+}
+
+/// `args` of `:y`: the register to yank into, if any.
+/// NOTE: Vim also takes a count, and appends to `a`-`z` when given `A`-`Z`.
+fn parse_yank(range: Option<LineRange>, args: &str) -> Result<ExCommand, String> {
+    let args = args.trim();
+    let mut chars = args.chars();
+    let register = match (chars.next(), chars.next()) {
+        (None, _) => '"',
+        (Some(c), None) if c.is_ascii_lowercase() || c == '"' => c,
+        _ => return Err(format!("E488: Trailing characters: {args}")),
+    };
+
+    Ok(ExCommand::Yank { range, register })
+}
+
+fn parse_set(local: bool, args: &str) -> Result<ExCommand, String> {
+    let settings = args
+        .split_whitespace()
+        .map(parse_setting)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(ExCommand::Set { local, settings })
+}
+
+/// `args` of `:new`/`:vnew`, which has to be empty.
+fn parse_new(vertical: bool, args: &str) -> Result<ExCommand, String> {
+    // NOTE: Vim takes a file name here, we only do empty buffers so far.
+    if !args.trim().is_empty() {
+        return Err(format!("E488: Trailing characters: {}", args.trim()));
+    }
+
+    Ok(ExCommand::New { vertical })
+}
+
+fn parse_quit(args: &str) -> Result<ExCommand, String> {
+    let (force, args) = match args.strip_prefix('!') {
+        Some(args) => (true, args),
+        None => (false, args),
+    };
+
+    if !args.trim().is_empty() {
+        return Err(format!("E488: Trailing characters: {}", args.trim()));
+    }
+
+    Ok(ExCommand::Quit { force })
+}
+
+/// NOTE: Vim also takes flags here, to only list some of the buffers.
+fn parse_buffers(args: &str) -> Result<ExCommand, String> {
+    if !args.trim().is_empty() {
+        return Err(format!("E488: Trailing characters: {}", args.trim()));
+    }
+
+    Ok(ExCommand::Buffers)
+}
+
+/// `args` of `:b`: the buffer's number in `:ls`.
+/// NOTE: Vim also takes part of the buffer's name.
+fn parse_buffer(args: &str) -> Result<ExCommand, String> {
+    let args = args.trim();
+    if args.is_empty() {
+        return Err("E471: Argument required".to_string());
+    }
+
+    args.parse()
+        .map(|id| ExCommand::Buffer { id })
+        .map_err(|_| format!("E94: No matching buffer for {args}"))
+}
+
+/// The count of `:earlier`/`:later`, 1 if there's none.
+/// NOTE: Vim also takes times (`10m`) and file writes (`1f`), we only keep undo steps so far.
+fn parse_steps(args: &str) -> Result<usize, String> {
+    let args = args.trim();
+    if args.is_empty() {
+        return Ok(1);
+    }
+
+    // Has to fit an `isize`, the steps are taken back with a negative count.
+    args.parse()
+        .ok()
+        .filter(|&steps| isize::try_from(steps).is_ok())
+        .ok_or_else(|| format!("E475: Invalid argument: {args}"))
+}
+
+/// `args` of `:g`: an optional `!` (same as `:v`), then `/pattern/command` with any
+/// punctuation as the delimiter. The delimiter can be escaped inside the pattern.
+fn parse_global(invert: bool, args: &str) -> Result<ExCommand, String> {
+    let (invert, args) = match args.strip_prefix('!') {
+        Some(args) => (true, args),
+        None => (invert, args),
+    };
+
+    let mut chars = args.chars();
+    let delimiter = chars
+        .next()
+        .filter(|c| c.is_ascii_punctuation() && !matches!(c, '\\' | '"' | '|'))
+        .ok_or("E146: Regular expressions can't be delimited by letters")?;
+
+    let mut pattern = String::new();
+    let mut command = "";
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.clone().next() == Some(delimiter) => {
+                pattern.push(delimiter);
+                chars.next();
+            }
+            c if c == delimiter => {
+                command = chars.as_str();
+                break;
+            }
+            c => pattern.push(c),
+        }
+    }
+
+    Ok(ExCommand::Global {
+        pattern,
+        invert,
+        command: command.trim().to_string(),
+    })
+}
+
+fn parse_setting(arg: &str) -> Result<OptionSetting, String> {
+    let (name, value) = if let Some((name, value)) = arg.split_once('=') {
+        let n = value
+            .parse()
+            .map_err(|_| format!("E521: Number required after =: {arg}"))?;
+        (name, OptionValue::Number(n))
+    } else if let Some(name) = arg.strip_suffix('!') {
+        (name, OptionValue::Toggle)
+    } else if let Some(name) = arg.strip_prefix("inv") {
+        (name, OptionValue::Toggle)
+    } else if let Some(name) = arg.strip_prefix("no") {
+        (name, OptionValue::Off)
+    } else {
+        (arg, OptionValue::On)
+    };
+
+    Ok(OptionSetting {
+        name: name.to_string(),
+        value,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setting(name: &str, value: OptionValue) -> OptionSetting {
+        OptionSetting {
+            name: name.to_string(),
+            value,
+        }
+    }
+
+    #[test]
+    fn parses_set_and_setlocal() {
+        assert_eq!(
+            parse_ex("set wrap nonumber list! sw=2"),
+            Ok(ExCommand::Set {
+                local: false,
+                settings: vec![
+                    setting("wrap", OptionValue::On),
+                    setting("number", OptionValue::Off),
+                    setting("list", OptionValue::Toggle),
+                    setting("sw", OptionValue::Number(2)),
+                ],
+            })
+        );
+
+        assert_eq!(
+            parse_ex("setl invwrap"),
+            Ok(ExCommand::Set {
+                local: true,
+                settings: vec![setting("wrap", OptionValue::Toggle)],
+            })
+        );
+    }
+
+    #[test]
+    fn parses_global() {
+        let global = |pattern: &str, invert, command: &str| ExCommand::Global {
+            pattern: pattern.to_string(),
+            invert,
+            command: command.to_string(),
+        };
+
+        assert_eq!(parse_ex("g/foo/d"), Ok(global("foo", false, "d")));
+        assert_eq!(parse_ex("v/^$/d"), Ok(global("^$", true, "d")));
+        assert_eq!(parse_ex("g!/a b/ delete"), Ok(global("a b", true, "delete")));
+        assert_eq!(parse_ex(r"global#a\#b#d"), Ok(global("a#b", false, "d")));
+        assert_eq!(parse_ex(r"g/a\/b/d"), Ok(global("a/b", false, "d")));
+        assert!(parse_ex("gfoofd").unwrap_err().starts_with("E492"));
+        assert!(parse_ex("g foo").unwrap_err().starts_with("E146"));
+    }
+
+    #[test]
+    fn parses_new() {
+        assert_eq!(parse_ex("new"), Ok(ExCommand::New { vertical: false }));
+        assert_eq!(parse_ex(" vnew "), Ok(ExCommand::New { vertical: true }));
+        assert_eq!(parse_ex("vne"), Ok(ExCommand::New { vertical: true }));
+        assert_eq!(parse_ex("new foo.txt"), Err("E488: Trailing characters: foo.txt".into()));
+    }
+
+    #[test]
+    fn parses_quit() {
+        assert_eq!(parse_ex("q"), Ok(ExCommand::Quit { force: false }));
+        assert_eq!(parse_ex("quit!"), Ok(ExCommand::Quit { force: true }));
+        assert_eq!(parse_ex("q! now"), Err("E488: Trailing characters: now".into()));
+    }
+
+    #[test]
+    fn parses_buffer_commands() {
+        assert_eq!(parse_ex("ls"), Ok(ExCommand::Buffers));
+        assert_eq!(parse_ex("buffers"), Ok(ExCommand::Buffers));
+        assert_eq!(parse_ex("ls +"), Err("E488: Trailing characters: +".into()));
+
+        assert_eq!(parse_ex("b 2"), Ok(ExCommand::Buffer { id: 2 }));
+        assert_eq!(parse_ex("b3"), Ok(ExCommand::Buffer { id: 3 }));
+        assert_eq!(parse_ex("buffer 10"), Ok(ExCommand::Buffer { id: 10 }));
+        assert_eq!(parse_ex("b"), Err("E471: Argument required".into()));
+        assert_eq!(parse_ex("b main.rs"), Err("E94: No matching buffer for main.rs".into()));
+    }
+
+    #[test]
+    fn parses_earlier_and_later() {
+        assert_eq!(parse_ex("earlier"), Ok(ExCommand::Earlier { steps: 1 }));
+        assert_eq!(parse_ex("ea 3"), Ok(ExCommand::Earlier { steps: 3 }));
+        assert_eq!(parse_ex("lat 2"), Ok(ExCommand::Later { steps: 2 }));
+        assert_eq!(parse_ex("earlier 5m"), Err("E475: Invalid argument: 5m".into()));
+        assert_eq!(parse_ex("ea 9223372036854775808"), Err("E475: Invalid argument: 9223372036854775808".into()));
+    }
+
+    #[test]
+    fn parses_ranges_for_yank() {
+        let range = |start, end| Some(LineRange { start, end });
+        let yank = |range, register| Ok(ExCommand::Yank { range, register });
+
+        assert_eq!(parse_ex("y"), yank(None, '"'));
+        assert_eq!(parse_ex("%y a"), yank(range(Address::Line(1), Address::Last), 'a'));
+        assert_eq!(parse_ex("'<,'>yank"), yank(range(Address::VisualStart, Address::VisualEnd), '"'));
+        assert_eq!(parse_ex("2,.ya"), yank(range(Address::Line(2), Address::Current), '"'));
+        assert_eq!(parse_ex("3y"), yank(range(Address::Line(3), Address::Line(3)), '"'));
+        assert_eq!(parse_ex("y ab"), Err("E488: Trailing characters: ab".into()));
+        assert_eq!(parse_ex("'ay"), Err("E20: Mark not set".into()));
+        assert_eq!(parse_ex("%q"), Err("E481: No range allowed".into()));
+    }
+
+    #[test]
+    fn ranges_resolve_to_lines() {
+        let lines = |start, end, visual| LineRange { start, end }.lines(4, 9, visual);
+
+        assert_eq!(lines(Address::Line(1), Address::Last, None), Ok(0..10));
+        assert_eq!(lines(Address::Current, Address::Line(2), None), Ok(1..5));
+        assert_eq!(lines(Address::VisualStart, Address::VisualEnd, Some((2, 3))), Ok(2..4));
+        assert_eq!(lines(Address::VisualStart, Address::VisualEnd, None), Err("E20: Mark not set".into()));
+        assert_eq!(lines(Address::Line(11), Address::Line(11), None), Err("E16: Invalid range".into()));
+    }
+
+    #[test]
+    fn reports_errors() {
+        assert_eq!(parse_ex("frobnicate"), Err("E492: Not an editor command: frobnicate".into()));
+        assert!(parse_ex("set ts=wide").unwrap_err().starts_with("E521"));
+    }
+}
@@ -1,4 +1,10 @@
-use atlas_engine::{Buffer, EditorMode, MultiCursor, Message};
+use std::ops::Range;
+use std::rc::Rc;
+
+use atlas_engine::{
+    Buffer, CharSearch, EditorMode, LineColumn, MultiCursor, Message, Registers, TextPosition, buffer::Edit,
+    cursor::MoveOpts,
+};
 use iced::keyboard::{self, Key, Modifiers};
 
 use crate::keymap::Keymap;
@@ -10,10 +16,23 @@ pub enum Motion {
     CharUp,
     CharDown,
     ToLineStart,
-    _ToLineEnd,
+    ToLineEnd,
+    ToFirstNonBlank, // `^`.
+    ToColumn(usize), // `{count}|`, the screen column, 1-based.
+    ToRowStart, // `g0`, the start of the screen row when soft wrapping.
+    ToRowEnd,   // `g$`.
+    ToFirstLine, // `gg`, to the first non-blank.
+    ToLastLine,  // `G`.
+    ToLine(usize), // `{count}gg` and `{count}G`, 1-based.
     NextWordStart(bool), // NOTE: Boolean value to represent if it's a big word or not.
     NextWordEnd(bool),
     PrevWord(bool),
+    MatchPair, // `%`, between the brackets of the configured pairs.
+    FindChar(CharSearch), // `f`, `F`, `t` and `T`, on the cursor's line.
+    RepeatFind,           // `;`, the last `FindChar` again.
+    RepeatFindReverse,    // `,`, the other way.
+    PageDown,  // `<PageDown>`, the window's height worth of lines.
+    PageUp,
 }
 
 impl Motion {
@@ -32,6 +51,45 @@ impl Motion {
             _ => return None,
         })
     }
+
+    /// Operators act on whole lines with these, `dj` deletes two lines.
+    pub fn is_linewise(&self) -> bool {
+        matches!(
+            self,
+            Motion::CharUp | Motion::CharDown | Motion::ToFirstLine | Motion::ToLastLine | Motion::ToLine(_)
+        )
+    }
+
+    /// Charwise motions that take the character they land on along, like `e` and `$`.
+    pub fn is_inclusive(&self) -> bool {
+        matches!(
+            self,
+            Motion::NextWordEnd(_)
+                | Motion::ToLineEnd
+                | Motion::ToRowEnd
+                | Motion::MatchPair
+                | Motion::FindChar(CharSearch { forward: true, .. })
+        )
+    }
+
+    /// Motions that go somewhere from the cursor, and so fail when there's nowhere to go, like `h`
+    /// on the first column. `0` there just stays put.
+    pub fn is_relative(&self) -> bool {
+        matches!(
+            self,
+            Motion::CharLeft
+                | Motion::CharRight
+                | Motion::CharUp
+                | Motion::CharDown
+                | Motion::NextWordStart(_)
+                | Motion::NextWordEnd(_)
+                | Motion::PrevWord(_)
+                | Motion::MatchPair
+                | Motion::FindChar(_)
+                | Motion::RepeatFind
+                | Motion::RepeatFindReverse
+        )
+    }
 }
 
 #[derive(Debug, Clone, Eq, Hash, PartialEq)]
@@ -63,30 +121,97 @@ pub enum Action {
         count: usize,
     },
     Operate {
-        _op: Operator, // dw, 3yy, etc.
-        _motion: Motion,
-        _count: usize,
+        op: Operator, // dw, 3dj, etc.
+        motion: Motion,
+        count: usize,
     },
     ChangeMode(EditorMode),
     RepeatLast,
     Backspace,
-    Delete,
+    DeleteChars { count: usize, before: bool }, // `x`, or `X` (`before`).
+    DeleteLines { count: usize },               // `dd`.
+    YankLines { count: usize },                 // `yy`.
+    ReplaceChars { c: char, count: usize },     // `r`.
+    ToggleCase { count: usize },                // `~`.
     DeleteSelection,
+    YankSelection,
+    ChangeSelection, // Visual `c`, Insert mode starts where the selection was.
+    SelectTextObject(TextObject), // Visual `i{`.
+    DeleteTextObject(TextObject), // `di{`.
+    Paste { before: bool, over_selection: bool }, // `p`/`P`, Visual mode replaces the selection.
+    InsertAtSelection { end: bool }, // Visual `I`/`A`.
+    Append { line_end: bool },       // `a`, or `A` (`line_end`).
+    InsertAtFirstNonBlank,           // `I`.
+    OpenLine { above: bool },        // `o`/`O`.
+    ResumeInsert,                    // `gi`, back where Insert mode was last left.
+    BlockInsert { end: bool },       // Visual block `I`/`A`.
+    BlockDelete,                     // Visual block `d`.
+    BlockYank,                       // Visual block `y`.
+    BlockChange,                     // Visual block `c`, Insert mode goes on at every line.
+    Indent,
+    Dedent,
+    JoinLines {
+        count: usize,
+        keep_whitespace: bool, // `gJ`.
+    },
+    Scroll { down: bool, lines: Option<usize> }, // `<C-d>`/`<C-u>`, a count sets `lines` for the ones after.
+    Undo { count: usize }, // `u`.
+    Redo { count: usize }, // `<C-r>`.
     AddCursor, // NOTE: This is likely just mocked.
     RemoveSecondaryCursors,
 }
 
+/// What `di{` or Visual `aw` act on: `inner` leaves out the delimiters or surrounding whitespace.
+#[derive(Debug, Clone, Copy, Eq, Hash, PartialEq)]
+pub enum TextObject {
+    /// The text between a pair of delimiters. Quotes use the same character for both.
+    Pair { open: char, close: char, inner: bool },
+    /// A word, `big` for `iW`/`aW`.
+    Word { big: bool, inner: bool },
+}
+
+impl TextObject {
+    /// Char range of the object around `offset`, if there's one.
+    fn range(self, buffer: &Buffer, offset: usize) -> Option<Range<usize>> {
+        match self {
+            TextObject::Pair { open, close, inner } => buffer.find_enclosing_pair(offset, open, close, inner),
+            TextObject::Word { big, inner } => buffer.word_object(offset, big, inner),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum EngineAction {
     Action(Action),
     App(Message),
+    Ex(String), // A `:` command line, submitted with Enter.
+}
+
+/// A change of `KeyEngine::mode` made by a key, passed to the hooks added with
+/// `KeyEngine::on_mode_change`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModeTransition {
+    pub from: EditorMode,
+    pub to: EditorMode,
 }
 
+type ModeHook = Rc<dyn Fn(&ModeTransition)>;
+
 #[derive(Clone)]
 pub struct KeyEngine {
     pub mode: EditorMode,
     keymap: Keymap,
     last_edit: Option<Action>, // For ".".
+    pub command_line: String, // What's been typed after `:` so far.
+    command_return: EditorMode, // Where the command line goes back to, Insert after `<C-o>:`.
+    insert_normal: bool, // `<C-o>`: back to Insert after one Normal command.
+    count: Option<usize>, // Digits typed before a command.
+    motion_count: Option<usize>, // Digits typed after an operator, `3` in `2d3w`.
+    replace_count: Option<usize>, // `r` waiting for the character to replace with.
+    // `f`/`t` waiting for the character: forward, till, count and the operator before it (`df,`).
+    find_pending: Option<(bool, bool, usize, Option<Operator>)>,
+    last_find: Option<CharSearch>, // For `;` and `,`.
+    mode_hooks: Vec<ModeHook>,
 }
 
 impl Default for KeyEngine {
@@ -95,36 +220,86 @@ impl Default for KeyEngine {
             mode: EditorMode::Normal,
             keymap: Keymap::new(),
             last_edit: None,
+            command_line: String::new(),
+            command_return: EditorMode::Normal,
+            insert_normal: false,
+            count: None,
+            motion_count: None,
+            replace_count: None,
+            find_pending: None,
+            last_find: None,
+            mode_hooks: Vec::new(),
         }
     }
 }
 
 impl KeyEngine {
+    /// Calls `hook` whenever a key switches modes, after the switch. Setting `mode` directly
+    /// doesn't.
+    pub fn on_mode_change(&mut self, hook: impl Fn(&ModeTransition) + 'static) {
+        self.mode_hooks.push(Rc::new(hook));
+    }
+
     /// Returns at most **one** high-level action for the editor to execute.
     pub fn handle_key(&mut self, key: KeyEvent) -> Option<EngineAction> {
+        if self.mode_hooks.is_empty() {
+            return self.handle_key_in_mode(key);
+        }
+
+        let from = self.mode.clone();
+        let action = self.handle_key_in_mode(key);
+        if self.mode != from {
+            let transition = ModeTransition { from, to: self.mode.clone() };
+            for hook in &self.mode_hooks {
+                hook(&transition);
+            }
+        }
+        action
+    }
+
+    fn handle_key_in_mode(&mut self, key: KeyEvent) -> Option<EngineAction> {
         use EditorMode::*;
+
+        // Named keys like `<Home>` never type anything, so they can be bound in Insert mode too.
+        if self.mode == Insert
+            && matches!(key, KeyEvent::Key { key: Key::Named(_), .. })
+            && let Some(action) = self.keymap.handle_key(&Insert, &key, None)
+        {
+            return Some(action);
+        }
         match self.mode {
             Insert => match key {
+                // Run a single Normal mode command, see the Normal branch.
+                KeyEvent::Key { key: Key::Character(ref c), modifiers, .. }
+                    if modifiers.control() && c.as_str() == "o" =>
+                {
+                    self.mode = Normal;
+                    self.insert_normal = true;
+                    None
+                }
+
                 KeyEvent::Key { key, text, .. } => {
-                    // Prioritize text if available.
-                    if let Some(s) = text {
-                        if !s.is_empty() {
-                            for ch in s.chars() {
-                                self.last_edit = Some(Action::InsertChar(ch));
-                            }
-                            return Some(EngineAction::Action(Action::InsertText(s)));
+                    // Prioritize text if available. Control characters other than tabs come
+                    // from keys like `<C-Enter>`, those aren't typed.
+                    if let Some(s) = text
+                        && !s.is_empty()
+                        && !s.chars().any(|c| c.is_control() && c != '\t')
+                    {
+                        for ch in s.chars() {
+                            self.last_edit = Some(Action::InsertChar(ch));
                         }
+                        return Some(EngineAction::Action(Action::InsertText(s)));
                     }
 
-                    // Fallback to raw key if no text.
-                    if let Key::Character(s) = key {
-                        if s.len() == 1 {
-                            let c = s.chars().next().unwrap();
-                            if !c.is_control() {
-                                self.last_edit = Some(Action::InsertChar(c));
-                                return Some(EngineAction::Action(Action::InsertChar(c)));
-                            }
-                        }
+                    // Fallback to raw key if no text. That's a single character, which can still be
+                    // several bytes (`é`).
+                    if let Key::Character(s) = key
+                        && let mut chars = s.chars()
+                        && let (Some(c), None) = (chars.next(), chars.next())
+                        && !c.is_control()
+                    {
+                        self.last_edit = Some(Action::InsertChar(c));
+                        return Some(EngineAction::Action(Action::InsertChar(c)));
                     }
                     None
                 }
@@ -138,31 +313,118 @@ impl KeyEngine {
             },
 
             Normal => {
-                if let Some(action) = self.keymap.handle_key(&self.mode, &key, None) {
+                // `<C-o><Esc>` goes straight back to Insert.
+                if self.insert_normal && matches!(key, KeyEvent::Esc) {
+                    self.insert_normal = false;
+                    self.mode = Insert;
+                    return None;
+                }
+
+                // `r` takes whatever character comes next, which no binding can spell out.
+                if let Some(count) = self.replace_count.take() {
+                    let c = typed_char(&key)?;
+                    let action = Action::ReplaceChars { c, count };
+                    self.last_edit = Some(action.clone());
+                    return Some(EngineAction::Action(action));
+                }
+                if self.find_pending.is_some() {
+                    let action = self.finish_find(&key);
+                    self.end_insert_normal();
+                    return action;
+                }
+
+                if self.push_count_digit(&key) {
+                    return None;
+                }
+                if !self.keymap.is_pending() && self.start_find(&key, None) {
+                    return None;
+                }
+                // An operator waiting for its motion takes a find too, `df,`.
+                if let Some(op) = self.keymap.pending_operator()
+                    && self.start_find(&key, Some(op))
+                {
+                    self.keymap.cancel();
+                    return None;
+                }
+
+                if !self.keymap.is_pending()
+                    && let KeyEvent::Key { key: Key::Character(s), modifiers, .. } = &key
+                    && s.as_str() == "r"
+                    && modifiers.is_empty()
+                {
+                    self.replace_count = Some(self.count.take().unwrap_or(1));
+                    return None;
+                }
+
+                if let Some(action) = self.handle_mapped_key(&key).and_then(|action| self.find_again(action)) {
                     if let EngineAction::Action(v_action) = &action {
                         if matches!(
                             v_action,
-                            Action::InsertChar(_) | Action::Operate { .. }
+                            Action::InsertChar(_)
+                                | Action::Operate { .. }
+                                | Action::DeleteChars { .. }
+                                | Action::DeleteLines { .. }
+                                | Action::ToggleCase { .. }
                         ) {
                             self.last_edit = Some(v_action.clone());
                         }
                         if let Action::ChangeMode(m) = &v_action {
                             self.mode = m.clone();
                         }
+                        if let Action::Operate { op: Operator::Change, .. }
+                        | Action::Append { .. }
+                        | Action::InsertAtFirstNonBlank
+                        | Action::OpenLine { .. }
+                        | Action::ResumeInsert = &v_action
+                        {
+                            self.mode = Insert;
+                        }
                     }
+
+                    self.end_insert_normal();
                     return Some(action);
                 }
 
                 None
             }
 
-            Visual => {
-                if let Some(action) = self.keymap.handle_key(&self.mode, &key, None) {
+            Command => self.handle_command_key(key),
+
+            Visual | VisualLine | VisualBlock => {
+                if self.find_pending.is_some() {
+                    return self.finish_find(&key);
+                }
+                if self.start_find(&key, None) {
+                    return None;
+                }
+
+                let action = self.keymap.handle_key(&self.mode, &key, None);
+                if let Some(action) = action.and_then(|action| self.find_again(action)) {
                     // NOTE: This is a bad way of doing this but will do it for now.
-                    if let EngineAction::Action(Action::DeleteSelection) = action {
-                        self.mode = Normal;
+                    match action {
+                        EngineAction::Action(
+                            Action::DeleteSelection
+                            | Action::YankSelection
+                            | Action::BlockDelete
+                            | Action::BlockYank
+                            | Action::Paste { .. },
+                        ) => self.mode = Normal,
+                        EngineAction::Action(
+                            Action::ChangeSelection
+                            | Action::BlockChange
+                            | Action::InsertAtSelection { .. }
+                            | Action::BlockInsert { .. },
+                        ) => self.mode = Insert,
+                        // A command line from Visual mode starts out on the selected lines.
+                        EngineAction::Action(Action::ChangeMode(Command)) => {
+                            self.mode = Command;
+                            self.command_line = "'<,'>".to_string();
+                        }
+                        // Switching between Visual modes keeps the selection, see `adjust_for_mode`.
+                        EngineAction::Action(Action::ChangeMode(ref mode)) => self.mode = mode.clone(),
+                        _ => {}
                     }
-                    
+
                     return Some(action);
                 }
 
@@ -172,15 +434,6 @@ impl KeyEngine {
                     if let Some(motion) = Motion::from_hjkl(c) {
                         return Some(EngineAction::Action(Action::Move { motion, count: 1 }));
                     }
-
-                    if let Some(op) = Operator::from_char(c) {
-                        self.mode = Normal;
-                        return Some(EngineAction::Action(Action::Operate {
-                            _op: op,
-                            _motion: Motion::CharRight,
-                            _count: 1,
-                        }));
-                    }
                 }
 
                 if let KeyEvent::Esc = key {
@@ -192,14 +445,161 @@ impl KeyEngine {
         }
     }
 
+    /// The command after `<C-o>` is done, unless it switched modes itself.
+    /// It then runs with Insert mode semantics, so `$` lands past the last character.
+    /// A command line goes back to Insert once it's run or cancelled.
+    fn end_insert_normal(&mut self) {
+        if std::mem::take(&mut self.insert_normal) {
+            match self.mode {
+                EditorMode::Normal => self.mode = EditorMode::Insert,
+                EditorMode::Command => self.command_return = EditorMode::Insert,
+                _ => {}
+            }
+        }
+    }
+
+    /// `f`, `F`, `t` and `T` wait for the character to find, which no binding can spell out.
+    /// Returns whether `key` was one of them.
+    fn start_find(&mut self, key: &KeyEvent, op: Option<Operator>) -> bool {
+        let KeyEvent::Key { key: Key::Character(s), modifiers, .. } = key else {
+            return false;
+        };
+        if modifiers.control() || modifiers.alt() {
+            return false;
+        }
+
+        let (forward, till) = match s.as_str() {
+            "f" => (true, false),
+            "F" => (false, false),
+            "t" => (true, true),
+            "T" => (false, true),
+            _ => return false,
+        };
+        // Counts before and after the operator multiply, like `handle_mapped_key` does.
+        let count = self.count.take().unwrap_or(1).saturating_mul(self.motion_count.take().unwrap_or(1));
+        self.find_pending = Some((forward, till, count, op));
+        true
+    }
+
+    /// The character after `f` and the like, moving to it or running the operator up to it.
+    fn finish_find(&mut self, key: &KeyEvent) -> Option<EngineAction> {
+        let (forward, till, count, op) = self.find_pending.take()?;
+        let search = CharSearch { ch: typed_char(key)?, forward, till, repeat: false };
+        let motion = Motion::FindChar(search);
+
+        self.last_find = Some(search);
+        let Some(op) = op else {
+            return Some(EngineAction::Action(Action::Move { motion, count }));
+        };
+
+        if op == Operator::Change {
+            self.mode = EditorMode::Insert;
+        }
+        let action = Action::Operate { op, motion, count };
+        self.last_edit = Some(action.clone());
+        Some(EngineAction::Action(action))
+    }
+
+    /// `action` with `;` or `,` turned into the search they repeat, `None` if there was none yet.
+    fn find_again(&self, action: EngineAction) -> Option<EngineAction> {
+        let again = |motion: Motion| match motion {
+            Motion::RepeatFind => Some(Motion::FindChar(self.last_find?.again(false))),
+            Motion::RepeatFindReverse => Some(Motion::FindChar(self.last_find?.again(true))),
+            motion => Some(motion),
+        };
+
+        Some(match action {
+            EngineAction::Action(Action::Move { motion, count }) => {
+                EngineAction::Action(Action::Move { motion: again(motion)?, count })
+            }
+            EngineAction::Action(Action::Operate { op, motion, count }) => {
+                EngineAction::Action(Action::Operate { op, motion: again(motion)?, count })
+            }
+            action => action,
+        })
+    }
+
+    /// Accumulates a count, `0` only counts once it's not the first digit (it's a motion then).
+    /// Digits after an operator count its motion instead, unless they continue a binding.
+    fn push_count_digit(&mut self, key: &KeyEvent) -> bool {
+        let KeyEvent::Key { key: Key::Character(c), modifiers, .. } = key else {
+            return false;
+        };
+        let Some(digit) = c.chars().next().and_then(|c| c.to_digit(10)) else {
+            return false;
+        };
+
+        let pending = self.keymap.is_pending();
+        let count = if pending { &mut self.motion_count } else { &mut self.count };
+        if !modifiers.is_empty() || (digit == 0 && count.is_none()) {
+            return false;
+        }
+        if pending && self.keymap.continues(&self.mode, key) {
+            return false;
+        }
+
+        *count = Some(count.unwrap_or(0).saturating_mul(10).saturating_add(digit as usize));
+        true
+    }
+
+    /// Looks `key` up in the keymap, handing it the pending count. Like Vim, counts before and
+    /// after an operator multiply: `2d3w` deletes 6 words. The counts are dropped once the key
+    /// sequence is done, whether it matched or not.
+    fn handle_mapped_key(&mut self, key: &KeyEvent) -> Option<EngineAction> {
+        let count = match (self.count, self.motion_count) {
+            (None, None) => None,
+            (count, motion_count) => Some(count.unwrap_or(1).saturating_mul(motion_count.unwrap_or(1))),
+        };
+
+        let action = self.keymap.handle_key(&self.mode, key, count);
+        if !self.keymap.is_pending() {
+            self.count = None;
+            self.motion_count = None;
+        }
+        action
+    }
+
+    /// Command mode just edits the command line, only Enter hands it out to be run. Either that
+    /// or cancelling it goes back to the mode it was started from.
+    fn handle_command_key(&mut self, key: KeyEvent) -> Option<EngineAction> {
+        match key {
+            KeyEvent::Key { key, text, .. } => {
+                let text = text.or_else(|| match key {
+                    Key::Character(s) => Some(s.to_string()),
+                    _ => None,
+                })?;
+                self.command_line.extend(text.chars().filter(|c| !c.is_control()));
+                None
+            }
+            KeyEvent::Backspace if !self.command_line.is_empty() => {
+                self.command_line.pop();
+                None
+            }
+            // Backspacing past the `:` leaves Command mode, like Esc.
+            KeyEvent::Esc | KeyEvent::Backspace => {
+                self.command_line.clear();
+                self.mode = std::mem::replace(&mut self.command_return, EditorMode::Normal);
+                Some(EngineAction::Action(Action::ChangeMode(self.mode.clone())))
+            }
+            KeyEvent::Enter => {
+                self.mode = std::mem::replace(&mut self.command_return, EditorMode::Normal);
+                Some(EngineAction::Ex(std::mem::take(&mut self.command_line)))
+            }
+        }
+    }
+
     pub fn _repeat_last(&self) -> Option<Action> {
         self.last_edit.clone()
     }
 
-    /// Count handling
     pub fn has_pending_count(&self) -> bool {
-        // TODO: Implement count handling in keymap.
-        false
+        self.count.is_some()
+    }
+
+    /// Keys to feed back into `handle_key`, in order, after the action they came with: `d` only
+    /// runs once the next key shows it wasn't `di{`, and that key still needs handling.
+    pub fn take_replay(&mut self) -> Vec<KeyEvent> {
+        self.keymap.take_replay()
     }
 }
 
@@ -217,18 +617,198 @@ pub enum KeyEvent {
     Enter,
 }
 
-pub fn execute(action: Action, buffer: &mut Buffer, multi_cursor: &mut MultiCursor, editor_mode: &EditorMode) {
+/// Carries out `action`. Returns `false` when it couldn't be, for the widget to ring the bell:
+/// relative motions that didn't move any cursor, or a cursor with nowhere to be added.
+pub fn execute(
+    action: Action,
+    buffer: &mut Buffer,
+    multi_cursor: &mut MultiCursor,
+    editor_mode: &EditorMode,
+    registers: &mut Registers,
+    match_pairs: &[(char, char)],
+) -> bool {
+    let mut done = true;
+
     match action {
+        // Typing over selections replaces them, at every cursor.
+        Action::InsertChar(c) if has_selection(multi_cursor) => {
+            buffer.replace_all_selections_text(multi_cursor, c.encode_utf8(&mut [0; 4]))
+        }
+        Action::InsertText(s) if has_selection(multi_cursor) => {
+            buffer.replace_all_selections_text(multi_cursor, &s)
+        }
         Action::InsertChar(c)        => buffer.insert_char(multi_cursor, c),
         Action::InsertText(s)        => buffer.insert_text(multi_cursor, s.as_str()),
-        Action::Move { motion, .. }  => apply_motion(motion, buffer, multi_cursor, editor_mode),
-        Action::Operate { .. }       => println!("Todo!"),
-        Action::ChangeMode(new_mode) => multi_cursor.adjust_for_mode(buffer, &new_mode),
+        // `2tx` stops before the second `x`, repeating `tx` wouldn't get past the first one.
+        Action::Move { motion: Motion::FindChar(search), count } => {
+            let before = cursor_positions(multi_cursor);
+            multi_cursor.find_char(buffer, search, count, editor_mode);
+            done = cursor_positions(multi_cursor) != before;
+        }
+        Action::Move { motion, count } => {
+            let before = cursor_positions(multi_cursor);
+            let mut last = before.clone();
+            for _ in 0..count.max(1) {
+                apply_motion(motion.clone(), buffer, multi_cursor, editor_mode, match_pairs);
+                // Once the cursors stop, going on won't move them, `999l` needn't try 999 times.
+                let positions = cursor_positions(multi_cursor);
+                if motion.is_relative() && positions == last {
+                    break;
+                }
+                last = positions;
+            }
+            done = !motion.is_relative() || last != before;
+        }
+        Action::Operate { op, motion, count } => {
+            let ranges = operator_ranges(&motion, count, buffer, multi_cursor, match_pairs);
+            match op {
+                Operator::Yank => {
+                    if let Some(range) = ranges[multi_cursor.primary_index].clone() {
+                        yank_range(buffer, registers, range, motion.is_linewise());
+                    }
+                }
+                Operator::Delete | Operator::Change => {
+                    delete_ranges(buffer, multi_cursor, registers, ranges, motion.is_linewise());
+                    multi_cursor.adjust_for_mode(buffer, editor_mode);
+                }
+            }
+            multi_cursor.collapse_all_selections();
+        }
+        Action::ChangeMode(new_mode) => {
+            // Leaving Visual (or any other mode) for Normal or a command line drops the
+            // selections, the primary one is remembered for `'<,'>`.
+            if matches!(new_mode, EditorMode::Normal | EditorMode::Command) {
+                if has_selection(multi_cursor) {
+                    buffer.last_visual = Some(multi_cursor.primary().selection_range(buffer));
+                }
+                multi_cursor.collapse_all_selections();
+            }
+            multi_cursor.adjust_for_mode(buffer, &new_mode);
+        }
         Action::RepeatLast           => println!("Handled by engine"),
         Action::Backspace            => buffer.backspace(multi_cursor),
         Action::InsertNewline        => buffer.insert_newline(multi_cursor),
-        Action::Delete               => buffer.delete(multi_cursor),
-        Action::DeleteSelection      => buffer.delete_selection(multi_cursor),
+        Action::DeleteChars { count, before } => {
+            let ranges = multi_cursor
+                .all_cursors()
+                .iter()
+                .map(|cursor| line_span(buffer, cursor.position(), count, before))
+                .collect();
+
+            delete_ranges(buffer, multi_cursor, registers, ranges, false);
+            multi_cursor.adjust_for_mode(buffer, editor_mode);
+        }
+        Action::DeleteLines { count } => delete_lines(buffer, multi_cursor, registers, count),
+        Action::YankLines { count } => {
+            let lines = counted_lines(buffer, multi_cursor.position().line, count);
+            registers.set_unnamed_lines(buffer.lines_text(lines));
+        }
+        Action::ReplaceChars { c, count } => {
+            let count = count.max(1);
+            // Like Vim, `3rx` with fewer than 3 characters left does nothing.
+            let fits = |buffer: &Buffer, pos: TextPosition| pos.col + count <= buffer.grapheme_len(pos.line);
+            let edits = multi_cursor
+                .all_cursors()
+                .iter()
+                .map(|cursor| cursor.position())
+                .filter(|&pos| fits(buffer, pos))
+                .filter_map(|pos| Some((line_span(buffer, pos, count, false)?, c.to_string().repeat(count))))
+                .collect();
+
+            buffer.apply_edits(&without_overlaps(edits), multi_cursor);
+            // The cursor ends up on the last replaced character.
+            multi_cursor.move_to_cols(buffer, editor_mode, |pos| match fits(buffer, pos) {
+                true => pos.col + count - 1,
+                false => pos.col,
+            });
+        }
+        Action::ToggleCase { count } => {
+            let edits = multi_cursor
+                .all_cursors()
+                .iter()
+                .filter_map(|cursor| {
+                    let span = line_span(buffer, cursor.position(), count, false)?;
+                    let toggled = buffer.content.slice(span.clone()).chars().map(toggle_case);
+                    Some((span, toggled.collect()))
+                })
+                .collect();
+
+            buffer.apply_edits(&without_overlaps(edits), multi_cursor);
+            // The cursor moves past the toggled characters, but not past the end of the line.
+            multi_cursor.move_to_cols(buffer, editor_mode, |pos| {
+                (pos.col + count.max(1)).min(buffer.grapheme_len(pos.line).saturating_sub(1))
+            });
+        }
+        Action::DeleteSelection      => {
+            yank_selection(buffer, multi_cursor, registers);
+            buffer.delete_selection(multi_cursor);
+        }
+        Action::YankSelection        => {
+            yank_selection(buffer, multi_cursor, registers);
+            multi_cursor.collapse_to_selection(buffer, false);
+        }
+        Action::ChangeSelection      => {
+            yank_selection(buffer, multi_cursor, registers);
+            let edits = multi_cursor
+                .all_cursors()
+                .iter()
+                .map(|cursor| {
+                    let (start, end) = cursor.selection_range(buffer);
+                    // Whole lines keep their last line break, leaving an empty line to type on.
+                    let end = match cursor.is_linewise() {
+                        true => end.offset,
+                        false => buffer.next_grapheme_offset(end.offset),
+                    };
+                    (start.offset..end, String::new())
+                })
+                .collect();
+
+            buffer.apply_edits(&without_overlaps(edits), multi_cursor);
+        }
+        Action::Paste { before, over_selection } => {
+            // Pasting over a selection swaps it into the register, so two regions can be swapped.
+            let replaced = over_selection.then(|| buffer.selection_text(multi_cursor.primary()));
+            match registers.is_linewise() && !over_selection {
+                true => buffer.paste_lines(multi_cursor, registers.unnamed(), before),
+                false => buffer.paste(multi_cursor, registers.unnamed(), before, over_selection),
+            }
+            if let Some(replaced) = replaced {
+                registers.set_unnamed(replaced);
+            }
+        }
+        Action::SelectTextObject(object) => multi_cursor.select_ranges(buffer, |pos| object.range(buffer, pos.offset)),
+        Action::DeleteTextObject(object) => {
+            let ranges = multi_cursor
+                .all_cursors()
+                .iter()
+                .map(|cursor| object.range(buffer, cursor.position().offset))
+                .collect();
+
+            delete_ranges(buffer, multi_cursor, registers, ranges, false);
+            multi_cursor.adjust_for_mode(buffer, editor_mode);
+        }
+        Action::InsertAtSelection { end } => multi_cursor.collapse_to_selection(buffer, end),
+        // Insert mode's columns, which go past the last character.
+        Action::Append { line_end: false } => multi_cursor.move_right(buffer, &EditorMode::Insert),
+        Action::Append { line_end: true } => multi_cursor.move_to_line_end(buffer, &EditorMode::Insert),
+        Action::InsertAtFirstNonBlank => multi_cursor.move_to_first_non_blank(buffer, &EditorMode::Insert),
+        Action::OpenLine { above }   => buffer.open_line(multi_cursor, above),
+        Action::ResumeInsert         => resume_insert(buffer, multi_cursor),
+        Action::BlockInsert { end }  => multi_cursor.block_insert(buffer, end),
+        Action::BlockDelete | Action::BlockYank | Action::BlockChange => {
+            edit_block(buffer, multi_cursor, registers, &action, editor_mode)
+        }
+        Action::Indent               => buffer.shift_lines(multi_cursor, true),
+        Action::Dedent               => buffer.shift_lines(multi_cursor, false),
+        Action::JoinLines { count, keep_whitespace } => buffer.join_lines(multi_cursor, count, keep_whitespace),
+        // Without a window there's nothing to scroll, the widget handles these.
+        Action::Scroll { .. }        => {}
+        Action::Undo { count } | Action::Redo { count } => {
+            let steps = count.max(1) as isize;
+            let steps = if matches!(action, Action::Undo { .. }) { -steps } else { steps };
+            done = buffer.travel_history(steps, multi_cursor);
+            multi_cursor.adjust_for_mode(buffer, editor_mode);
+        }
         
         // MOCKED
         Action::AddCursor => {
@@ -243,29 +823,276 @@ pub fn execute(action: Action, buffer: &mut Buffer, multi_cursor: &mut MultiCurs
                 let new_col = current_pos.col.min(line_len);
                 let new_offset = buffer.grapheme_col_to_offset(next_line, new_col);
 
-                atlas_engine::TextPosition::new(next_line, new_col, new_offset)
+                Some(atlas_engine::TextPosition::new(next_line, new_col, new_offset))
             } else {
                 // At last line, try to move right instead.
                 let line_len = buffer.grapheme_len(current_pos.line);
                 if current_pos.col < line_len {
                     let new_col = current_pos.col + 1;
                     let new_offset = buffer.grapheme_col_to_offset(current_pos.line, new_col);
-                    atlas_engine::TextPosition::new(current_pos.line, new_col, new_offset)
+                    Some(atlas_engine::TextPosition::new(current_pos.line, new_col, new_offset))
                 } else {
-                    // Can't add cursor anywhere, just return without adding.
-                    return;
+                    None
                 }
             };
 
-            buffer.validate_position(&new_pos);
-            multi_cursor.add_cursor(new_pos, buffer);
+            match new_pos {
+                Some(new_pos) => {
+                    buffer.validate_position(&new_pos);
+                    multi_cursor.add_cursor(new_pos, buffer);
+                }
+                // Can't add cursor anywhere.
+                None => done = false,
+            }
         },
         
         Action::RemoveSecondaryCursors => multi_cursor.clear_secondary_cursors(),
     }
+
+    // Every move in Insert mode could be the last one before leaving it.
+    if *editor_mode == EditorMode::Insert {
+        buffer.last_insert = Some(multi_cursor.position());
+    }
+
+    done
+}
+
+/// `~` on one character. Those whose other case takes several characters, like `ß`, stay as
+/// they are.
+fn toggle_case(c: char) -> char {
+    let toggled: Vec<char> = match c.is_uppercase() {
+        true => c.to_lowercase().collect(),
+        false => c.to_uppercase().collect(),
+    };
+    match toggled.as_slice() {
+        [t] => *t,
+        _ => c,
+    }
+}
+
+/// The character `key` types, if it's a single printable one.
+fn typed_char(key: &KeyEvent) -> Option<char> {
+    let KeyEvent::Key { key: Key::Character(s), modifiers, .. } = key else {
+        return None;
+    };
+    let mut chars = s.chars();
+    let (Some(c), None) = (chars.next(), chars.next()) else {
+        return None;
+    };
+    if modifiers.control() || modifiers.alt() || c.is_control() {
+        return None;
+    }
+    Some(c)
+}
+
+fn cursor_positions(multi_cursor: &MultiCursor) -> Vec<TextPosition> {
+    multi_cursor.all_cursors().iter().map(|cursor| cursor.position()).collect()
+}
+
+/// `gi`: a single cursor where Insert mode was last left, clamped to what the buffer has become.
+/// Without one, Insert mode just starts at the cursor.
+fn resume_insert(buffer: &Buffer, multi_cursor: &mut MultiCursor) {
+    let Some(pos) = buffer.last_insert else {
+        return;
+    };
+
+    let line = pos.line.min(buffer.content.len_lines() - 1);
+    let col = pos.col.min(buffer.grapheme_len(line));
+    let pos = TextPosition::new(line, col, buffer.grapheme_col_to_offset(line, col));
+
+    multi_cursor.clear_secondary_cursors();
+    multi_cursor
+        .primary_mut()
+        .move_to(pos, MoveOpts { anchor: None, update_preferred_col: true }, buffer);
+}
+
+/// What an operator with `motion` covers at each cursor (in cursor order): from the cursor to
+/// where the motion takes it. Linewise motions cover whole lines, `j`, `k` and finds cover nothing
+/// when they can't move.
+fn operator_ranges(
+    motion: &Motion,
+    count: usize,
+    buffer: &Buffer,
+    multi_cursor: &MultiCursor,
+    match_pairs: &[(char, char)],
+) -> Vec<Option<Range<usize>>> {
+    multi_cursor
+        .all_cursors()
+        .iter()
+        .map(|cursor| {
+            let start = cursor.position();
+            let mut moved = MultiCursor { cursors: vec![cursor.clone()], primary_index: 0 };
+            match motion {
+                // `2dt,` goes up to the second `,`, repeating `t,` wouldn't get past the first one.
+                // Finding nothing leaves the text alone, even though `f` is inclusive.
+                Motion::FindChar(search) => {
+                    moved.primary_mut().find_char(buffer, *search, count, &EditorMode::Normal)?;
+                }
+                _ => {
+                    for _ in 0..count.max(1) {
+                        apply_motion(motion.clone(), buffer, &mut moved, &EditorMode::Normal, match_pairs);
+                    }
+                }
+            }
+            let end = moved.position();
+
+            if matches!(motion, Motion::CharUp | Motion::CharDown) && start.line == end.line {
+                return None;
+            }
+
+            if motion.is_linewise() {
+                return Some(buffer.lines_char_range(start.line.min(end.line)..start.line.max(end.line) + 1));
+            }
+
+            let (from, to) = if start.offset <= end.offset { (start, end) } else { (end, start) };
+            let to = if motion.is_inclusive() { buffer.next_grapheme_offset(to.offset) } else { to.offset };
+            Some(from.offset..to)
+        })
+        .collect()
+}
+
+/// Deletes every cursor's range (if it has one) in one pass, the primary cursor's text goes to
+/// the register, as whole lines when `linewise`. Cursors can share a range, and an outer range
+/// swallows the ones inside it.
+fn delete_ranges(
+    buffer: &mut Buffer,
+    multi_cursor: &mut MultiCursor,
+    registers: &mut Registers,
+    ranges: Vec<Option<Range<usize>>>,
+    linewise: bool,
+) {
+    if let Some(range) = ranges[multi_cursor.primary_index].clone() {
+        yank_range(buffer, registers, range, linewise);
+    }
+
+    let edits = ranges.into_iter().flatten().map(|range| (range, String::new())).collect();
+    buffer.apply_edits(&without_overlaps(edits), multi_cursor);
+}
+
+/// Puts `range` in the register. When `linewise` it's the whole lines from `Buffer::lines_char_range`,
+/// which go in as lines like `yy` puts them.
+fn yank_range(buffer: &Buffer, registers: &mut Registers, range: Range<usize>, linewise: bool) {
+    if !linewise {
+        registers.set_unnamed(buffer.content.slice(range).to_string());
+        return;
+    }
+
+    // At the end of the buffer the range starts on the line break before its lines.
+    let first = buffer.content.char_to_line(range.start);
+    let first = if buffer.content.line_to_char(first) == range.start { first } else { first + 1 };
+    let last = buffer.content.char_to_line(range.end.saturating_sub(1)).max(first);
+    registers.set_unnamed_lines(buffer.lines_text(first..last + 1));
+}
+
+/// Puts the primary cursor's selection in the register, as lines in Visual line mode.
+fn yank_selection(buffer: &Buffer, multi_cursor: &MultiCursor, registers: &mut Registers) {
+    let primary = multi_cursor.primary();
+    match primary.is_linewise() {
+        true => {
+            let (start, end) = primary.selection_range(buffer);
+            registers.set_unnamed_lines(buffer.lines_text(start.line..end.line + 1));
+        }
+        false => registers.set_unnamed(buffer.selection_text(primary)),
+    }
+}
+
+/// Visual block `d`, `y` and `c`: the block goes to the register, one line of it per line. `d` and
+/// `c` take it out of every line, then `c` types on each line that reached it. The others leave
+/// the cursor on the block's top left corner.
+fn edit_block(
+    buffer: &mut Buffer,
+    multi_cursor: &mut MultiCursor,
+    registers: &mut Registers,
+    action: &Action,
+    editor_mode: &EditorMode,
+) {
+    let (lines, cols) = multi_cursor.primary().block();
+    let ranges = buffer.block_ranges(lines.clone(), cols.clone());
+    let text: Vec<String> = ranges.iter().map(|range| buffer.content.slice(range.clone()).to_string()).collect();
+    registers.set_unnamed(text.join("\n"));
+
+    let col = cols.start.min(buffer.grapheme_len(lines.start));
+    let corner = TextPosition::new(lines.start, col, buffer.grapheme_col_to_offset(lines.start, col));
+    multi_cursor.clear_secondary_cursors();
+    multi_cursor.primary_mut().move_to(corner, MoveOpts { anchor: None, update_preferred_col: true }, buffer);
+
+    // Lines too short to reach the block are left alone, `c` doesn't type on them either.
+    let reached: Vec<usize> = lines.clone().filter(|&line| !ranges[line - lines.start].is_empty()).collect();
+    if !matches!(action, Action::BlockYank) {
+        let edits = ranges.into_iter().filter(|range| !range.is_empty()).map(|range| (range, String::new()));
+        buffer.apply_edits(&edits.collect::<Vec<_>>(), multi_cursor);
+    }
+    match action {
+        Action::BlockChange => multi_cursor.insert_on_lines(buffer, &reached, cols.start),
+        _ => multi_cursor.adjust_for_mode(buffer, editor_mode),
+    }
+}
+
+/// `dd`: deletes `count` lines from each cursor's down, as lines into the register, and leaves
+/// the cursors on the first non-blank of the line after.
+fn delete_lines(buffer: &mut Buffer, multi_cursor: &mut MultiCursor, registers: &mut Registers, count: usize) {
+    let primary = counted_lines(buffer, multi_cursor.position().line, count);
+    registers.set_unnamed_lines(buffer.lines_text(primary));
+
+    let lines: Vec<usize> = multi_cursor
+        .all_cursors()
+        .iter()
+        .flat_map(|cursor| counted_lines(buffer, cursor.position().line, count))
+        .collect();
+    buffer.delete_lines(&lines, multi_cursor);
+
+    // Each cursor is at the start of what followed its lines, or the end of the line before
+    // them at the end of the buffer. The empty line after a final line break doesn't count.
+    let last = buffer.line_count() - 1;
+    for cursor in multi_cursor.all_cursors_mut() {
+        let line = cursor.position().line;
+        let line = if line == last && line > 0 && buffer.grapheme_len(line) == 0 { line - 1 } else { line };
+        cursor.move_to_line(buffer, line, LineColumn::FirstNonBlank, &EditorMode::Normal);
+    }
+}
+
+/// `count` lines from `line` down, as many as there are.
+fn counted_lines(buffer: &Buffer, line: usize, count: usize) -> Range<usize> {
+    line..(line + count.max(1)).min(buffer.content.len_lines())
+}
+
+/// Drops the edits that overlap an earlier one, so cursors that share a span edit it once.
+/// An edit that starts at the same spot as another but reaches further wins.
+fn without_overlaps(mut edits: Vec<Edit>) -> Vec<Edit> {
+    edits.sort_by_key(|(range, _)| (range.start, std::cmp::Reverse(range.end)));
+
+    let mut kept: Vec<Edit> = Vec::with_capacity(edits.len());
+    for edit in edits {
+        if kept.last().is_none_or(|(last, _)| edit.0.start >= last.end) {
+            kept.push(edit);
+        }
+    }
+    kept
+}
+
+/// Char range of the `count` graphemes from `pos` on (`before`: the ones before it), cut short
+/// at the edge of its line.
+fn line_span(buffer: &Buffer, pos: TextPosition, count: usize, before: bool) -> Option<Range<usize>> {
+    let count = count.max(1);
+    let (start, end) = match before {
+        true => (pos.col.saturating_sub(count), pos.col),
+        false => (pos.col, (pos.col + count).min(buffer.grapheme_len(pos.line))),
+    };
+
+    (start < end).then(|| buffer.grapheme_col_to_offset(pos.line, start)..buffer.grapheme_col_to_offset(pos.line, end))
+}
+
+fn has_selection(multi_cursor: &MultiCursor) -> bool {
+    multi_cursor.all_cursors().iter().any(|cursor| cursor.has_selection())
 }
 
-fn apply_motion(motion: Motion, buffer: &Buffer, multi_cursor: &mut MultiCursor, editor_mode: &EditorMode) {
+fn apply_motion(
+    motion: Motion,
+    buffer: &Buffer,
+    multi_cursor: &mut MultiCursor,
+    editor_mode: &EditorMode,
+    match_pairs: &[(char, char)],
+) {
     match motion {
         Motion::CharLeft => multi_cursor.move_left(buffer, editor_mode),
         Motion::CharRight => multi_cursor.move_right(buffer, editor_mode),
@@ -274,7 +1101,1277 @@ fn apply_motion(motion: Motion, buffer: &Buffer, multi_cursor: &mut MultiCursor,
         Motion::NextWordStart(big_word) => multi_cursor.move_word_forward(buffer, big_word, editor_mode),
         Motion::PrevWord(big_word) => multi_cursor.move_word_backward(buffer, big_word, editor_mode),
         Motion::NextWordEnd(big_word) => multi_cursor.move_word_end(buffer, big_word, editor_mode),
-        Motion::ToLineStart => println!("Line start"),
-        Motion::_ToLineEnd => todo!(),
+        Motion::ToLineStart => multi_cursor.move_to_line_start(buffer, editor_mode),
+        Motion::ToLineEnd => multi_cursor.move_to_line_end(buffer, editor_mode),
+        Motion::ToFirstNonBlank => multi_cursor.move_to_first_non_blank(buffer, editor_mode),
+        Motion::ToColumn(col) => multi_cursor.move_to_display_col(buffer, col.saturating_sub(1), editor_mode),
+        // Screen rows are the widget's business, without it they're just lines.
+        Motion::ToRowStart => multi_cursor.move_to_line_start(buffer, editor_mode),
+        Motion::ToRowEnd => multi_cursor.move_to_line_end(buffer, editor_mode),
+        // The empty "line" after a trailing line break is only reachable with `j`.
+        Motion::ToFirstLine => multi_cursor.move_to_line(buffer, 0, LineColumn::FirstNonBlank, editor_mode),
+        Motion::ToLastLine => {
+            multi_cursor.move_to_line(buffer, buffer.line_count() - 1, LineColumn::FirstNonBlank, editor_mode)
+        }
+        Motion::ToLine(line) => {
+            let line = line.saturating_sub(1).min(buffer.line_count() - 1);
+            multi_cursor.move_to_line(buffer, line, LineColumn::FirstNonBlank, editor_mode)
+        }
+        Motion::MatchPair => multi_cursor.move_to_matching_pair(buffer, match_pairs, editor_mode),
+        Motion::FindChar(search) => multi_cursor.find_char(buffer, search, 1, editor_mode),
+        // The key engine turns these into the `FindChar` they repeat.
+        Motion::RepeatFind | Motion::RepeatFindReverse => {}
+        // Without a window there's no page to move by, the widget handles these.
+        Motion::PageDown | Motion::PageUp => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use atlas_engine::{RegisterKind, registers::UNNAMED};
+
+    fn key(c: &str, modifiers: Modifiers) -> KeyEvent {
+        KeyEvent::Key {
+            key: Key::Character(c.into()),
+            text: Some(c.to_string()),
+            modifiers,
+        }
+    }
+
+    const MATCH_PAIRS: &[(char, char)] = &[('(', ')'), ('[', ']'), ('{', '}')];
+
+    /// Feed `keys` through the engine, executing every resulting action.
+    fn press(engine: &mut KeyEngine, buffer: &mut Buffer, mc: &mut MultiCursor, keys: &[KeyEvent]) {
+        press_with(engine, buffer, mc, &mut Registers::new(), keys);
+    }
+
+    fn press_with(
+        engine: &mut KeyEngine,
+        buffer: &mut Buffer,
+        mc: &mut MultiCursor,
+        registers: &mut Registers,
+        keys: &[KeyEvent],
+    ) {
+        let mut keys: Vec<KeyEvent> = keys.iter().rev().cloned().collect();
+        while let Some(k) = keys.pop() {
+            if let Some(EngineAction::Action(action)) = engine.handle_key(k) {
+                execute(action, buffer, mc, &engine.mode, registers, MATCH_PAIRS);
+            }
+            keys.extend(engine.take_replay().into_iter().rev());
+        }
+    }
+
+    /// Unmodified keys for each character of `s`.
+    fn typed(s: &str) -> Vec<KeyEvent> {
+        s.chars().map(|c| key(&c.to_string(), Modifiers::empty())).collect()
+    }
+
+    fn named(named: keyboard::key::Named, modifiers: Modifiers) -> KeyEvent {
+        KeyEvent::Key {
+            key: Key::Named(named),
+            text: None,
+            modifiers,
+        }
+    }
+
+    #[test]
+    fn tab_in_normal_mode_indents_line() {
+        let mut buffer = Buffer::new("foo\nbar", "t");
+        let mut mc = MultiCursor::new();
+        let mut engine = KeyEngine::default();
+        let tab = keyboard::key::Named::Tab;
+
+        press(&mut engine, &mut buffer, &mut mc, &[named(tab, Modifiers::empty())]);
+        assert_eq!(buffer.content.to_string(), "    foo\nbar");
+        assert_eq!(engine.mode, EditorMode::Normal);
+
+        press(&mut engine, &mut buffer, &mut mc, &[named(tab, Modifiers::SHIFT)]);
+        assert_eq!(buffer.content.to_string(), "foo\nbar");
+    }
+
+    #[test]
+    fn shifted_symbols_bind_without_shift() {
+        let mut buffer = Buffer::new("foo", "t");
+        let mut mc = MultiCursor::new();
+        let mut engine = KeyEngine::default();
+
+        press(&mut engine, &mut buffer, &mut mc, &[key(">", Modifiers::SHIFT), key(">", Modifiers::SHIFT)]);
+        assert_eq!(buffer.content.to_string(), "    foo");
+
+        press(&mut engine, &mut buffer, &mut mc, &[key("<", Modifiers::SHIFT), key("<", Modifiers::SHIFT)]);
+        assert_eq!(buffer.content.to_string(), "foo");
+    }
+
+    #[test]
+    fn g_shift_j_joins_without_space() {
+        let mut buffer = Buffer::new("foo\nbar\nbaz", "t");
+        let mut mc = MultiCursor::new();
+        let mut engine = KeyEngine::default();
+
+        press(&mut engine, &mut buffer, &mut mc, &[key("g", Modifiers::empty()), key("J", Modifiers::SHIFT)]);
+        assert_eq!(buffer.content.to_string(), "foobar\nbaz");
+
+        press(&mut engine, &mut buffer, &mut mc, &[key("J", Modifiers::SHIFT)]);
+        assert_eq!(buffer.content.to_string(), "foobar baz");
+    }
+
+    #[test]
+    fn a_count_joins_that_many_lines() {
+        let mut engine = KeyEngine::default();
+        let mut buffer = Buffer::new("one\n  two\n  three\nfour", "t");
+        let mut mc = MultiCursor::new();
+
+        // The cursor ends up on the space put in by the last join.
+        press(&mut engine, &mut buffer, &mut mc, &[key("3", Modifiers::empty()), key("J", Modifiers::SHIFT)]);
+        assert_eq!(buffer.content.to_string(), "one two three\nfour");
+        assert_eq!(mc.position().col, 7);
+
+        // Less than two still joins two, and `gJ` lands on the first joined character.
+        press(&mut engine, &mut buffer, &mut mc, &[key("1", Modifiers::empty()), key("g", Modifiers::empty()), key("J", Modifiers::SHIFT)]);
+        assert_eq!(buffer.content.to_string(), "one two threefour");
+        assert_eq!(mc.position().col, 13);
+    }
+
+    #[test]
+    fn visual_a_appends_after_selection() {
+        let mut buffer = Buffer::new("hello world", "t");
+        let mut mc = MultiCursor::new();
+        let mut engine = KeyEngine::default();
+        let none = Modifiers::empty();
+
+        press(&mut engine, &mut buffer, &mut mc, &[key("v", none), key("l", none), key("l", none)]);
+        assert_eq!(engine.mode, EditorMode::Visual);
+        assert!(mc.primary().has_selection());
+
+        press(&mut engine, &mut buffer, &mut mc, &[key("A", Modifiers::SHIFT)]);
+        assert_eq!(engine.mode, EditorMode::Insert);
+        assert!(!mc.primary().has_selection());
+        assert_eq!(mc.position().col, 3);
+
+        press(&mut engine, &mut buffer, &mut mc, &[key("!", none)]);
+        assert_eq!(buffer.content.to_string(), "hel!lo world");
+    }
+
+    #[test]
+    fn visual_c_replaces_the_selection() {
+        let mut engine = KeyEngine::default();
+        let mut buffer = Buffer::new("hello world\nfoo\nbar", "t");
+        let mut mc = MultiCursor::new();
+        let mut registers = Registers::new();
+
+        press_with(&mut engine, &mut buffer, &mut mc, &mut registers, &typed("fwvllc"));
+        assert_eq!(engine.mode, EditorMode::Insert);
+        assert_eq!(buffer.content.to_string(), "hello ld\nfoo\nbar");
+        assert_eq!(registers.get(UNNAMED), Some(("wor", RegisterKind::Charwise)));
+        press_with(&mut engine, &mut buffer, &mut mc, &mut registers, &typed("!"));
+        assert_eq!(buffer.content.to_string(), "hello !ld\nfoo\nbar");
+
+        // Whole lines leave an empty one to type on.
+        press_with(&mut engine, &mut buffer, &mut mc, &mut registers, &[KeyEvent::Esc]);
+        press_with(&mut engine, &mut buffer, &mut mc, &mut registers, &[key("j", Modifiers::empty()), key("V", Modifiers::SHIFT)]);
+        press_with(&mut engine, &mut buffer, &mut mc, &mut registers, &typed("jc?"));
+        assert_eq!(buffer.content.to_string(), "hello !ld\n?");
+    }
+
+    #[test]
+    fn ctrl_o_runs_one_normal_command() {
+        let mut buffer = Buffer::new("hello", "t");
+        let mut mc = MultiCursor::new();
+        let mut engine = KeyEngine::default();
+        let none = Modifiers::empty();
+
+        press(&mut engine, &mut buffer, &mut mc, &[key("i", none), key("l", none), key("l", none)]);
+        assert_eq!(mc.position().col, 2);
+
+        press(&mut engine, &mut buffer, &mut mc, &[key("o", Modifiers::CTRL)]);
+        assert_eq!(engine.mode, EditorMode::Normal);
+
+        press(&mut engine, &mut buffer, &mut mc, &[key("0", none)]);
+        assert_eq!(mc.position().col, 0);
+        assert_eq!(engine.mode, EditorMode::Insert);
+
+        // Only one command, the next key is typed again.
+        press(&mut engine, &mut buffer, &mut mc, &[key("x", none)]);
+        assert_eq!(buffer.content.to_string(), "xllhello");
+    }
+
+    #[test]
+    fn ctrl_o_dollar_stays_past_line_end() {
+        let mut buffer = Buffer::new("hello", "t");
+        let mut mc = MultiCursor::new();
+        let mut engine = KeyEngine::default();
+        let none = Modifiers::empty();
+
+        press(&mut engine, &mut buffer, &mut mc, &[key("i", none), key("o", Modifiers::CTRL), key("$", Modifiers::SHIFT)]);
+        assert_eq!(engine.mode, EditorMode::Insert);
+        assert_eq!(mc.position().col, 5);
+
+        press(&mut engine, &mut buffer, &mut mc, &[key("!", none)]);
+        assert_eq!(buffer.content.to_string(), "hello!");
+    }
+
+    #[test]
+    fn block_append_pads_short_lines_only_on_edit() {
+        let mut buffer = Buffer::new("long line\nab\nlong line", "t");
+        let mut mc = MultiCursor::new();
+        let mut engine = KeyEngine::default();
+        let none = Modifiers::empty();
+
+        // A block over columns 4..=5 of all three lines, past the end of the short one.
+        press(&mut engine, &mut buffer, &mut mc, &[key("l", none), key("l", none), key("l", none), key("l", none)]);
+        press(&mut engine, &mut buffer, &mut mc, &[key("v", Modifiers::ALT), key("l", none), key("j", none), key("j", none)]);
+        assert_eq!(engine.mode, EditorMode::VisualBlock);
+        assert_eq!(mc.primary().block(), (0..3, 4..6));
+
+        press(&mut engine, &mut buffer, &mut mc, &[key("A", Modifiers::SHIFT)]);
+        assert_eq!(engine.mode, EditorMode::Insert);
+        assert_eq!(mc.all_cursors().len(), 3);
+        assert_eq!(mc.all_cursors()[1].virtual_col(), 6);
+        assert_eq!(buffer.content.to_string(), "long line\nab\nlong line"); // Nothing padded yet.
+
+        press(&mut engine, &mut buffer, &mut mc, &[key("|", Modifiers::SHIFT)]);
+        assert_eq!(buffer.content.to_string(), "long l|ine\nab    |\nlong l|ine");
+    }
+
+    #[test]
+    fn block_append_at_column_ten_pads_the_short_line_up_to_it() {
+        let mut buffer = Buffer::new("abc\nabcdefghijklmno\n", "t");
+        let mut mc = MultiCursor::new();
+        let mut engine = KeyEngine::default();
+
+        // From column 9 of the long line up into the short one, appending at column 10.
+        press(&mut engine, &mut buffer, &mut mc, &typed("j9l"));
+        press(&mut engine, &mut buffer, &mut mc, &[key("v", Modifiers::ALT), key("k", Modifiers::empty())]);
+        assert_eq!(mc.primary().block(), (0..2, 9..10));
+
+        press(&mut engine, &mut buffer, &mut mc, &[key("A", Modifiers::SHIFT)]);
+        press(&mut engine, &mut buffer, &mut mc, &typed("XY"));
+        assert_eq!(buffer.content.to_string(), "abc       XY\nabcdefghijXYklmno\n");
+    }
+
+    #[test]
+    fn block_virtual_space_is_dropped_without_edit() {
+        let mut buffer = Buffer::new("long line\nab", "t");
+        let mut mc = MultiCursor::new();
+        let mut engine = KeyEngine::default();
+        let none = Modifiers::empty();
+
+        press(&mut engine, &mut buffer, &mut mc, &[key("$", Modifiers::SHIFT), key("v", Modifiers::ALT), key("j", none)]);
+        assert_eq!(mc.primary().virtual_col(), 8);
+        assert_eq!(mc.position().col, 1);
+
+        press(&mut engine, &mut buffer, &mut mc, &[KeyEvent::Esc]);
+        assert_eq!(mc.primary().virtual_col(), 1);
+        assert!(!buffer.modified);
+    }
+
+    #[test]
+    fn block_insert_skips_short_lines() {
+        let mut buffer = Buffer::new("long line\nab\nlong line", "t");
+        let mut mc = MultiCursor::new();
+        let mut engine = KeyEngine::default();
+        let none = Modifiers::empty();
+
+        press(&mut engine, &mut buffer, &mut mc, &[key("l", none), key("l", none), key("l", none), key("l", none)]);
+        press(&mut engine, &mut buffer, &mut mc, &[key("v", Modifiers::ALT), key("j", none), key("j", none), key("I", Modifiers::SHIFT)]);
+        press(&mut engine, &mut buffer, &mut mc, &[key("#", Modifiers::SHIFT)]);
+        assert_eq!(buffer.content.to_string(), "long# line\nab\nlong# line");
+    }
+
+    #[test]
+    fn block_d_and_c_take_the_block_out_of_every_line() {
+        let mut engine = KeyEngine::default();
+        let mut buffer = Buffer::new("long line\nab\nlong line", "t");
+        let mut mc = MultiCursor::new();
+        let mut registers = Registers::new();
+        let none = Modifiers::empty();
+
+        // Columns 1..=4, which the short line only partly reaches.
+        press_with(&mut engine, &mut buffer, &mut mc, &mut registers, &[key("l", none), key("v", Modifiers::ALT)]);
+        press_with(&mut engine, &mut buffer, &mut mc, &mut registers, &typed("lllj"));
+        press_with(&mut engine, &mut buffer, &mut mc, &mut registers, &typed("d"));
+        assert_eq!(engine.mode, EditorMode::Normal);
+        assert_eq!(buffer.content.to_string(), "lline\na\nlong line");
+        assert_eq!(registers.get(UNNAMED), Some(("ong \nb", RegisterKind::Charwise)));
+        assert_eq!((mc.all_cursors().len(), mc.position().line, mc.position().col), (1, 0, 1));
+
+        // `c` goes on to type on every line the block reached.
+        press_with(&mut engine, &mut buffer, &mut mc, &mut registers, &[key("v", Modifiers::ALT)]);
+        press_with(&mut engine, &mut buffer, &mut mc, &mut registers, &typed("ljjc"));
+        assert_eq!(engine.mode, EditorMode::Insert);
+        press_with(&mut engine, &mut buffer, &mut mc, &mut registers, &typed("#"));
+        assert_eq!(buffer.content.to_string(), "l#ne\na\nl#g line");
+    }
+
+    #[test]
+    fn command_line_is_submitted_on_enter() {
+        let mut engine = KeyEngine::default();
+        let none = Modifiers::empty();
+
+        engine.handle_key(key(":", Modifiers::SHIFT));
+        assert_eq!(engine.mode, EditorMode::Command);
+
+        for c in ["s", "e", "t", "x"] {
+            assert!(engine.handle_key(key(c, none)).is_none());
+        }
+        engine.handle_key(KeyEvent::Backspace);
+        assert_eq!(engine.command_line, "set");
+
+        let action = engine.handle_key(KeyEvent::Enter);
+        assert!(matches!(action, Some(EngineAction::Ex(line)) if line == "set"));
+        assert_eq!(engine.mode, EditorMode::Normal);
+        assert!(engine.command_line.is_empty());
+
+        // Backspace on an empty command line cancels it.
+        engine.handle_key(key(":", Modifiers::SHIFT));
+        engine.handle_key(KeyEvent::Backspace);
+        assert_eq!(engine.mode, EditorMode::Normal);
+    }
+
+    #[test]
+    fn enter_and_esc_go_back_to_the_mode_the_command_line_came_from() {
+        let mut engine = KeyEngine::default();
+        let mut buffer = Buffer::new("ab", "t");
+        let mut mc = MultiCursor::new();
+
+        // In Insert mode Enter is still a line break.
+        press(&mut engine, &mut buffer, &mut mc, &[key("i", Modifiers::empty()), KeyEvent::Enter]);
+        assert_eq!(buffer.content.to_string(), "\nab");
+
+        // `<C-o>:` runs a single command line from Insert mode.
+        for end in [KeyEvent::Enter, KeyEvent::Esc] {
+            engine.handle_key(key("o", Modifiers::CTRL));
+            engine.handle_key(key(":", Modifiers::SHIFT));
+            assert_eq!(engine.mode, EditorMode::Command);
+            let submitted = matches!(end, KeyEvent::Enter);
+            let action = engine.handle_key(end);
+            assert_eq!(matches!(action, Some(EngineAction::Ex(_))), submitted);
+            assert_eq!(engine.mode, EditorMode::Insert);
+        }
+
+        // Only that once.
+        press(&mut engine, &mut buffer, &mut mc, &[KeyEvent::Esc]);
+        engine.handle_key(key(":", Modifiers::SHIFT));
+        engine.handle_key(KeyEvent::Enter);
+        assert_eq!(engine.mode, EditorMode::Normal);
+    }
+
+    #[test]
+    fn visual_i_inserts_before_selection() {
+        let mut buffer = Buffer::new("hello world", "t");
+        let mut mc = MultiCursor::new();
+        let mut engine = KeyEngine::default();
+        let none = Modifiers::empty();
+
+        // Select backwards so the active end is *before* the anchor.
+        press(&mut engine, &mut buffer, &mut mc, &[key("l", none), key("l", none), key("l", none)]);
+        press(&mut engine, &mut buffer, &mut mc, &[key("v", none), key("h", none), key("h", none)]);
+        let (start, _) = mc.primary().get_selection_range();
+        assert_eq!(start.col, 1);
+
+        press(&mut engine, &mut buffer, &mut mc, &[key("I", Modifiers::SHIFT)]);
+        assert_eq!(engine.mode, EditorMode::Insert);
+        assert!(!mc.primary().has_selection());
+        assert_eq!(mc.position(), start);
+    }
+
+    #[test]
+    fn count_jumps_to_line_first_non_blank() {
+        let mut buffer = Buffer::new("one\n  two\n    three\nfour\n", "t");
+        let mut mc = MultiCursor::new();
+        let mut engine = KeyEngine::default();
+
+        press(&mut engine, &mut buffer, &mut mc, &[key("3", Modifiers::empty()), key("G", Modifiers::SHIFT)]);
+        assert_eq!(mc.position(), TextPosition::new(2, 4, 14));
+        assert!(!engine.has_pending_count());
+
+        // Without a count `G` goes to the last line, past the end counts too.
+        press(&mut engine, &mut buffer, &mut mc, &[key("G", Modifiers::SHIFT)]);
+        assert_eq!(mc.position().line, 3);
+        press(&mut engine, &mut buffer, &mut mc, &[key("2", Modifiers::empty()), key("0", Modifiers::empty()), key("g", Modifiers::empty()), key("g", Modifiers::empty())]);
+        assert_eq!(mc.position().line, 3);
+
+        press(&mut engine, &mut buffer, &mut mc, &[key("2", Modifiers::empty()), key("g", Modifiers::empty()), key("g", Modifiers::empty())]);
+        assert_eq!(mc.position(), TextPosition::new(1, 2, 6));
+    }
+
+    #[test]
+    fn zero_gg_is_plain_gg() {
+        let mut buffer = Buffer::new("  one\ntwo\nthree", "t");
+        let mut mc = MultiCursor::new();
+        let mut engine = KeyEngine::default();
+
+        press(&mut engine, &mut buffer, &mut mc, &[key("G", Modifiers::SHIFT)]);
+        assert_eq!(mc.position().line, 2);
+
+        press(&mut engine, &mut buffer, &mut mc, &[key("0", Modifiers::empty()), key("g", Modifiers::empty()), key("g", Modifiers::empty())]);
+        assert_eq!(mc.position(), TextPosition::new(0, 2, 2));
+    }
+
+    #[test]
+    fn caret_goes_to_the_first_non_blank() {
+        let mut buffer = Buffer::new("    indented line\n   \n", "t");
+        let mut mc = MultiCursor::new();
+        let mut engine = KeyEngine::default();
+        let caret = || key("^", Modifiers::SHIFT);
+
+        press(&mut engine, &mut buffer, &mut mc, &[key("$", Modifiers::SHIFT), caret()]);
+        assert_eq!(mc.position(), TextPosition::new(0, 4, 4));
+        // Up and down keep the column `^` went to.
+        press(&mut engine, &mut buffer, &mut mc, &typed("jk"));
+        assert_eq!(mc.position().col, 4);
+
+        // `d^` deletes back to it.
+        press(&mut engine, &mut buffer, &mut mc, &typed("3ld"));
+        press(&mut engine, &mut buffer, &mut mc, &[caret()]);
+        assert_eq!(buffer.content.to_string(), "    ented line\n   \n");
+
+        // A blank line has none, `^` stops on its last character.
+        press(&mut engine, &mut buffer, &mut mc, &[key("j", Modifiers::empty()), key("0", Modifiers::empty()), caret()]);
+        assert_eq!(mc.position().col, 2);
+    }
+
+    #[test]
+    fn bar_goes_to_a_screen_column() {
+        let mut buffer = Buffer::new("0123456789\nab\n\tx\n", "t");
+        let mut mc = MultiCursor::new();
+        let mut engine = KeyEngine::default();
+        let bar = || key("|", Modifiers::SHIFT);
+
+        press(&mut engine, &mut buffer, &mut mc, &[typed("5"), vec![bar()]].concat());
+        assert_eq!(mc.position().col, 4);
+        press(&mut engine, &mut buffer, &mut mc, &[bar()]);
+        assert_eq!(mc.position().col, 0);
+
+        // A short line stops on its last character.
+        press(&mut engine, &mut buffer, &mut mc, &[typed("j5"), vec![bar()]].concat());
+        assert_eq!(mc.position(), TextPosition::new(1, 1, 12));
+
+        // Columns count the tab's width.
+        press(&mut engine, &mut buffer, &mut mc, &[typed("j3"), vec![bar()]].concat());
+        assert_eq!(mc.position().col, 0);
+        press(&mut engine, &mut buffer, &mut mc, &[typed("5"), vec![bar()]].concat());
+        assert_eq!(mc.position().col, 1);
+
+        // `d|` deletes back to the column.
+        press(&mut engine, &mut buffer, &mut mc, &[typed("gg$3d"), vec![bar()]].concat());
+        assert_eq!(buffer.content.line(0).to_string(), "019\n");
+    }
+
+    #[test]
+    fn gg_and_g_work_on_empty_buffers_and_set_the_column_for_j_and_k() {
+        let mut buffer = Buffer::new("", "t");
+        let mut mc = MultiCursor::new();
+        let mut engine = KeyEngine::default();
+
+        press(&mut engine, &mut buffer, &mut mc, &[key("G", Modifiers::SHIFT)]);
+        press(&mut engine, &mut buffer, &mut mc, &typed("gg"));
+        assert_eq!(mc.position(), TextPosition::new(0, 0, 0));
+
+        let mut buffer = Buffer::new("  one\ntwo three\n  four\n", "t");
+        press(&mut engine, &mut buffer, &mut mc, &typed("j$gg"));
+        assert_eq!(mc.position(), TextPosition::new(0, 2, 2));
+        press(&mut engine, &mut buffer, &mut mc, &typed("j"));
+        assert_eq!(mc.position().col, 2);
+
+        press(&mut engine, &mut buffer, &mut mc, &typed("$"));
+        press(&mut engine, &mut buffer, &mut mc, &[key("G", Modifiers::SHIFT)]);
+        assert_eq!(mc.position(), TextPosition::new(2, 2, 18));
+        press(&mut engine, &mut buffer, &mut mc, &typed("k"));
+        assert_eq!(mc.position().col, 2);
+    }
+
+    #[test]
+    fn count_repeats_motions() {
+        let mut buffer = Buffer::new("a b c d e", "t");
+        let mut mc = MultiCursor::new();
+        let mut engine = KeyEngine::default();
+
+        press(&mut engine, &mut buffer, &mut mc, &[key("3", Modifiers::empty()), key("w", Modifiers::empty())]);
+        assert_eq!(mc.position().col, 6);
+        press(&mut engine, &mut buffer, &mut mc, &[key("l", Modifiers::empty())]);
+        assert_eq!(mc.position().col, 7);
+    }
+
+    #[test]
+    fn counted_motions_stop_where_the_motion_does() {
+        let mut buffer = Buffer::new("one\ntwo\nthree\nfour\n", "t");
+        let mut mc = MultiCursor::new();
+        let mut engine = KeyEngine::default();
+
+        press(&mut engine, &mut buffer, &mut mc, &typed("2j"));
+        assert_eq!(mc.position(), TextPosition::new(2, 0, 8));
+
+        // On the last character, with the count left over.
+        press(&mut engine, &mut buffer, &mut mc, &typed("10l"));
+        assert_eq!(mc.position(), TextPosition::new(2, 4, 12));
+        assert!(!engine.has_pending_count());
+        press(&mut engine, &mut buffer, &mut mc, &typed("100000h"));
+        assert_eq!(mc.position().col, 0);
+
+        // `0` alone is still the start of the line.
+        press(&mut engine, &mut buffer, &mut mc, &typed("ll0"));
+        assert_eq!(mc.position().col, 0);
+    }
+
+    #[test]
+    fn visual_paste_swaps_selection_into_register() {
+        let mut buffer = Buffer::new("alpha beta gamma", "t");
+        let mut mc = MultiCursor::new();
+        let mut engine = KeyEngine::default();
+        let mut registers = Registers::new();
+
+        press_with(&mut engine, &mut buffer, &mut mc, &mut registers, &typed("vlllly"));
+        assert_eq!(registers.unnamed(), "alpha");
+        assert_eq!(engine.mode, EditorMode::Normal);
+        assert_eq!(mc.position().col, 0);
+
+        // Pasting over "beta" puts it in the register instead.
+        press_with(&mut engine, &mut buffer, &mut mc, &mut registers, &typed("llllllvlllp"));
+        assert_eq!(buffer.content.to_string(), "alpha alpha gamma");
+        assert_eq!(registers.unnamed(), "beta");
+        assert_eq!(mc.position().col, 10);
+
+        press_with(&mut engine, &mut buffer, &mut mc, &mut registers, &typed("$p"));
+        assert_eq!(buffer.content.to_string(), "alpha alpha gammabeta");
+        assert_eq!(registers.unnamed(), "beta");
+        assert_eq!(mc.position().col, 20);
+    }
+
+    #[test]
+    fn normal_paste_ignores_word_selection() {
+        let mut buffer = Buffer::new("alpha beta", "t");
+        let mut mc = MultiCursor::new();
+        let mut engine = KeyEngine::default();
+        let mut registers = Registers::new();
+        registers.set_unnamed("X");
+
+        // `w` selects the next word, `p` still just pastes after the cursor.
+        press_with(&mut engine, &mut buffer, &mut mc, &mut registers, &typed("wp"));
+        assert_eq!(buffer.content.to_string(), "alpha betaX");
+        assert_eq!(registers.unnamed(), "X");
+    }
+
+    #[test]
+    fn typing_after_a_word_motion_inserts() {
+        let mut buffer = Buffer::new("alpha beta", "t");
+        let mut mc = MultiCursor::new();
+        let mut engine = KeyEngine::default();
+
+        press(&mut engine, &mut buffer, &mut mc, &typed("wix"));
+        assert_eq!(buffer.content.to_string(), "alpha betxa");
+    }
+
+    #[test]
+    fn multi_byte_characters_are_typed() {
+        let mut buffer = Buffer::new("", "t");
+        let mut mc = MultiCursor::new();
+        let mut engine = KeyEngine::default();
+
+        // Without a text payload the key itself is used.
+        let e_acute = KeyEvent::Key { key: Key::Character("é".into()), text: None, modifiers: Modifiers::empty() };
+        press(&mut engine, &mut buffer, &mut mc, &[key("i", Modifiers::empty()), e_acute, key("👍", Modifiers::empty())]);
+        assert_eq!(buffer.content.to_string(), "é👍");
+        assert_eq!(mc.position().col, 2);
+    }
+
+    const MAIN: &str = "int main() {\n    if (x) {\n        y();\n    }\n    return 0;\n}\n";
+
+    #[test]
+    fn di_brace_empties_a_multi_line_block() {
+        let mut buffer = Buffer::new(MAIN, "t");
+        let mut mc = MultiCursor::new();
+        let mut engine = KeyEngine::default();
+
+        press(&mut engine, &mut buffer, &mut mc, &typed("jjjjdi{"));
+        assert_eq!(buffer.content.to_string(), "int main() {\n}\n");
+        assert_eq!(mc.position().line, 1);
+
+        // Only the innermost block goes.
+        let mut buffer = Buffer::new(MAIN, "t");
+        let mut mc = MultiCursor::new();
+        let mut registers = Registers::new();
+        press_with(&mut engine, &mut buffer, &mut mc, &mut registers, &typed("jjdi}"));
+        assert_eq!(buffer.content.to_string(), "int main() {\n    if (x) {\n    }\n    return 0;\n}\n");
+        assert_eq!(registers.unnamed(), "        y();\n");
+    }
+
+    #[test]
+    fn da_brace_takes_the_braces_too() {
+        let mut buffer = Buffer::new(MAIN, "t");
+        let mut mc = MultiCursor::new();
+        let mut engine = KeyEngine::default();
+
+        press(&mut engine, &mut buffer, &mut mc, &typed("jjjjda{"));
+        assert_eq!(buffer.content.to_string(), "int main() \n");
+    }
+
+    #[test]
+    fn visual_i_brace_selects_the_block() {
+        let mut buffer = Buffer::new(MAIN, "t");
+        let mut mc = MultiCursor::new();
+        let mut engine = KeyEngine::default();
+
+        press(&mut engine, &mut buffer, &mut mc, &typed("jjvi{"));
+        assert_eq!(engine.mode, EditorMode::Visual);
+        assert_eq!(buffer.selection_text(mc.primary()), "        y();\n");
+    }
+
+    #[test]
+    fn daw_takes_the_space_after_a_word_or_else_before_it() {
+        let run = |content: &str, keys: &str| {
+            let mut buffer = Buffer::new(content, "t");
+            let mut mc = MultiCursor::new();
+            press(&mut KeyEngine::default(), &mut buffer, &mut mc, &typed(keys));
+            (buffer.content.to_string(), mc.position().col)
+        };
+
+        assert_eq!(run("foo bar baz\n", "lllldaw"), ("foo baz\n".to_string(), 4));
+        assert_eq!(run("foo bar baz\n", "$daw"), ("foo bar\n".to_string(), 6));
+        assert_eq!(run("foo bar baz\n", "lllldiw"), ("foo  baz\n".to_string(), 4));
+        // On whitespace, the word after it goes too. Indentation stays.
+        assert_eq!(run("foo bar baz\n", "llldaw"), ("foo baz\n".to_string(), 3));
+        assert_eq!(run("    foo\n", "$daw"), ("    \n".to_string(), 3));
+        // `W` words run over punctuation.
+        assert_eq!(run("a.b c\n", "daw"), (".b c\n".to_string(), 0));
+        let mut buffer = Buffer::new("a.b c\n", "t");
+        let mut mc = MultiCursor::new();
+        let keys = [typed("da"), vec![key("W", Modifiers::SHIFT)]].concat();
+        press(&mut KeyEngine::default(), &mut buffer, &mut mc, &keys);
+        assert_eq!(buffer.content.to_string(), "c\n");
+
+        let mut buffer = Buffer::new("foo bar baz\n", "t");
+        let mut mc = MultiCursor::new();
+        press(&mut KeyEngine::default(), &mut buffer, &mut mc, &typed("llllvaw"));
+        assert_eq!(buffer.selection_text(mc.primary()), "bar ");
+    }
+
+    #[test]
+    fn iw_on_punctuation_takes_the_whole_operator() {
+        let run = |content: &str, keys: &str| {
+            let mut buffer = Buffer::new(content, "t");
+            let mut mc = MultiCursor::new();
+            press(&mut KeyEngine::default(), &mut buffer, &mut mc, &typed(keys));
+            (buffer.content.to_string(), mc.position().col)
+        };
+
+        assert_eq!(run("a -> b\n", "lldiw"), ("a  b\n".to_string(), 2));
+        assert_eq!(run("a -> b\n", "llldiw"), ("a  b\n".to_string(), 2));
+        assert_eq!(run("std::io\n", "llldiw"), ("stdio\n".to_string(), 3));
+        assert_eq!(run("std::io\n", "lllldiw"), ("stdio\n".to_string(), 3));
+        // Only up to where the letters start again.
+        assert_eq!(run("x=>y(z)\n", "ldiw"), ("xy(z)\n".to_string(), 1));
+
+        let mut buffer = Buffer::new("foo::bar\n", "t");
+        let mut mc = MultiCursor::new();
+        press(&mut KeyEngine::default(), &mut buffer, &mut mc, &typed("$hhhhviw"));
+        assert_eq!(buffer.selection_text(mc.primary()), "::");
+    }
+
+    #[test]
+    fn d_still_deletes_when_no_text_object_follows() {
+        let mut buffer = Buffer::new("abc", "t");
+        let mut mc = MultiCursor::new();
+        let mut engine = KeyEngine::default();
+
+        // `di` could still become `di{`, until `X` shows it was `d` then `i`.
+        press(&mut engine, &mut buffer, &mut mc, &typed("diX"));
+        assert_eq!(buffer.content.to_string(), "Xbc");
+        assert_eq!(engine.mode, EditorMode::Insert);
+    }
+
+    #[test]
+    fn visual_line_toggles_keep_the_selection() {
+        let mut buffer = Buffer::new("one\ntwo\nthree\n", "t");
+        let mut mc = MultiCursor::new();
+        let mut engine = KeyEngine::default();
+
+        press(&mut engine, &mut buffer, &mut mc, &typed("lvj"));
+        assert_eq!(buffer.selection_text(mc.primary()), "ne\ntw");
+
+        press(&mut engine, &mut buffer, &mut mc, &[key("V", Modifiers::SHIFT)]);
+        assert_eq!(engine.mode, EditorMode::VisualLine);
+        assert_eq!(buffer.selection_text(mc.primary()), "one\ntwo\n");
+
+        // Back to charwise, from the same anchor.
+        press(&mut engine, &mut buffer, &mut mc, &typed("v"));
+        assert_eq!(engine.mode, EditorMode::Visual);
+        assert_eq!(buffer.selection_text(mc.primary()), "ne\ntw");
+
+        press(&mut engine, &mut buffer, &mut mc, &[key("V", Modifiers::SHIFT), key("d", Modifiers::empty())]);
+        assert_eq!(buffer.content.to_string(), "three\n");
+        assert_eq!(engine.mode, EditorMode::Normal);
+        assert!(!mc.primary().has_selection());
+    }
+
+    const LINES: &str = "one\ntwo\nthree\nfour\n";
+
+    #[test]
+    fn dj_and_dk_delete_whole_lines() {
+        let mut engine = KeyEngine::default();
+
+        let mut buffer = Buffer::new(LINES, "t");
+        let mut mc = MultiCursor::new();
+        let mut registers = Registers::new();
+        press_with(&mut engine, &mut buffer, &mut mc, &mut registers, &typed("jldj"));
+        assert_eq!(buffer.content.to_string(), "one\nfour\n");
+        assert_eq!(registers.unnamed(), "two\nthree\n");
+        assert_eq!(mc.position().line, 1);
+
+        let mut buffer = Buffer::new(LINES, "t");
+        let mut mc = MultiCursor::new();
+        press(&mut engine, &mut buffer, &mut mc, &typed("jjldk"));
+        assert_eq!(buffer.content.to_string(), "one\nfour\n");
+
+        // Nothing above the first line.
+        let mut buffer = Buffer::new(LINES, "t");
+        let mut mc = MultiCursor::new();
+        press(&mut engine, &mut buffer, &mut mc, &typed("dk"));
+        assert_eq!(buffer.content.to_string(), LINES);
+    }
+
+    #[test]
+    fn dd_deletes_lines_that_paste_back_as_lines() {
+        let mut engine = KeyEngine::default();
+        let mut buffer = Buffer::new("one\n  two\nthree\nfour", "t");
+        let mut mc = MultiCursor::new();
+        let mut registers = Registers::new();
+
+        press_with(&mut engine, &mut buffer, &mut mc, &mut registers, &typed("ldd"));
+        assert_eq!(buffer.content.to_string(), "  two\nthree\nfour");
+        assert_eq!((registers.unnamed(), registers.is_linewise()), ("one\n", true));
+        assert_eq!(mc.position().col, 2);
+
+        // Below the cursor's line, not after the cursor.
+        press_with(&mut engine, &mut buffer, &mut mc, &mut registers, &typed("p"));
+        assert_eq!(buffer.content.to_string(), "  two\none\nthree\nfour");
+        assert_eq!(mc.position().line, 1);
+
+        // The last line takes the line break before it, and the cursor goes up.
+        press_with(&mut engine, &mut buffer, &mut mc, &mut registers, &typed("j2dd"));
+        assert_eq!(buffer.content.to_string(), "  two\none");
+        assert_eq!(registers.unnamed(), "three\nfour\n");
+        assert_eq!(mc.position().line, 1);
+        press_with(&mut engine, &mut buffer, &mut mc, &mut registers, &typed("p"));
+        assert_eq!(buffer.content.to_string(), "  two\none\nthree\nfour");
+        assert_eq!(mc.position().line, 2);
+
+        // Other deletes aren't lines.
+        press_with(&mut engine, &mut buffer, &mut mc, &mut registers, &typed("x"));
+        assert!(!registers.is_linewise());
+    }
+
+    #[test]
+    fn yanks_fill_the_unnamed_register() {
+        let mut engine = KeyEngine::default();
+        let mut buffer = Buffer::new("foo(bar)\nbaz\n", "t");
+        let mut mc = MultiCursor::new();
+        let mut registers = Registers::new();
+        assert_eq!(registers.get(UNNAMED), None);
+
+        press_with(&mut engine, &mut buffer, &mut mc, &mut registers, &typed("yw"));
+        assert_eq!(registers.get(UNNAMED), Some(("foo", RegisterKind::Charwise)));
+        assert_eq!(mc.position().col, 0);
+
+        press_with(&mut engine, &mut buffer, &mut mc, &mut registers, &typed("lvly"));
+        assert_eq!(registers.get(UNNAMED), Some(("oo", RegisterKind::Charwise)));
+
+        press_with(&mut engine, &mut buffer, &mut mc, &mut registers, &typed("2yy"));
+        assert_eq!(registers.get(UNNAMED), Some(("foo(bar)\nbaz\n", RegisterKind::Linewise)));
+        assert_eq!(buffer.content.to_string(), "foo(bar)\nbaz\n");
+    }
+
+    #[test]
+    fn linewise_motions_and_visual_lines_yank_whole_lines() {
+        let mut engine = KeyEngine::default();
+        let mut buffer = Buffer::new("one\ntwo\nthree", "t");
+        let mut mc = MultiCursor::new();
+        let mut registers = Registers::new();
+
+        press_with(&mut engine, &mut buffer, &mut mc, &mut registers, &typed("lyjp"));
+        assert_eq!(registers.get(UNNAMED), Some(("one\ntwo\n", RegisterKind::Linewise)));
+        assert_eq!(buffer.content.to_string(), "one\none\ntwo\ntwo\nthree");
+
+        press_with(&mut engine, &mut buffer, &mut mc, &mut registers, &[key("G", Modifiers::SHIFT), key("V", Modifiers::SHIFT)]);
+        press_with(&mut engine, &mut buffer, &mut mc, &mut registers, &typed("yp"));
+        assert_eq!(registers.get(UNNAMED), Some(("three\n", RegisterKind::Linewise)));
+        assert_eq!(buffer.content.to_string(), "one\none\ntwo\ntwo\nthree\nthree");
+
+        // Deleting the last lines takes the line break before them, but not into the register.
+        press_with(&mut engine, &mut buffer, &mut mc, &mut registers, &typed("kdj"));
+        assert_eq!(registers.get(UNNAMED), Some(("three\nthree\n", RegisterKind::Linewise)));
+        assert_eq!(buffer.content.to_string(), "one\none\ntwo\ntwo");
+    }
+
+    #[test]
+    fn shift_p_pastes_before_the_cursor_or_above_its_line() {
+        let mut engine = KeyEngine::default();
+        let mut buffer = Buffer::new("ab\n  cd\n", "t");
+        let mut mc = MultiCursor::new();
+        let mut registers = Registers::new();
+        let shift_p = || key("P", Modifiers::SHIFT);
+
+        // Charwise: at each cursor, which ends up on the last pasted character.
+        registers.set_unnamed("xy");
+        mc.add_cursor(TextPosition::new(1, 2, 5), &buffer);
+        press_with(&mut engine, &mut buffer, &mut mc, &mut registers, &[key("l", Modifiers::empty()), shift_p()]);
+        assert_eq!(buffer.content.to_string(), "axyb\n  cxyd\n");
+        assert_eq!(mc.snapshot().iter().map(|cursor| cursor.active.col).collect::<Vec<_>>(), [2, 4]);
+
+        // Linewise: above each cursor's line, the cursors on the first pasted one.
+        registers.set_unnamed_lines("  new\n");
+        press_with(&mut engine, &mut buffer, &mut mc, &mut registers, &[shift_p()]);
+        assert_eq!(buffer.content.to_string(), "  new\naxyb\n  new\n  cxyd\n");
+        assert_eq!(mc.snapshot().iter().map(|cursor| (cursor.active.line, cursor.active.col)).collect::<Vec<_>>(), [(0, 2), (2, 2)]);
+
+        press_with(&mut engine, &mut buffer, &mut mc, &mut registers, &typed("p"));
+        assert_eq!(buffer.content.to_string(), "  new\n  new\naxyb\n  new\n  new\n  cxyd\n");
+        assert_eq!(mc.position().line, 1);
+    }
+
+    #[test]
+    fn line_jumps_after_d_are_linewise() {
+        let mut engine = KeyEngine::default();
+
+        let mut buffer = Buffer::new(LINES, "t");
+        let mut mc = MultiCursor::new();
+        press(&mut engine, &mut buffer, &mut mc, &[key("j", Modifiers::empty()), key("d", Modifiers::empty()), key("G", Modifiers::SHIFT)]);
+        assert_eq!(buffer.content.to_string(), "one\n");
+
+        let mut buffer = Buffer::new(LINES, "t");
+        let mut mc = MultiCursor::new();
+        press(&mut engine, &mut buffer, &mut mc, &typed("jjldgg"));
+        assert_eq!(buffer.content.to_string(), "four\n");
+
+        // The last line takes the line break before it.
+        let mut buffer = Buffer::new("one\ntwo", "t");
+        let mut mc = MultiCursor::new();
+        press(&mut engine, &mut buffer, &mut mc, &typed("jdk"));
+        assert_eq!(buffer.content.to_string(), "");
+    }
+
+    #[test]
+    fn charwise_motions_after_d() {
+        let mut engine = KeyEngine::default();
+
+        // `$` takes the last character along, `h` doesn't take the one under the cursor.
+        let mut buffer = Buffer::new("one two\n", "t");
+        let mut mc = MultiCursor::new();
+        press(&mut engine, &mut buffer, &mut mc, &[key("l", Modifiers::empty()), key("d", Modifiers::empty()), key("$", Modifiers::SHIFT)]);
+        assert_eq!(buffer.content.to_string(), "o\n");
+
+        let mut buffer = Buffer::new("one two\n", "t");
+        let mut mc = MultiCursor::new();
+        press(&mut engine, &mut buffer, &mut mc, &typed("lldh"));
+        assert_eq!(buffer.content.to_string(), "oe two\n");
+    }
+
+    #[test]
+    fn single_character_edits_take_a_count() {
+        let mut engine = KeyEngine::default();
+        let edit = |engine: &mut KeyEngine, keys: &[KeyEvent]| {
+            // A second cursor on the next line gets the same edit.
+            let mut buffer = Buffer::new("abcdef\nghijkl\n", "t");
+            let mut mc = MultiCursor::new();
+            press(engine, &mut buffer, &mut mc, &typed("ll"));
+            mc.add_cursor(TextPosition::new(1, 2, 9), &buffer);
+            press(engine, &mut buffer, &mut mc, keys);
+            (buffer.content.to_string(), mc.position().col)
+        };
+
+        assert_eq!(edit(&mut engine, &typed("3x")), ("abf\nghl\n".to_string(), 2));
+        assert_eq!(edit(&mut engine, &[typed("3").as_slice(), &[key("X", Modifiers::SHIFT)]].concat()), ("cdef\nijkl\n".to_string(), 0));
+        assert_eq!(edit(&mut engine, &typed("3rz")), ("abzzzf\nghzzzl\n".to_string(), 4));
+        assert_eq!(edit(&mut engine, &typed("3~")), ("abCDEf\nghIJKl\n".to_string(), 5));
+
+        // The count stops at the end of the line, except for `r` which then does nothing.
+        assert_eq!(edit(&mut engine, &typed("9x")), ("ab\ngh\n".to_string(), 1));
+        assert_eq!(edit(&mut engine, &typed("9~")), ("abCDEF\nghIJKL\n".to_string(), 5));
+        assert_eq!(edit(&mut engine, &typed("9rz")), ("abcdef\nghijkl\n".to_string(), 2));
+    }
+
+    #[test]
+    fn counted_x_and_shift_x_stay_on_the_line_and_fill_the_register() {
+        let mut engine = KeyEngine::default();
+        let mut buffer = Buffer::new("hello\nworld\n", "t");
+        let mut mc = MultiCursor::new();
+        let mut registers = Registers::new();
+
+        press_with(&mut engine, &mut buffer, &mut mc, &mut registers, &typed("3x"));
+        assert_eq!(buffer.content.to_string(), "lo\nworld\n");
+        assert_eq!(registers.unnamed(), "hel");
+        press_with(&mut engine, &mut buffer, &mut mc, &mut registers, &typed("p"));
+        assert_eq!(buffer.content.to_string(), "lhelo\nworld\n");
+
+        // Neither goes past its end of the line.
+        press_with(&mut engine, &mut buffer, &mut mc, &mut registers, &typed("j$h9"));
+        press_with(&mut engine, &mut buffer, &mut mc, &mut registers, &[key("X", Modifiers::SHIFT)]);
+        assert_eq!(buffer.content.to_string(), "lhelo\nld\n");
+        assert_eq!(registers.unnamed(), "wor");
+        press_with(&mut engine, &mut buffer, &mut mc, &mut registers, &typed("9x"));
+        assert_eq!(buffer.content.to_string(), "lhelo\n\n");
+        assert_eq!(registers.unnamed(), "ld");
+    }
+
+    #[test]
+    fn r_replaces_whole_graphemes_and_stays_in_normal_mode() {
+        let mut engine = KeyEngine::default();
+        let mut buffer = Buffer::new("e\u{301}👍b\n\nc\n", "t");
+        let mut mc = MultiCursor::new();
+
+        press(&mut engine, &mut buffer, &mut mc, &typed("rx"));
+        assert_eq!(engine.mode, EditorMode::Normal);
+        press(&mut engine, &mut buffer, &mut mc, &typed("lry"));
+        assert_eq!(buffer.content.to_string(), "xyb\n\nc\n");
+
+        // Nothing to replace on an empty line.
+        press(&mut engine, &mut buffer, &mut mc, &typed("jrz"));
+        assert_eq!(buffer.content.to_string(), "xyb\n\nc\n");
+
+        // `<Esc>` after `r` replaces nothing, the next key is a command again.
+        press(&mut engine, &mut buffer, &mut mc, &[key("j", Modifiers::empty()), key("r", Modifiers::empty()), KeyEvent::Esc]);
+        press(&mut engine, &mut buffer, &mut mc, &typed("x"));
+        assert_eq!(buffer.content.to_string(), "xyb\n\n\n");
+    }
+
+    #[test]
+    fn open_line_follows_autoindent() {
+        let mut engine = KeyEngine::default();
+        let mut buffer = Buffer::new("fn main() {\n    let x;\n}\n", "t");
+        let mut mc = MultiCursor::new();
+
+        press(&mut engine, &mut buffer, &mut mc, &typed("jlloy"));
+        assert_eq!(engine.mode, EditorMode::Insert);
+        assert_eq!(buffer.content.to_string(), "fn main() {\n    let x;\n    y\n}\n");
+        assert_eq!((mc.position().line, mc.position().col), (2, 5));
+
+        // `O` opens above, and without autoindent new lines start at column 0.
+        buffer.options.auto_indent = false;
+        press(&mut engine, &mut buffer, &mut mc, &[KeyEvent::Esc, key("O", Modifiers::SHIFT)]);
+        assert_eq!(buffer.content.to_string(), "fn main() {\n    let x;\n\n    y\n}\n");
+        assert_eq!((mc.position().line, mc.position().col), (2, 0));
+    }
+
+    #[test]
+    fn a_shift_a_and_shift_i_pick_where_insert_mode_starts() {
+        let run = |content: &str, keys: Vec<KeyEvent>| {
+            let mut engine = KeyEngine::default();
+            let mut buffer = Buffer::new(content, "t");
+            let mut mc = MultiCursor::new();
+            press(&mut engine, &mut buffer, &mut mc, &keys);
+            assert_eq!(engine.mode, EditorMode::Insert);
+            (buffer.content.to_string(), mc.position().col)
+        };
+        let shift = |c: &str| vec![key(c, Modifiers::SHIFT)];
+
+        assert_eq!(run("  abc\n", typed("llax")), ("  axbc\n".to_string(), 4));
+        // `a` on the last character goes past it.
+        assert_eq!(run("  abc\n", typed("$ax")), ("  abcx\n".to_string(), 6));
+        assert_eq!(run("  abc\n", [shift("A"), typed("x")].concat()), ("  abcx\n".to_string(), 6));
+        assert_eq!(run("  abc\n", [typed("$"), shift("I"), typed("x")].concat()), ("  xabc\n".to_string(), 3));
+        // A blank line has no first non-blank, `I` goes to its end.
+        assert_eq!(run("   \n", [shift("I"), typed("x")].concat()), ("   x\n".to_string(), 4));
+
+        // Empty lines, with or without a line break, leave nowhere to go but column 0.
+        for content in ["", "\n"] {
+            assert_eq!(run(content, typed("a")), (content.to_string(), 0));
+            assert_eq!(run(content, shift("A")), (content.to_string(), 0));
+            assert_eq!(run(content, shift("I")), (content.to_string(), 0));
+        }
+
+        // Every cursor moves.
+        let mut engine = KeyEngine::default();
+        let mut buffer = Buffer::new("ab\ncd\n", "t");
+        let mut mc = MultiCursor::new();
+        mc.add_cursor(TextPosition::new(1, 0, 3), &buffer);
+        press(&mut engine, &mut buffer, &mut mc, &[shift("A"), typed("!")].concat());
+        assert_eq!(buffer.content.to_string(), "ab!\ncd!\n");
+    }
+
+    #[test]
+    fn open_line_at_the_ends_of_the_buffer() {
+        let run = |content: &str, keys: Vec<KeyEvent>| {
+            let mut engine = KeyEngine::default();
+            let mut buffer = Buffer::new(content, "t");
+            let mut mc = MultiCursor::new();
+            press(&mut engine, &mut buffer, &mut mc, &keys);
+            assert_eq!(engine.mode, EditorMode::Insert);
+            (buffer.content.to_string(), mc.position())
+        };
+
+        // Below a last line without a line break, one gets added before the new line.
+        assert_eq!(run("abc", typed("lox")), ("abc\nx".to_string(), TextPosition::new(1, 1, 5)));
+        assert_eq!(run("abc\n", typed("ox")), ("abc\nx\n".to_string(), TextPosition::new(1, 1, 5)));
+        // Above the first line, right at the start of the buffer.
+        let keys = [vec![key("l", Modifiers::empty()), key("O", Modifiers::SHIFT)], typed("x")].concat();
+        assert_eq!(run("abc\n", keys.clone()), ("x\nabc\n".to_string(), TextPosition::new(0, 1, 1)));
+        assert_eq!(run("", keys), ("x\n".to_string(), TextPosition::new(0, 1, 1)));
+    }
+
+    #[test]
+    fn mode_hooks_see_every_switch() {
+        use std::cell::RefCell;
+        use EditorMode::*;
+
+        let mut engine = KeyEngine::default();
+        let mut buffer = Buffer::new("abc\n", "t");
+        let mut mc = MultiCursor::new();
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let log = seen.clone();
+        engine.on_mode_change(move |t| log.borrow_mut().push((t.from.clone(), t.to.clone())));
+
+        press(&mut engine, &mut buffer, &mut mc, &typed("ix"));
+        press(&mut engine, &mut buffer, &mut mc, &[KeyEvent::Esc, key("l", Modifiers::empty())]);
+        press(&mut engine, &mut buffer, &mut mc, &[typed("vl"), vec![key("V", Modifiers::SHIFT)], typed("v")].concat());
+        press(&mut engine, &mut buffer, &mut mc, &[KeyEvent::Esc]);
+        assert_eq!(
+            *seen.borrow(),
+            [(Normal, Insert), (Insert, Normal), (Normal, Visual), (Visual, VisualLine), (VisualLine, Visual), (Visual, Normal)]
+        );
+
+        // Clones share the hooks.
+        seen.borrow_mut().clear();
+        let mut clone = engine.clone();
+        press(&mut clone, &mut buffer, &mut mc, &typed(":"));
+        assert_eq!(*seen.borrow(), [(Normal, Command)]);
+    }
+
+    #[test]
+    fn gi_goes_back_to_where_insert_mode_was_left() {
+        let mut engine = KeyEngine::default();
+        let mut buffer = Buffer::new("one\ntwo\nthree\n", "t");
+        let mut mc = MultiCursor::new();
+
+        press(&mut engine, &mut buffer, &mut mc, &typed("ji!"));
+        press(&mut engine, &mut buffer, &mut mc, &[KeyEvent::Esc]);
+        press(&mut engine, &mut buffer, &mut mc, &typed("j0"));
+        assert_eq!(mc.position().line, 2);
+
+        press(&mut engine, &mut buffer, &mut mc, &typed("gi?"));
+        assert_eq!(engine.mode, EditorMode::Insert);
+        assert_eq!(buffer.content.to_string(), "one\n!?two\nthree\n");
+
+        // Clamped once the buffer has shrunk.
+        press(&mut engine, &mut buffer, &mut mc, &[KeyEvent::Esc]);
+        buffer.content = "on\n".into();
+        mc.primary_mut().move_to(TextPosition::new(0, 0, 0), MoveOpts { anchor: None, update_preferred_col: true }, &buffer);
+        press(&mut engine, &mut buffer, &mut mc, &typed("gi"));
+        assert_eq!((mc.position().line, mc.position().col), (1, 0));
+    }
+
+    #[test]
+    fn leaving_visual_mode_drops_the_selection() {
+        let mut engine = KeyEngine::default();
+        let mut buffer = Buffer::new("hello world\n", "t");
+        let mut mc = MultiCursor::new();
+
+        press(&mut engine, &mut buffer, &mut mc, &typed("vll"));
+        assert!(mc.primary().has_selection());
+        press(&mut engine, &mut buffer, &mut mc, &[KeyEvent::Esc]);
+        assert!(!mc.primary().has_selection());
+        assert_eq!(mc.position().col, 2);
+    }
+
+    #[test]
+    fn counts_before_and_after_an_operator_multiply() {
+        let mut engine = KeyEngine::default();
+        let words = "one two three four five six seven eight\n";
+
+        let mut delete = |keys: &str| {
+            let mut buffer = Buffer::new(words, "t");
+            let mut mc = MultiCursor::new();
+            press(&mut engine, &mut buffer, &mut mc, &typed(keys));
+            buffer.content.to_string()
+        };
+        assert_eq!(delete("2d3w"), delete("d6w"));
+        assert_ne!(delete("2d3w"), delete("d3w"));
+
+        // Six lines down from the first, seven lines in all.
+        let mut buffer = Buffer::new(&(0..9).map(|i| format!("{i}\n")).collect::<String>(), "t");
+        let mut mc = MultiCursor::new();
+        press(&mut engine, &mut buffer, &mut mc, &typed("3d2j"));
+        assert_eq!(buffer.content.to_string(), "7\n8\n");
+
+        // A `0` right after the operator is still the motion.
+        let mut buffer = Buffer::new("abc\n", "t");
+        let mut mc = MultiCursor::new();
+        press(&mut engine, &mut buffer, &mut mc, &typed("$d0"));
+        assert_eq!(buffer.content.to_string(), "c\n");
+    }
+
+    #[test]
+    fn percent_jumps_between_brackets() {
+        let mut engine = KeyEngine::default();
+        let mut buffer = Buffer::new("if f(a[0]) {\n    x\n}\n", "t");
+        let mut mc = MultiCursor::new();
+
+        // The first bracket after the cursor is the `(`, `%` bounces between it and its match.
+        press(&mut engine, &mut buffer, &mut mc, &typed("%"));
+        assert_eq!(mc.position().col, 9);
+        press(&mut engine, &mut buffer, &mut mc, &typed("%"));
+        assert_eq!(mc.position().col, 4);
+
+        // Across lines, and `d%` takes both brackets.
+        press(&mut engine, &mut buffer, &mut mc, &typed("$%"));
+        assert_eq!(mc.position().line, 2);
+        press(&mut engine, &mut buffer, &mut mc, &typed("d%"));
+        assert_eq!(buffer.content.to_string(), "if f(a[0]) \n");
+
+        // In Visual mode the selection goes along to the other bracket.
+        press(&mut engine, &mut buffer, &mut mc, &typed("0fav%"));
+        assert_eq!(engine.mode, EditorMode::Visual);
+        assert_eq!(buffer.selection_text(mc.primary()), "a[0]");
+    }
+
+    #[test]
+    fn motions_with_nowhere_to_go_fail() {
+        let mut buffer = Buffer::new("ab\ncd", "t");
+        let mut mc = MultiCursor::new();
+        let mut registers = Registers::new();
+        let mut run = |motion, buffer: &mut Buffer, mc: &mut MultiCursor| {
+            execute(Action::Move { motion, count: 1 }, buffer, mc, &EditorMode::Normal, &mut registers, MATCH_PAIRS)
+        };
+
+        assert!(!run(Motion::CharLeft, &mut buffer, &mut mc));
+        assert!(!run(Motion::CharUp, &mut buffer, &mut mc));
+        assert!(!run(Motion::MatchPair, &mut buffer, &mut mc));
+        // Staying put is all `0` is asked to do.
+        assert!(run(Motion::ToLineStart, &mut buffer, &mut mc));
+
+        assert!(run(Motion::CharDown, &mut buffer, &mut mc));
+        assert!(run(Motion::CharRight, &mut buffer, &mut mc));
+        assert!(!run(Motion::CharRight, &mut buffer, &mut mc));
+        assert!(!run(Motion::CharDown, &mut buffer, &mut mc));
+    }
+
+    #[test]
+    fn f_and_t_find_characters_on_the_line() {
+        let mut buffer = Buffer::new("a,b,c,d\nnext,\n", "t");
+        let mut mc = MultiCursor::new();
+        let mut engine = KeyEngine::default();
+        let shifted = |c: &str| key(c, Modifiers::SHIFT);
+
+        press(&mut engine, &mut buffer, &mut mc, &typed("f,"));
+        assert_eq!(mc.position().col, 1);
+        press(&mut engine, &mut buffer, &mut mc, &typed("2t,"));
+        assert_eq!(mc.position().col, 4);
+        press(&mut engine, &mut buffer, &mut mc, &[shifted("F"), key("a", Modifiers::empty())]);
+        assert_eq!(mc.position().col, 0);
+        press(&mut engine, &mut buffer, &mut mc, &[key("$", Modifiers::SHIFT), shifted("T"), key(",", Modifiers::empty())]);
+        assert_eq!(mc.position().col, 6);
+
+        // `;` finds the last one again, `,` the other way.
+        press(&mut engine, &mut buffer, &mut mc, &typed("0fb;"));
+        assert_eq!(mc.position().col, 2);
+        press(&mut engine, &mut buffer, &mut mc, &typed("f,;"));
+        assert_eq!(mc.position().col, 5);
+        press(&mut engine, &mut buffer, &mut mc, &typed(","));
+        assert_eq!(mc.position().col, 3);
+
+        // Not past the end of the line, and Esc gives up waiting for the character.
+        press(&mut engine, &mut buffer, &mut mc, &typed("fn"));
+        assert_eq!(mc.position().col, 3);
+        press(&mut engine, &mut buffer, &mut mc, &[key("f", Modifiers::empty()), KeyEvent::Esc, key("l", Modifiers::empty())]);
+        assert_eq!(mc.position().col, 4);
+        assert!(!execute(
+            Action::Move { motion: Motion::FindChar(CharSearch { ch: 'z', forward: true, till: false, repeat: false }), count: 1 },
+            &mut buffer,
+            &mut mc,
+            &EditorMode::Normal,
+            &mut Registers::new(),
+            MATCH_PAIRS,
+        ));
+
+        // In Visual mode the selection goes along.
+        press(&mut engine, &mut buffer, &mut mc, &typed("0vtc"));
+        assert_eq!(buffer.selection_text(mc.primary()), "a,b,");
+    }
+
+    #[test]
+    fn a_cursor_that_cant_be_added_still_marks_the_insert() {
+        let mut buffer = Buffer::new("ab", "t");
+        let mut mc = MultiCursor::new();
+        mc.move_to_line_end(&buffer, &EditorMode::Insert);
+
+        // Nowhere below or to the right, but the Insert position is kept like after any other action.
+        assert!(!execute(Action::AddCursor, &mut buffer, &mut mc, &EditorMode::Insert, &mut Registers::new(), MATCH_PAIRS));
+        assert_eq!(mc.all_cursors().len(), 1);
+        assert_eq!(buffer.last_insert, Some(mc.position()));
+    }
+
+    #[test]
+    fn semicolon_after_t_goes_on_to_the_next_character() {
+        let mut buffer = Buffer::new("a.b.c.d.e\n", "t");
+        let mut mc = MultiCursor::new();
+        let mut engine = KeyEngine::default();
+
+        // Before each `.` in turn, instead of staying before the first.
+        press(&mut engine, &mut buffer, &mut mc, &typed("t."));
+        assert_eq!(mc.position().col, 0);
+        press(&mut engine, &mut buffer, &mut mc, &typed(";"));
+        assert_eq!(mc.position().col, 2);
+        press(&mut engine, &mut buffer, &mut mc, &typed("2;"));
+        assert_eq!(mc.position().col, 6);
+        // And back, after them.
+        press(&mut engine, &mut buffer, &mut mc, &typed(",,"));
+        assert_eq!(mc.position().col, 2);
+
+        // `d;` deletes up to and including the next one.
+        press(&mut engine, &mut buffer, &mut mc, &typed("0f.d;"));
+        assert_eq!(buffer.content.to_string(), "ac.d.e\n");
+
+        // Nothing to repeat yet, nothing happens.
+        let mut engine = KeyEngine::default();
+        assert!(engine.handle_key(key(";", Modifiers::empty())).is_none());
+    }
+
+    #[test]
+    fn operators_take_finds_as_their_motion() {
+        let run = |keys: &str| {
+            let mut engine = KeyEngine::default();
+            let mut buffer = Buffer::new("a,b,c,d", "t");
+            let mut mc = MultiCursor::new();
+            let mut registers = Registers::new();
+            // Capitals are typed with Shift.
+            let keys: Vec<KeyEvent> = keys
+                .chars()
+                .map(|c| key(&c.to_string(), if c.is_uppercase() { Modifiers::SHIFT } else { Modifiers::empty() }))
+                .collect();
+            press_with(&mut engine, &mut buffer, &mut mc, &mut registers, &keys);
+            (buffer.content.to_string(), engine.mode, registers.unnamed().to_string())
+        };
+
+        // `f` takes the character it finds along, `t` stops before it.
+        assert_eq!(run("df,"), ("b,c,d".to_string(), EditorMode::Normal, "a,".to_string()));
+        assert_eq!(run("dt,"), (",b,c,d".to_string(), EditorMode::Normal, "a".to_string()));
+        assert_eq!(run("2df,"), ("c,d".to_string(), EditorMode::Normal, "a,b,".to_string()));
+        assert_eq!(run("cf,X"), ("Xb,c,d".to_string(), EditorMode::Insert, "a,".to_string()));
+        assert_eq!(run("yf,P"), ("a,a,b,c,d".to_string(), EditorMode::Normal, "a,".to_string()));
+        // Nothing found, nothing deleted.
+        assert_eq!(run("df;").0, "a,b,c,d");
+    }
+
+    #[test]
+    fn word_motions_after_d_delete_at_every_cursor() {
+        let mut engine = KeyEngine::default();
+        let run = |engine: &mut KeyEngine, keys: &str| {
+            let mut buffer = Buffer::new("one two three\n", "t");
+            let mut mc = MultiCursor::new();
+            press(engine, &mut buffer, &mut mc, &typed(keys));
+            (buffer.content.to_string(), mc.position().col)
+        };
+
+        // `e` takes the character it lands on, `b` leaves the one under the cursor.
+        assert_eq!(run(&mut engine, "lde"), ("o two three\n".to_string(), 1));
+        assert_eq!(run(&mut engine, "$db"), ("one two e\n".to_string(), 8));
+
+        // Each cursor deletes its own word, the later ones first so the earlier offsets hold.
+        let mut buffer = Buffer::new("one two\nfoo bar\n", "t");
+        let mut mc = MultiCursor::new();
+        mc.add_cursor(TextPosition::new(1, 0, 8), &buffer);
+        press(&mut engine, &mut buffer, &mut mc, &typed("de"));
+        assert_eq!(buffer.content.to_string(), " two\n bar\n");
+        assert_eq!(mc.all_cursors().iter().map(|cursor| cursor.position().offset).collect::<Vec<_>>(), [0, 5]);
     }
 }
@@ -1,9 +1,9 @@
 use std::collections::HashMap;
 
 use atlas_engine::{Message, EditorMode};
-use iced::keyboard::Key;
+use iced::keyboard::{key::Named, Key};
 
-use crate::{EngineAction, KeyEvent, Motion, Operator, Action};
+use crate::{EngineAction, KeyEvent, Motion, Operator, Action, TextObject};
 
 #[derive(Clone)]
 pub enum KeyAction {
@@ -18,6 +18,75 @@ pub enum KeyAction {
 pub struct Keymap {
     bindings: HashMap<(EditorMode, String), KeyAction>,
     multi_key_buffer: String,
+    pending_keys: Vec<KeyEvent>, // The keys behind `multi_key_buffer`.
+    // A binding that was typed but is also the start of longer ones, like `d` and `di{`, with
+    // how many of `pending_keys` it took.
+    pending_match: Option<(usize, KeyAction)>,
+    replay: Vec<KeyEvent>, // Keys typed after `pending_match` that still have to be handled.
+}
+
+/// Vim's name for a named key, e.g. `Enter` for `<C-Enter>`.
+fn named_key(named: Named) -> Option<String> {
+    let name = match named {
+        Named::Tab => "Tab",
+        Named::Enter => "Enter",
+        Named::Escape => "Esc",
+        Named::Backspace => "BS",
+        Named::Delete => "Del",
+        Named::Insert => "Insert",
+        Named::Space => "Space",
+        Named::ArrowLeft => "Left",
+        Named::ArrowRight => "Right",
+        Named::ArrowUp => "Up",
+        Named::ArrowDown => "Down",
+        Named::Home => "Home",
+        Named::End => "End",
+        Named::PageUp => "PageUp",
+        Named::PageDown => "PageDown",
+        Named::F1 => "F1",
+        Named::F2 => "F2",
+        Named::F3 => "F3",
+        Named::F4 => "F4",
+        Named::F5 => "F5",
+        Named::F6 => "F6",
+        Named::F7 => "F7",
+        Named::F8 => "F8",
+        Named::F9 => "F9",
+        Named::F10 => "F10",
+        Named::F11 => "F11",
+        Named::F12 => "F12",
+        _ => return None,
+    };
+
+    Some(name.to_string())
+}
+
+/// `motion` repeated `count` times. With a count, `gg` and `G` go to that line instead, and `|`
+/// to that column.
+fn counted(motion: &Motion, count: Option<usize>) -> (Motion, usize) {
+    match (motion, count) {
+        (Motion::ToFirstLine | Motion::ToLastLine, Some(line)) => (Motion::ToLine(line), 1),
+        (Motion::ToColumn(_), Some(col)) => (Motion::ToColumn(col), 1),
+        (motion, count) => (motion.clone(), count.unwrap_or(1)),
+    }
+}
+
+/// `action` with the count typed before it, for the commands that take one.
+fn with_count(action: &Action, count: Option<usize>) -> Action {
+    match (action, count) {
+        (Action::DeleteChars { before, .. }, Some(count)) => Action::DeleteChars { count, before: *before },
+        (Action::ToggleCase { .. }, Some(count)) => Action::ToggleCase { count },
+        (Action::DeleteLines { .. }, Some(count)) => Action::DeleteLines { count },
+        (Action::YankLines { .. }, Some(count)) => Action::YankLines { count },
+        // Like Vim, `1J` still joins two lines.
+        (Action::JoinLines { keep_whitespace, .. }, Some(count)) => {
+            Action::JoinLines { count: count.max(2), keep_whitespace: *keep_whitespace }
+        }
+        (Action::Undo { .. }, Some(count)) => Action::Undo { count },
+        (Action::Redo { .. }, Some(count)) => Action::Redo { count },
+        (Action::Scroll { down, .. }, Some(lines)) => Action::Scroll { down: *down, lines: Some(lines) },
+        (action, _) => action.clone(),
+    }
 }
 
 impl Keymap {
@@ -25,6 +94,9 @@ impl Keymap {
         let mut keymap = Self {
             bindings: HashMap::new(),
             multi_key_buffer: String::new(),
+            pending_keys: Vec::new(),
+            pending_match: None,
+            replay: Vec::new(),
         };
 
         // Set up default bindings.
@@ -36,6 +108,9 @@ impl Keymap {
         self.bindings.insert((mode, keys.to_string()), action);
     }
 
+    /// Resolves `key` against the bindings for `mode`. A binding that's also the start of longer
+    /// ones waits for the next key: if that doesn't continue any of them, the shorter binding
+    /// runs after all and the keys after it are left in `take_replay`.
     pub fn handle_key(
         &mut self,
         mode: &EditorMode,
@@ -44,81 +119,133 @@ impl Keymap {
     ) -> Option<EngineAction> {
         let key_str = self.key_to_string(key);
         if key_str.is_empty() {
+            // Esc and friends cancel whatever was pending.
+            self.clear_pending();
             return None;
         }
         self.multi_key_buffer.push_str(&key_str);
+        self.pending_keys.push(key.clone());
 
-        // Check for exact match.
-        if let Some(action) = self
+        let exact = self
             .bindings
             .get(&(mode.clone(), self.multi_key_buffer.clone()))
-        {
-            let result = self.create_action(action, count.unwrap_or(1));
-            self.multi_key_buffer.clear();
-            return Some(result);
+            .cloned();
+        let has_longer = self.bindings.keys().any(|(m, keys)| {
+            m == mode && keys.len() > self.multi_key_buffer.len() && keys.starts_with(&self.multi_key_buffer)
+        });
+
+        match (exact, has_longer) {
+            (Some(action), false) => {
+                self.clear_pending();
+                Some(self.create_action(&action, count))
+            }
+            (Some(action), true) => {
+                self.pending_match = Some((self.pending_keys.len(), action));
+                None
+            }
+            (None, true) => None,
+            (None, false) => {
+                let pending_match = self.pending_match.take();
+                let keys = std::mem::take(&mut self.pending_keys);
+                self.clear_pending();
+
+                let (matched, action) = pending_match?;
+                self.replay = keys[matched..].to_vec();
+                Some(self.create_action(&action, count))
+            }
         }
+    }
 
-        let has_partial = self
-            .bindings
-            .keys()
-            .any(|(m, keys)| m == mode && keys.starts_with(&self.multi_key_buffer));
+    /// Keys that were typed after a shorter binding turned out to be the one meant, to be handled
+    /// again from scratch.
+    pub fn take_replay(&mut self) -> Vec<KeyEvent> {
+        std::mem::take(&mut self.replay)
+    }
 
-        if !has_partial {
-            self.multi_key_buffer.clear();
-            None
-        } else {
-            None
+    fn clear_pending(&mut self) {
+        self.multi_key_buffer.clear();
+        self.pending_keys.clear();
+        self.pending_match = None;
+    }
+
+    /// Whether `key` continues one of `mode`'s bindings from what's been typed so far.
+    pub fn continues(&self, mode: &EditorMode, key: &KeyEvent) -> bool {
+        let keys = format!("{}{}", self.multi_key_buffer, self.key_to_string(key));
+        self.bindings.keys().any(|(m, binding)| m == mode && binding.starts_with(&keys))
+    }
+
+    /// The operator waiting for its motion, when that's all that's been typed (the `d` of `dw`).
+    pub fn pending_operator(&self) -> Option<Operator> {
+        let mut keys = self.multi_key_buffer.chars();
+        match (keys.next(), keys.next()) {
+            (Some(c), None) => Operator::from_char(c),
+            _ => None,
         }
     }
 
-    fn key_to_string(&self, key: &KeyEvent) -> String {
-        if let KeyEvent::Key { key, modifiers, .. } = key {
-            let mut s = String::new();
+    /// Drops what's been typed of a multi-key binding, for keys handled outside the keymap.
+    pub fn cancel(&mut self) {
+        self.clear_pending();
+    }
 
-            let key_char = match key.as_ref() {
-                Key::Character(c) => Some(c.to_lowercase()),
-                _ => None,
-            };
+    /// Whether part of a multi-key binding has been typed, like the first `g` of `gg`.
+    pub fn is_pending(&self) -> bool {
+        !self.multi_key_buffer.is_empty()
+    }
 
-            if modifiers.control() {
-                s.push_str("<C-");
-            }
-            if modifiers.alt() {
-                s.push_str("<A-");
-            }
-            if modifiers.shift() {
-                s.push_str("<S-");
-            }
+    fn key_to_string(&self, key: &KeyEvent) -> String {
+        let KeyEvent::Key { key, modifiers, .. } = key else {
+            return String::new();
+        };
 
-            if let Some(c) = key_char {
-                s.push_str(&c);
-            }
+        // Named keys are always written in angle brackets with Vim's names, like `<Tab>` or
+        // `<C-Enter>`.
+        let (name, named) = match key.as_ref() {
+            Key::Character(c) => (c.to_lowercase(), false),
+            Key::Named(named) => match named_key(named) {
+                Some(name) => (name, true),
+                None => return String::new(),
+            },
+            _ => return String::new(),
+        };
 
-            if s.len() > 1 && s.starts_with('<') {
-                s.push('>');
-            }
+        // Shift is only meaningful for letters (`<S-w>`) and named keys, symbols like `>` or `$`
+        // already carry it in the character itself.
+        let shift = modifiers.shift() && (named || name.chars().any(char::is_alphabetic));
 
-            if s.len() <= 2 && s.ends_with('>') {
-                return String::new();
-            }
+        let mut prefix = String::new();
+        if modifiers.control() {
+            prefix.push_str("C-");
+        }
+        if modifiers.alt() {
+            prefix.push_str("A-");
+        }
+        if shift {
+            prefix.push_str("S-");
+        }
 
-            s
+        if prefix.is_empty() && !named {
+            name
         } else {
-            String::new()
+            format!("<{prefix}{name}>")
         }
     }
 
-    fn create_action(&self, action: &KeyAction, count: usize) -> EngineAction {
+    fn create_action(&self, action: &KeyAction, count: Option<usize>) -> EngineAction {
         match action {
-            KeyAction::KeyMotion(motion) => EngineAction::Action(Action::Move {
-                motion: motion.clone(),
-                count,
-            }),
+            KeyAction::KeyMotion(motion) => {
+                let (motion, count) = counted(motion, count);
+                EngineAction::Action(Action::Move { motion, count })
+            }
+            KeyAction::Command(Action::Operate { op, motion, .. }) => {
+                let (motion, count) = counted(motion, count);
+                EngineAction::Action(Action::Operate { op: op.clone(), motion, count })
+            }
             KeyAction::KeyOperator(_) => {
                 // NOTE: This would be handled differently - operators need motions.
                 todo!("Handle operators with keymap")
             }
-            KeyAction::Command(cmd) => EngineAction::Action(cmd.clone()),
+            KeyAction::Command(cmd) => EngineAction::Action(with_count(cmd, count)),
             KeyAction::Custom(func) => EngineAction::Action(func()),
             KeyAction::AppCommand(msg) => EngineAction::App(msg.clone()),
         }
@@ -126,13 +253,39 @@ impl Keymap {
 
     fn setup_defaults(&mut self) {
         use KeyAction::*;
-        use EditorMode::*;
+        use EditorMode::{Insert, Normal, Visual, VisualBlock, VisualLine};
 
         // Basic movements.
         self.set(Normal, "h", KeyMotion(Motion::CharLeft));
         self.set(Normal, "j", KeyMotion(Motion::CharDown));
         self.set(Normal, "k", KeyMotion(Motion::CharUp));
         self.set(Normal, "l", KeyMotion(Motion::CharRight));
+        self.set(Normal, "0", KeyMotion(Motion::ToLineStart));
+        self.set(Normal, "$", KeyMotion(Motion::ToLineEnd));
+        self.set(Normal, "^", KeyMotion(Motion::ToFirstNonBlank));
+        self.set(Normal, "|", KeyMotion(Motion::ToColumn(1)));
+        for mode in [Normal, Visual, VisualLine, VisualBlock] {
+            self.set(mode.clone(), ";", KeyMotion(Motion::RepeatFind));
+            self.set(mode, ",", KeyMotion(Motion::RepeatFindReverse));
+        }
+        self.set(Normal, "g0", KeyMotion(Motion::ToRowStart));
+        self.set(Normal, "g$", KeyMotion(Motion::ToRowEnd));
+        self.set(Normal, "gg", KeyMotion(Motion::ToFirstLine));
+        self.set(Normal, "<S-g>", KeyMotion(Motion::ToLastLine));
+        for mode in [Normal, Visual, VisualLine, VisualBlock] {
+            self.set(mode, "%", KeyMotion(Motion::MatchPair));
+        }
+        // The same for those used to these keys, in every mode.
+        for mode in [Normal, Insert, Visual, VisualLine, VisualBlock] {
+            self.set(mode.clone(), "<Home>", KeyMotion(Motion::ToLineStart));
+            self.set(mode.clone(), "<End>", KeyMotion(Motion::ToLineEnd));
+            self.set(mode.clone(), "<PageUp>", KeyMotion(Motion::PageUp));
+            self.set(mode, "<PageDown>", KeyMotion(Motion::PageDown));
+        }
+        for mode in [Normal, Visual, VisualLine, VisualBlock] {
+            self.set(mode.clone(), "<C-d>", Command(Action::Scroll { down: true, lines: None }));
+            self.set(mode, "<C-u>", Command(Action::Scroll { down: false, lines: None }));
+        }
 
         // Word movements.
         self.set(Normal, "w", KeyMotion(Motion::NextWordStart(false)));
@@ -144,10 +297,38 @@ impl Keymap {
 
         // Mode changes.
         self.set(Normal, "i", Command(Action::ChangeMode(Insert)));
+        self.set(Normal, "a", Command(Action::Append { line_end: false }));
+        self.set(Normal, "<S-a>", Command(Action::Append { line_end: true }));
+        self.set(Normal, "<S-i>", Command(Action::InsertAtFirstNonBlank));
         self.set(Normal, "v", Command(Action::ChangeMode(Visual)));
+        self.set(Normal, "<S-v>", Command(Action::ChangeMode(VisualLine)));
+        self.set(Normal, "o", Command(Action::OpenLine { above: false }));
+        self.set(Normal, "<S-o>", Command(Action::OpenLine { above: true }));
+        self.set(Normal, "gi", Command(Action::ResumeInsert));
+        // `v` and `V` switch between charwise and linewise Visual, or leave it when pressed again.
+        self.set(Visual, "<S-v>", Command(Action::ChangeMode(VisualLine)));
+        self.set(VisualLine, "v", Command(Action::ChangeMode(Visual)));
+        self.set(Visual, "v", Command(Action::ChangeMode(Normal)));
+        self.set(VisualLine, "<S-v>", Command(Action::ChangeMode(Normal)));
+        // `<C-v>` splits the window, so block mode lives on Alt.
+        self.set(Normal, "<A-v>", Command(Action::ChangeMode(VisualBlock)));
+        self.set(Normal, ":", Command(Action::ChangeMode(EditorMode::Command)));
+
+        // Indentation. Tab doubles as `>>`, this doesn't clash with `<C-i>` since
+        // the modifiers are part of the binding.
+        self.set(Normal, ">>", Command(Action::Indent));
+        self.set(Normal, "<<", Command(Action::Dedent));
+        self.set(Normal, "<Tab>", Command(Action::Indent));
+        self.set(Normal, "<S-Tab>", Command(Action::Dedent));
+
+        // Joining lines.
+        self.set(Normal, "<S-j>", Command(Action::JoinLines { count: 2, keep_whitespace: false }));
+        self.set(Normal, "g<S-j>", Command(Action::JoinLines { count: 2, keep_whitespace: true }));
 
         // Other commands.
-        self.set(Normal, "x", Command(Action::Delete));
+        self.set(Normal, "x", Command(Action::DeleteChars { count: 1, before: false }));
+        self.set(Normal, "<S-x>", Command(Action::DeleteChars { count: 1, before: true }));
+        self.set(Normal, "~", Command(Action::ToggleCase { count: 1 }));
         self.set(Normal, ".", Command(Action::RepeatLast));
 
         // Operators.
@@ -156,7 +337,71 @@ impl Keymap {
         // self.set(Normal, "c", KeyOperator(Operator::Change));
 
         self.set(Normal, "d", Command(Action::DeleteSelection));
-        self.set(Visual, "d", Command(Action::DeleteSelection));
+        // `d` followed by a motion deletes up to where it goes, `dj` takes two whole lines. `y`
+        // copies the same text, `c` deletes it and starts Insert mode.
+        let motions: Vec<(String, Motion)> = self
+            .bindings
+            .iter()
+            .filter_map(|((mode, keys), action)| match (mode, action) {
+                (Normal, KeyMotion(motion)) => Some((keys.clone(), motion.clone())),
+                _ => None,
+            })
+            .collect();
+        for (keys, motion) in motions {
+            for (prefix, op) in [("d", Operator::Delete), ("y", Operator::Yank), ("c", Operator::Change)] {
+                let operate = Action::Operate { op, motion: motion.clone(), count: 1 };
+                self.set(Normal, &format!("{prefix}{keys}"), Command(operate));
+            }
+        }
+        // Like Vim, `cw` stops at the end of the word and leaves the space after it.
+        for (keys, big) in [("cw", false), ("c<S-w>", true)] {
+            let operate = Action::Operate { op: Operator::Change, motion: Motion::NextWordEnd(big), count: 1 };
+            self.set(Normal, keys, Command(operate));
+        }
+        self.set(Normal, "dd", Command(Action::DeleteLines { count: 1 }));
+        self.set(Normal, "yy", Command(Action::YankLines { count: 1 }));
+        for mode in [Visual, VisualLine, VisualBlock] {
+            self.set(mode, ":", Command(Action::ChangeMode(EditorMode::Command)));
+        }
+        for mode in [Visual, VisualLine] {
+            self.set(mode.clone(), "d", Command(Action::DeleteSelection));
+            self.set(mode.clone(), "y", Command(Action::YankSelection));
+            self.set(mode.clone(), "c", Command(Action::ChangeSelection));
+            self.set(mode.clone(), "p", Command(Action::Paste { before: false, over_selection: true }));
+            self.set(mode.clone(), "<S-i>", Command(Action::InsertAtSelection { end: false }));
+            self.set(mode, "<S-a>", Command(Action::InsertAtSelection { end: true }));
+        }
+        self.set(Normal, "u", Command(Action::Undo { count: 1 }));
+        self.set(Normal, "<C-r>", Command(Action::Redo { count: 1 }));
+        self.set(Normal, "p", Command(Action::Paste { before: false, over_selection: false }));
+        self.set(Normal, "<S-p>", Command(Action::Paste { before: true, over_selection: false }));
+        // Text objects: `di{` deletes a block's contents, Visual `a{` selects it with its braces,
+        // `daw` deletes a word and a space next to it.
+        for (keys, open, close) in [
+            ("(", '(', ')'), (")", '(', ')'), ("b", '(', ')'),
+            ("{", '{', '}'), ("}", '{', '}'), ("<S-b>", '{', '}'),
+            ("[", '[', ']'), ("]", '[', ']'),
+            ("<", '<', '>'), (">", '<', '>'),
+            ("\"", '"', '"'), ("'", '\'', '\''), ("`", '`', '`'),
+        ] {
+            for (prefix, inner) in [("i", true), ("a", false)] {
+                let object = TextObject::Pair { open, close, inner };
+                self.set(Normal, &format!("d{prefix}{keys}"), Command(Action::DeleteTextObject(object)));
+                self.set(Visual, &format!("{prefix}{keys}"), Command(Action::SelectTextObject(object)));
+            }
+        }
+        for (keys, big) in [("w", false), ("<S-w>", true)] {
+            for (prefix, inner) in [("i", true), ("a", false)] {
+                let object = TextObject::Word { big, inner };
+                self.set(Normal, &format!("d{prefix}{keys}"), Command(Action::DeleteTextObject(object)));
+                self.set(Visual, &format!("{prefix}{keys}"), Command(Action::SelectTextObject(object)));
+            }
+        }
+        self.set(VisualBlock, "<S-i>", Command(Action::BlockInsert { end: false }));
+        self.set(VisualBlock, "<S-a>", Command(Action::BlockInsert { end: true }));
+        self.set(VisualBlock, "d", Command(Action::BlockDelete));
+        self.set(VisualBlock, "y", Command(Action::BlockYank));
+        self.set(VisualBlock, "c", Command(Action::BlockChange));
         
         // Testing multiple cursors.
         self.set(Normal, "<S-c>", Command(Action::AddCursor));
@@ -165,23 +410,51 @@ impl Keymap {
         // Window splitting.
         self.set(Normal, "<C-v>", AppCommand(Message::SplitVertical));
         self.set(Normal, "<C-h>", AppCommand(Message::SplitHorizontal));
-        self.set(Normal, "<C-w>", AppCommand(Message::CloseSplit));
+        self.set(Normal, "<C-w>", AppCommand(Message::CloseSplit { force: false }));
+
+        // Finding files.
+        self.set(Normal, "<C-p>", AppCommand(Message::OpenFinder));
 
         // Quit atlas.
         self.set(Normal, "<C-q>", AppCommand(Message::Quit));
 
-        // Multi-key bindings.
-        self.set(
-            Normal,
-            "gg",
-            Custom(|| Action::Move {
-                motion: Motion::ToLineStart,
-                count: 1,
-            }),
-        );
-
         // A taste of the future.
         // self.set(Normal, "gd", Custom(go_to_definition));
         // self.set(Normal, "gr", Custom(replace_under_cursor));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use iced::keyboard::Modifiers;
+
+    use super::*;
+
+    fn event(key: Key, modifiers: Modifiers) -> KeyEvent {
+        KeyEvent::Key { key, text: None, modifiers }
+    }
+
+    #[test]
+    fn symbols_and_named_keys_take_modifiers() {
+        let keymap = Keymap::new();
+        let ctrl = Modifiers::CTRL;
+
+        assert_eq!(keymap.key_to_string(&event(Key::Character("]".into()), ctrl)), "<C-]>");
+        assert_eq!(keymap.key_to_string(&event(Key::Named(Named::Enter), ctrl)), "<C-Enter>");
+        assert_eq!(keymap.key_to_string(&event(Key::Named(Named::ArrowUp), Modifiers::SHIFT)), "<S-Up>");
+        assert_eq!(keymap.key_to_string(&event(Key::Named(Named::F5), Modifiers::empty())), "<F5>");
+    }
+
+    #[test]
+    fn ctrl_bracket_resolves_to_its_binding() {
+        let mut keymap = Keymap::new();
+        keymap.set(EditorMode::Normal, "<C-]>", KeyAction::Command(Action::Indent));
+
+        let action = keymap.handle_key(&EditorMode::Normal, &event(Key::Character("]".into()), Modifiers::CTRL), None);
+        assert!(matches!(action, Some(EngineAction::Action(Action::Indent))));
+
+        // Without Ctrl it's a different key.
+        let action = keymap.handle_key(&EditorMode::Normal, &event(Key::Character("]".into()), Modifiers::empty()), None);
+        assert!(action.is_none());
+    }
+}
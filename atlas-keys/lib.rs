@@ -1,5 +1,7 @@
+pub mod command;
 pub mod engine;
 pub mod keymap;
 
-pub use engine::{EngineAction, Action, KeyEngine, KeyEvent, Motion, Operator, execute};
+pub use command::{Address, ExCommand, LineRange, OptionSetting, OptionValue, parse_ex};
+pub use engine::{EngineAction, Action, KeyEngine, KeyEvent, ModeTransition, Motion, Operator, TextObject, execute};
 pub use keymap::{Keymap, KeyAction};
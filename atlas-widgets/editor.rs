@@ -1,15 +1,20 @@
 use std::cell::RefCell;
+use std::ops::Range;
 use std::rc::Rc;
+use std::time::{Duration, Instant};
 
 use atlas_config::Config;
-use atlas_engine::{Buffer, Message, MultiCursor, EditorMode};
-use atlas_keys::{KeyEvent, KeyEngine, execute};
+use atlas_engine::{Buffer, EditorMode, LineColumn, Message, MultiCursor, RegisterKind, Registers, TextPosition, cursor::MoveOpts};
+use atlas_keys::{Action, ExCommand, KeyEvent, KeyEngine, LineRange, Motion, OptionSetting, execute, parse_ex};
 use iced::{
     advanced::{
         graphics::core::{event, widget}, layout, mouse, renderer, text::Paragraph as _, widget::Tree, Clipboard, Layout, Shell, Text, Widget
-    }, alignment, keyboard::{self, Key}, widget::span, Border, Color, Element, Event, Point, Rectangle, Renderer, Shadow, Size, Theme
+    }, alignment, keyboard::{self, Key}, widget::span, window, Border, Color, Element, Event, Font, Pixels, Point, Rectangle, Renderer, Shadow, Size, Theme
 };
 use iced_graphics::{core::SmolStr, text::Paragraph};
+use regex::Regex;
+
+use crate::rows::{RowMap, ScreenRow};
 
 pub type SharedBuffer = Rc<RefCell<Buffer>>;
 
@@ -23,6 +28,18 @@ pub struct Editor {
     pub key_engine: KeyEngine,
     pub is_focused: bool,
     pub config: Config,
+    pub message: Option<String>, // Shown on the bottom row, e.g. command errors.
+    pub registers: Registers, // Yanked text, for `p`.
+    pub scroll_lines: Option<usize>, // How far `<C-d>`/`<C-u>` go once given a count (Vim's `scroll`).
+}
+
+/// View options in effect for the current buffer: its `:setlocal` overrides layered over the
+/// global config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ViewOptions {
+    pub wrap: bool,
+    pub number: bool,
+    pub list: bool,
 }
 
 #[derive(Default, Debug)]
@@ -30,7 +47,12 @@ struct EditorState {
     // Cached values.
     char_width: Option<f32>,
     line_height: Option<f32>,
+    font: Option<(Font, Pixels)>, // What `char_width` was measured with.
+    font_warning: Option<String>, // See `Editor::check_monospace`, shown until a key is pressed.
     bounds: Rectangle,
+    gutter_drag: Option<usize>, // The line a selection was started on by pressing in the gutter.
+    text_drag: Option<TextPosition>, // Where a selection was started by pressing on the text.
+    flash_until: Option<Instant>, // The visual bell, see `Editor::ring_bell`.
 }
 
 impl Default for Editor {
@@ -42,6 +64,9 @@ impl Default for Editor {
             scroll_offset: Point::new(0.0, 0.0),
             is_focused: false,
             config: Config::default(),
+            message: None,
+            registers: Registers::new(),
+            scroll_lines: None,
         }
     }
 }
@@ -49,6 +74,9 @@ impl Default for Editor {
 impl Editor {
     const MARGIN_LINES: usize = 3;
     const MARGIN_COL: usize = 8;
+    /// Glyphs with very different widths in proportional fonts.
+    const WIDTH_SAMPLE: &str = "MiW.l0";
+    const FLASH_DURATION: Duration = Duration::from_millis(100);
 
     pub fn new() -> Self {
         Self::default()
@@ -59,18 +87,26 @@ impl Editor {
         self
     }
 
+    /// Shows `buffer` from its start in place of the current one. The rest stays with the pane:
+    /// its registers, options and pending keys.
+    pub fn show_buffer(&mut self, buffer: SharedBuffer) {
+        self.buffer = buffer;
+        self.multi_cursor = MultiCursor::new();
+        self.scroll_offset = Point::ORIGIN;
+        self.message = None;
+    }
+
     fn char_width(&self, renderer: &impl iced::advanced::text::Renderer<Font = iced::Font>) -> f32 {
         // Create a paragraph with a single character to get precise width.
-        // NOTE: We probably need to cache this.
         let bounds = Size::new(1000.0, 100.0);
 
         // We assume all characters have the same width, hence only monospaced fonts work.
         let paragraph = self.create_paragraph("M", bounds, renderer);
 
-        if let Some(run) = paragraph.buffer().layout_runs().next() {
-            if let Some(glyph) = run.glyphs.first() {
-                return glyph.w;
-            }
+        if let Some(run) = paragraph.buffer().layout_runs().next()
+            && let Some(glyph) = run.glyphs.first()
+        {
+            return glyph.w;
         }
 
         // Fallback.
@@ -79,6 +115,17 @@ impl Editor {
         size * 0.6
     }
 
+    /// A warning for the bottom row when the font doesn't look monospaced, which misaligns the
+    /// text. This measures a few different glyphs, so it's only done when the font changes, see
+    /// `layout`.
+    fn check_monospace(&self, renderer: &impl iced::advanced::text::Renderer<Font = iced::Font>) -> Option<String> {
+        let paragraph = self.create_paragraph(Self::WIDTH_SAMPLE, Size::new(1000.0, 100.0), renderer);
+        let run = paragraph.buffer().layout_runs().next()?;
+
+        let widths: Vec<f32> = run.glyphs.iter().map(|g| g.w).collect();
+        (!is_uniform_width(&widths)).then(|| "The font doesn't look monospaced, text will be misaligned".to_string())
+    }
+
     pub fn create_paragraph(
         &self,
         content: &str,
@@ -95,20 +142,326 @@ impl Editor {
             wrapping: iced::advanced::text::Wrapping::None,
             horizontal_alignment: iced::alignment::Horizontal::Left,
             vertical_alignment: iced::alignment::Vertical::Top,
-            line_height: iced::widget::text::LineHeight::Relative(1.2),
+            line_height: iced::widget::text::LineHeight::Relative(self.config.line_height),
             font: renderer.default_font(),
         })
     }
 
-    fn line_height(&self, _renderer: &impl iced::advanced::text::Renderer) -> f32 {
+    fn line_height(&self) -> f32 {
         let size: f32 = self.config.font_size.into();
-        size * 1.2
+        size * self.config.line_height
+    }
+
+    pub fn view_options(&self) -> ViewOptions {
+        let local = &self.buffer.borrow().options;
+
+        ViewOptions {
+            wrap: local.wrap.unwrap_or(self.config.wrap),
+            number: local.number.unwrap_or(self.config.number),
+            list: local.list.unwrap_or(self.config.list),
+        }
+    }
+
+    /// What's drawn for `len` graphemes of `line` from `start_col`: tabs expanded, and whitespace
+    /// made visible with `list`.
+    /// Lines are borrowed from the rope, we only allocate the visible part handed to `fill_text`.
+    fn row_text(&self, buffer: &Buffer, line: usize, start_col: usize, len: usize, list: bool) -> String {
+        let mut content = buffer.grapheme_substring(line, start_col, len);
+        if list {
+            let reaches_end = start_col + len >= buffer.grapheme_len(line);
+            content = show_whitespace(&content, reaches_end);
+        }
+
+        expand_tabs(&content, buffer.display_col(line, start_col), buffer.options.tab_width, list)
+    }
+
+    /// Which of the `visible` screen rows are past the end of the buffer and get a `~`, if
+    /// `end_of_buffer_markers` is set.
+    fn end_of_buffer_rows(&self, rows: &RowMap, visible: Range<usize>) -> Range<usize> {
+        if !self.config.end_of_buffer_markers {
+            return 0..0;
+        }
+
+        visible.start.max(rows.total_rows())..visible.end
+    }
+
+    /// Columns of the line number column: enough digits for the last line plus a space, and at
+    /// least `number_width`.
+    fn gutter_columns(&self) -> usize {
+        if !self.view_options().number {
+            return 0;
+        }
+
+        let total_lines = self.buffer.borrow().content.len_lines();
+        (total_lines.to_string().len() + 1).max(self.config.number_width)
+    }
+
+    fn gutter_width(&self, char_width: f32) -> f32 {
+        self.gutter_columns() as f32 * char_width
+    }
+
+    /// The part of `bounds` where the buffer's text goes, i.e. without the gutter.
+    fn text_bounds(&self, bounds: Rectangle, char_width: f32) -> Rectangle {
+        let gutter = self.gutter_width(char_width).min(bounds.width);
+
+        Rectangle {
+            x: bounds.x + gutter,
+            width: bounds.width - gutter,
+            ..bounds
+        }
+    }
+
+    /// Line shown at height `y`, clamped to the buffer's lines above and below them.
+    fn line_at_y(&self, y: f32, bounds: Rectangle, char_width: f32, line_height: f32) -> usize {
+        let rows = self.row_map(self.text_bounds(bounds, char_width), char_width);
+        let row = ((y - bounds.y + self.scroll_offset.y) / line_height).max(0.0).floor() as usize;
+        let last_line = self.buffer.borrow().line_count() - 1;
+
+        rows.row(row.min(rows.total_rows().saturating_sub(1)))
+            .map_or(last_line, |row| row.line.min(last_line))
+    }
+
+    /// Line whose number is under `point`, `None` outside the gutter (or without one).
+    fn gutter_line_at(&self, point: Point, bounds: Rectangle, char_width: f32, line_height: f32) -> Option<usize> {
+        let gutter = Rectangle { width: self.gutter_width(char_width).min(bounds.width), ..bounds };
+        gutter
+            .contains(point)
+            .then(|| self.line_at_y(point.y, bounds, char_width, line_height))
+    }
+
+    /// Selects lines `anchor` through `line` in VisualLine mode, as clicking and dragging in the
+    /// gutter does.
+    fn select_lines(&mut self, anchor: usize, line: usize) {
+        let buffer = self.buffer.borrow();
+        let anchor = TextPosition::new(anchor, 0, buffer.content.line_to_char(anchor));
+        let active = TextPosition::new(line, 0, buffer.content.line_to_char(line));
+
+        self.key_engine.mode = EditorMode::VisualLine;
+        self.multi_cursor.clear_secondary_cursors();
+        self.multi_cursor.primary_mut().move_to(
+            active,
+            MoveOpts { anchor: Some(anchor), update_preferred_col: true },
+            &buffer,
+        );
+        self.multi_cursor.adjust_for_mode(&buffer, &EditorMode::VisualLine);
+    }
+
+    /// Clicking the text moves the cursor there, leaving Visual mode.
+    fn click(&mut self, pos: TextPosition) {
+        if self.key_engine.mode.is_visual() {
+            self.key_engine.mode = EditorMode::Normal;
+        }
+        self.select_to(pos, pos);
+    }
+
+    /// Selects from `anchor` to `pos` in Visual mode, as dragging over the text does.
+    fn drag_to(&mut self, anchor: TextPosition, pos: TextPosition) {
+        self.key_engine.mode = EditorMode::Visual;
+        self.select_to(anchor, pos);
+    }
+
+    fn select_to(&mut self, anchor: TextPosition, pos: TextPosition) {
+        let buffer = self.buffer.borrow();
+        let anchor = (anchor != pos).then_some(anchor);
+
+        self.multi_cursor.clear_secondary_cursors();
+        self.multi_cursor.primary_mut().move_to(pos, MoveOpts { anchor, update_preferred_col: true }, &buffer);
+        self.multi_cursor.adjust_for_mode(&buffer, &self.key_engine.mode);
+    }
+
+    /// Position of the grapheme under `point`, clamped to the buffer's lines and the row's
+    /// columns.
+    fn position_at(&self, point: Point, bounds: Rectangle, char_width: f32, line_height: f32) -> TextPosition {
+        let text_bounds = self.text_bounds(bounds, char_width);
+        let rows = self.row_map(text_bounds, char_width);
+        let row = ((point.y - bounds.y + self.scroll_offset.y) / line_height).max(0.0).floor() as usize;
+        let last_line = self.buffer.borrow().line_count() - 1;
+
+        let row = rows.row(row.min(rows.total_rows().saturating_sub(1))).filter(|row| row.line <= last_line);
+        let (line, cols) = row.map_or((last_line, None), |row| (row.line, row.cols));
+
+        // Back from where the row is drawn to where the line would be, see `column_to_row_x`.
+        let row_start = cols.as_ref().map_or(0, |cols| cols.start);
+        let indent = rows.indent(line, row_start) as f32 * char_width;
+        let x = point.x - text_bounds.x + self.scroll_offset.x - indent + self.column_to_x(line, row_start, char_width);
+
+        let mut col = self.x_to_column(line, x, char_width);
+        if let Some(cols) = cols {
+            col = col.clamp(cols.start, cols.end.saturating_sub(1).max(cols.start));
+        }
+
+        let buffer = self.buffer.borrow();
+        let col = col.min(buffer.grapheme_len(line));
+        TextPosition::new(line, col, buffer.grapheme_col_to_offset(line, col))
+    }
+
+    fn row_map(&self, text_bounds: Rectangle, char_width: f32) -> RowMap {
+        let wrap_width = self.view_options().wrap.then(|| {
+            let width = (text_bounds.width / char_width).floor() as usize;
+            self.config.wrap_column.map_or(width, |column| column.min(width))
+        });
+
+        RowMap::new(&self.buffer.borrow(), wrap_width, self.config.break_indent)
+    }
+
+    /// Horizontal distance from the start of `line` to grapheme column `col`, with tabs expanded.
+    pub fn column_to_x(&self, line: usize, col: usize, char_width: f32) -> f32 {
+        self.buffer.borrow().display_col(line, col) as f32 * char_width
+    }
+
+    /// Grapheme column of `line` under `x` (measured from the start of the line), the inverse of
+    /// `column_to_x`. Anywhere over a tab gives the tab's column.
+    pub fn x_to_column(&self, line: usize, x: f32, char_width: f32) -> usize {
+        let display_col = (x / char_width).max(0.0).floor() as usize;
+        self.buffer.borrow().col_at_display(line, display_col)
+    }
+
+    /// `column_to_x` within a screen row starting at column `row_start` (soft wrap), which may be
+    /// indented.
+    fn column_to_row_x(&self, rows: &RowMap, line: usize, col: usize, row_start: usize, char_width: f32) -> f32 {
+        let indent = rows.indent(line, row_start) as f32 * char_width;
+        indent + self.column_to_x(line, col, char_width) - self.column_to_x(line, row_start, char_width)
+    }
+
+    /// Runs `action`, the motions that depend on how lines are laid out on screen happen here.
+    /// Returns `false` if it failed, see `execute`.
+    fn perform(&mut self, action: Action, bounds: Rectangle, char_width: f32) -> bool {
+        let done = match action {
+            Action::Move { motion: motion @ (Motion::ToRowStart | Motion::ToRowEnd), .. } if self.view_options().wrap => {
+                let rows = self.row_map(self.text_bounds(bounds, char_width), char_width);
+                self.move_to_row_edge(&rows, motion == Motion::ToRowEnd);
+                true
+            }
+            Action::Move { motion: motion @ (Motion::PageDown | Motion::PageUp), count } => {
+                let window_rows = (bounds.height / self.line_height()).floor() as usize;
+                let rows = window_rows.saturating_sub(2).max(1) * count.max(1);
+                self.scroll(motion == Motion::PageDown, rows, bounds, char_width);
+                true
+            }
+            Action::Scroll { down, lines } => {
+                if lines.is_some() {
+                    self.scroll_lines = lines;
+                }
+                let window_rows = (bounds.height / self.line_height()).floor() as usize;
+                let rows = self.scroll_lines.unwrap_or(window_rows / 2).max(1);
+                self.scroll(down, rows, bounds, char_width);
+                true
+            }
+            action => {
+                self.buffer.borrow_mut().begin_change(&self.multi_cursor);
+                execute(
+                    action,
+                    &mut self.buffer.borrow_mut(),
+                    &mut self.multi_cursor,
+                    &self.key_engine.mode,
+                    &mut self.registers,
+                    &self.config.match_pairs,
+                )
+            }
+        };
+
+        // What's typed in Insert mode is recorded once it's left.
+        if self.key_engine.mode != EditorMode::Insert {
+            self.buffer.borrow_mut().checkpoint(&self.multi_cursor);
+        }
+
+        done
+    }
+
+    /// Flashes the window for a moment when a motion or command fails, if `visual_bell` is set.
+    /// Failed commands also leave their error on the bottom row either way.
+    fn ring_bell(&self, state: &mut EditorState, shell: &mut Shell<'_, Message>) {
+        if !self.config.visual_bell {
+            return;
+        }
+
+        let until = Instant::now() + Self::FLASH_DURATION;
+        state.flash_until = Some(until);
+        // Another frame once it's over, to take the flash away.
+        shell.request_redraw(window::RedrawRequest::At(until));
+    }
+
+    /// `:earlier`/`:later`, see `Buffer::travel_history`.
+    fn travel_history(&mut self, steps: isize) -> Result<(), String> {
+        let mut buffer = self.buffer.borrow_mut();
+        if !buffer.travel_history(steps, &mut self.multi_cursor) {
+            let end = if steps < 0 { "oldest" } else { "newest" };
+            return Err(format!("Already at {end} change"));
+        }
+
+        self.multi_cursor.adjust_for_mode(&buffer, &self.key_engine.mode);
+        Ok(())
+    }
+
+    /// Scrolls `rows` down (or up), and takes the cursor along by as many lines, or into the new
+    /// view. `<PageDown>` goes a window at a time less two rows, like Vim, `<C-d>` half of one.
+    fn scroll(&mut self, down: bool, rows: usize, bounds: Rectangle, char_width: f32) {
+        let line_height = self.line_height();
+        let total_rows = self.row_map(self.text_bounds(bounds, char_width), char_width).total_rows();
+        let window_rows = (bounds.height / line_height).floor() as usize;
+
+        let delta = rows as f32 * line_height;
+        let max_offset = total_rows.saturating_sub(1) as f32 * line_height;
+        let offset = if down { self.scroll_offset.y + delta } else { self.scroll_offset.y - delta };
+        self.scroll_offset.y = offset.clamp(0.0, max_offset);
+
+        let buffer = self.buffer.borrow();
+        let line = self.multi_cursor.position().line;
+        let line = if down { line + rows } else { line.saturating_sub(rows) };
+        // Kept inside the new view, past the margins, so it doesn't scroll right back.
+        let top = (self.scroll_offset.y / line_height) as usize;
+        let margin = Self::MARGIN_LINES.min(window_rows.saturating_sub(1) / 2);
+        let line = line.clamp(top + margin, (top + window_rows).saturating_sub(margin + 1).max(top + margin));
+        let line = line.min(buffer.line_count() - 1);
+        self.multi_cursor.move_to_line(&buffer, line, LineColumn::Preferred, &self.key_engine.mode);
+    }
+
+    /// `g0`/`g$`: to the first or last column of the screen row under each cursor.
+    fn move_to_row_edge(&mut self, rows: &RowMap, end: bool) {
+        let buffer = self.buffer.borrow();
+        let mode = &self.key_engine.mode;
+
+        self.multi_cursor.move_to_cols(&buffer, mode, |pos| {
+            let Some(cols) = rows.row_cols(pos.line, pos.col) else {
+                return pos.col;
+            };
+
+            // Only Insert mode goes past the last grapheme, and only on the line's last row.
+            let past_end = *mode == EditorMode::Insert && cols.end == buffer.grapheme_len(pos.line);
+            match end {
+                false => cols.start,
+                true if past_end => cols.end,
+                true => cols.end.saturating_sub(1).max(cols.start),
+            }
+        });
+    }
+
+    /// Screen position of the top-left corner of the cell at `pos`.
+    fn position_to_point(
+        &self,
+        pos: TextPosition,
+        rows: &RowMap,
+        bounds: Rectangle,
+        char_width: f32,
+        line_height: f32,
+    ) -> Point {
+        let (row, row_start) = rows.locate(pos.line, pos.col);
+        let x = self.column_to_row_x(rows, pos.line, pos.col, row_start, char_width);
+
+        Point::new(
+            bounds.x + (x - self.scroll_offset.x),
+            bounds.y + (row as f32 * line_height - self.scroll_offset.y),
+        )
     }
 
     fn ensure_cursor_visible(&mut self, bounds: Rectangle, char_width: f32, line_height: f32) {
-        let cursor_pos = self.multi_cursor.position();
-        let cursor_x = cursor_pos.col as f32 * char_width;
-        let cursor_y = cursor_pos.line as f32 * line_height;
+        let bounds = self.text_bounds(bounds, char_width);
+        let rows = self.row_map(bounds, char_width);
+        let cursor = self.multi_cursor.primary();
+        let (line, col) = (cursor.position().line, cursor.virtual_col());
+        let (row, row_start) = rows.locate(line, col);
+        let cursor_x = self.column_to_row_x(&rows, line, col, row_start, char_width);
+        let cursor_y = row as f32 * line_height;
 
         // Defining vertical limits.
         let top_limit = self.scroll_offset.y + Self::MARGIN_LINES as f32 * line_height;
@@ -128,8 +481,10 @@ impl Editor {
         let right_limit =
             self.scroll_offset.x + bounds.width - (Self::MARGIN_COL + 1) as f32 * char_width;
 
-        // Horizontal scrolling.
-        if cursor_x < left_limit {
+        // Horizontal scrolling, wrapped lines always fit.
+        if self.view_options().wrap {
+            self.scroll_offset.x = 0.0;
+        } else if cursor_x < left_limit {
             self.scroll_offset.x = (cursor_x - Self::MARGIN_COL as f32 * char_width).max(0.0);
         } else if cursor_x > right_limit {
             self.scroll_offset.x =
@@ -137,18 +492,214 @@ impl Editor {
         }
     }
 
+    /// Vertical scroll offset after applying a mouse-wheel `delta`, honoring the configured
+    /// scroll speed and direction, and clamped so the last row can reach the top of the view.
+    fn wheel_scroll_offset(&self, delta: mouse::ScrollDelta, line_height: f32, total_rows: usize) -> f32 {
+        let pixels = match delta {
+            mouse::ScrollDelta::Lines { y, .. } => y * line_height,
+            mouse::ScrollDelta::Pixels { y, .. } => y,
+        };
+
+        let direction = if self.config.scroll_invert { -1.0 } else { 1.0 };
+        let delta = pixels * self.config.scroll_speed * direction;
+
+        let max_offset = total_rows.saturating_sub(1) as f32 * line_height;
+
+        (self.scroll_offset.y - delta).clamp(0.0, max_offset)
+    }
+
+    /// Runs a `:` command line, reporting errors on the bottom row. Commands the app has to carry
+    /// out, like opening a split, are handed back.
+    pub fn run_ex_command(&mut self, line: &str) -> Option<Message> {
+        self.message = None;
+
+        if line.trim().is_empty() {
+            return None;
+        }
+
+        self.buffer.borrow_mut().begin_change(&self.multi_cursor);
+        let result = parse_ex(line).and_then(|command| match command {
+            ExCommand::Set { local, settings } => settings
+                .iter()
+                .try_for_each(|setting| self.set_option(local, setting))
+                .map(|()| None),
+            ExCommand::Global { pattern, invert, command } => {
+                self.global(&pattern, invert, &command).map(|()| None)
+            }
+            // Splits are the app's business.
+            ExCommand::New { vertical: true } => Ok(Some(Message::SplitVertical)),
+            ExCommand::New { vertical: false } => Ok(Some(Message::SplitHorizontal)),
+            ExCommand::Quit { force } => Ok(Some(Message::CloseSplit { force })),
+            // So is the buffer list.
+            ExCommand::Buffers => Ok(Some(Message::ListBuffers)),
+            ExCommand::Buffer { id } => Ok(Some(Message::SwitchBuffer(id))),
+            ExCommand::Earlier { steps } => self.travel_history(-(steps as isize)).map(|()| None),
+            ExCommand::Later { steps } => self.travel_history(steps as isize).map(|()| None),
+            ExCommand::Yank { range, register } => self.yank_lines(range, register).map(|()| None),
+        });
+        // Vim's undo states count whole commands.
+        self.buffer.borrow_mut().checkpoint(&self.multi_cursor);
+
+        result.unwrap_or_else(|message| {
+            self.message = Some(message);
+            None
+        })
+    }
+
+    /// `:y`, the lines in `range` (the cursor's without one) into `register`, linewise.
+    fn yank_lines(&mut self, range: Option<LineRange>, register: char) -> Result<(), String> {
+        let buffer = self.buffer.borrow();
+        let current = self.multi_cursor.position().line;
+        let lines = match range {
+            Some(range) => {
+                let visual = buffer.last_visual.map(|(start, end)| (start.line, end.line));
+                range.lines(current, buffer.line_count() - 1, visual)?
+            }
+            None => current..current + 1,
+        };
+
+        self.registers.set(register, buffer.lines_text(lines), RegisterKind::Linewise);
+        Ok(())
+    }
+
+    /// `:g`/`:v`, only deleting the lines (`d`) is supported for now.
+    /// NOTE: Patterns use Rust's regex syntax rather than Vim's.
+    fn global(&mut self, pattern: &str, invert: bool, command: &str) -> Result<(), String> {
+        if !matches!(command, "d" | "delete") {
+            return Err(format!("E492: Not an editor command: {command}"));
+        }
+
+        if pattern.is_empty() {
+            return Err("E35: No previous regular expression".to_string());
+        }
+
+        let regex = Regex::new(pattern).map_err(|_| format!("E383: Invalid search string: {pattern}"))?;
+        let mut buffer = self.buffer.borrow_mut();
+
+        let lines = buffer.matching_lines(&regex, invert);
+        if lines.is_empty() {
+            return Err(format!("E486: Pattern not found: {pattern}"));
+        }
+
+        // Every line goes in a single edit.
+        buffer.delete_lines(&lines, &mut self.multi_cursor);
+        self.multi_cursor.adjust_for_mode(&buffer, &EditorMode::Normal);
+
+        Ok(())
+    }
+
+    /// Applies one `:set`/`:setlocal` argument.
+    /// View options set globally go to the config and drop this buffer's override, like Vim.
+    /// Editing options are always buffer-local.
+    /// NOTE: Every pane has its own config for now, so "global" only goes as far as this editor.
+    fn set_option(&mut self, local: bool, setting: &OptionSetting) -> Result<(), String> {
+        let invalid = || format!("E474: Invalid argument: {}", setting.name);
+        let mut buffer = self.buffer.borrow_mut();
+        let options = &mut buffer.options;
+
+        let (global, overridden) = match setting.name.as_str() {
+            "wrap" => (&mut self.config.wrap, &mut options.wrap),
+            "number" | "nu" => (&mut self.config.number, &mut options.number),
+            "list" => (&mut self.config.list, &mut options.list),
+            "expandtab" | "et" => {
+                options.expand_tab = setting.value.as_bool(options.expand_tab).ok_or_else(invalid)?;
+                return Ok(());
+            }
+            "shiftwidth" | "sw" => {
+                options.shift_width = setting.value.as_number().ok_or_else(invalid)?;
+                return Ok(());
+            }
+            "tabstop" | "ts" => {
+                let width = setting.value.as_number().filter(|&n| n > 0);
+                options.tab_width = width.ok_or_else(invalid)?;
+                return Ok(());
+            }
+            "autoindent" | "ai" => {
+                options.auto_indent = setting.value.as_bool(options.auto_indent).ok_or_else(invalid)?;
+                return Ok(());
+            }
+            "numberwidth" | "nuw" => {
+                let width = setting.value.as_number().filter(|n| (1..=20).contains(n));
+                self.config.number_width = width.ok_or_else(invalid)?;
+                return Ok(());
+            }
+            "softtabstop" | "sts" => {
+                options.soft_tab_stop = setting.value.as_number().ok_or_else(invalid)?;
+                return Ok(());
+            }
+            name => return Err(format!("E518: Unknown option: {name}")),
+        };
+
+        let current = overridden.unwrap_or(*global);
+        let value = setting.value.as_bool(current).ok_or_else(invalid)?;
+
+        if local {
+            *overridden = Some(value);
+        } else {
+            *global = value;
+            *overridden = None;
+        }
+
+        Ok(())
+    }
+
     //
     // Drawing
     //
 
+    /// The focused pane's border stands out among the splits.
+    fn border_color(&self) -> Color {
+        match self.is_focused {
+            true => self.config.focused_border_color,
+            false => self.config.border_color,
+        }
+    }
+
     /// Draws the cursor depending upon the current mode.
-    fn draw_cursor(
+    /// Where `cursor` is drawn, and the grapheme shown inside it: a block over the grapheme under
+    /// it, or a bar in Insert mode. Only Insert mode and Visual block virtual space go past the last
+    /// grapheme of a line, the block never does.
+    fn cursor_bounds(
         &self,
-        renderer: &mut impl iced::advanced::text::Renderer,
         cursor: &atlas_engine::Cursor,
-        position: Point,
+        rows: &RowMap,
+        text_bounds: Rectangle,
         char_width: f32,
         line_height: f32,
+    ) -> (Rectangle, String) {
+        let pos = cursor.position();
+        let len = self.buffer.borrow().grapheme_len(pos.line);
+        let col = match self.key_engine.mode {
+            EditorMode::Insert | EditorMode::VisualBlock => cursor.virtual_col(),
+            _ => pos.col.min(len.saturating_sub(1)),
+        };
+
+        let under = if col < len {
+            self.buffer.borrow().grapheme_substring(pos.line, col, 1)
+        } else {
+            String::new()
+        };
+
+        let position =
+            self.position_to_point(TextPosition { col, ..pos }, rows, text_bounds, char_width, line_height);
+        let width = match self.key_engine.mode {
+            EditorMode::Insert => 2.0,
+            // As wide as what's under it, tabs included.
+            _ if !under.is_empty() => {
+                self.column_to_x(pos.line, col + 1, char_width) - self.column_to_x(pos.line, col, char_width)
+            }
+            _ => char_width,
+        };
+
+        let under = if under.is_empty() || under == "\t" { " ".to_string() } else { under };
+        (Rectangle::new(position, Size::new(width, line_height)), under)
+    }
+
+    fn draw_cursor(
+        &self,
+        renderer: &mut impl iced::advanced::text::Renderer,
+        cursor_bounds: Rectangle,
+        char_under_cursor: String,
         layout: iced::advanced::Layout<'_>,
     ) {
         // Do not render the cursor unless we're focusing on it.
@@ -156,36 +707,21 @@ impl Editor {
             return;
         }
 
-        let cursor_bounds = match self.key_engine.mode {
-            EditorMode::Normal | EditorMode::Visual => Rectangle {
-                x: position.x,
-                y: position.y,
-                width: char_width, // Block, basically.
-                height: line_height,
-            },
-            EditorMode::Insert => Rectangle {
-                x: position.x,
-                y: position.y,
-                width: 2.0,
-                height: line_height,
-            },
-        };
-
-        // Get character under the cursor.
-        let char_under_cursor = self
-            .buffer
-            .borrow()
-            .content
-            .get_char(cursor.position().offset)
-            .unwrap_or(' ');
-
         let cursor_background = match self.key_engine.mode {
-            EditorMode::Normal | EditorMode::Visual => Color::WHITE,
+            EditorMode::Normal
+            | EditorMode::Visual
+            | EditorMode::VisualLine
+            | EditorMode::VisualBlock
+            | EditorMode::Command => Color::WHITE,
             EditorMode::Insert => Color::WHITE,
         };
 
         let text_color = match self.key_engine.mode {
-            EditorMode::Normal | EditorMode::Visual => Color::BLACK,
+            EditorMode::Normal
+            | EditorMode::Visual
+            | EditorMode::VisualLine
+            | EditorMode::VisualBlock
+            | EditorMode::Command => Color::BLACK,
             _ => Color::WHITE,
         };
 
@@ -201,10 +737,10 @@ impl Editor {
         if self.key_engine.mode != EditorMode::Insert {
             renderer.fill_text(
                 Text {
-                    content: char_under_cursor.to_string(),
+                    content: char_under_cursor,
                     bounds: cursor_bounds.size(),
                     size: self.config.font_size,
-                    line_height: line_height.into(),
+                    line_height: self.config.line_height.into(),
                     font: renderer.default_font(),
                     horizontal_alignment: alignment::Horizontal::Center,
                     vertical_alignment: alignment::Vertical::Center,
@@ -218,88 +754,228 @@ impl Editor {
         }
     }
 
-    /// Draws the visual selection background.
-    fn draw_selection(
+    /// Horizontal position of the left edge of column `col`, scrolling along with the text.
+    fn ruler_x(&self, col: usize, bounds: Rectangle, char_width: f32) -> f32 {
+        bounds.x + col as f32 * char_width - self.scroll_offset.x
+    }
+
+    /// Draws the `color_columns` rulers.
+    fn draw_rulers(
         &self,
         renderer: &mut impl iced::advanced::text::Renderer,
         bounds: Rectangle,
         char_width: f32,
-        line_height: f32,
     ) {
-        for cursor in self.multi_cursor.all_cursors() {
-            let (start, end) = cursor.get_selection_range();
-            // Selection color.
-            let selection_color = Color::from_rgba(0.3, 0.5, 0.8, 0.3);
-
-            if start.line == end.line {
-                // Single line selection.
-                let start_x = bounds.x + (start.col as f32 * char_width - self.scroll_offset.x);
-                let start_y =
-                    bounds.y + (start.line as f32 * line_height - self.scroll_offset.y);
-                
-                // NOTE: We add 1 here to treat the upper bound as inclusive.
-                let mut width = (end.col - start.col + 1) as f32 * char_width;
-
-                // Ensure minimum width for empty selections (like newlines).
-                if width < char_width * 0.5 {
-                    width = char_width * 0.5;
-                }
+        let ruler_color = Color::from_rgba(1.0, 1.0, 1.0, 0.15);
 
-                let selection_bounds = Rectangle {
-                    x: start_x,
-                    y: start_y,
-                    width,
-                    height: line_height,
-                };
+        for &col in &self.config.color_columns {
+            let x = self.ruler_x(col, bounds, char_width);
+
+            // Scrolled out of view.
+            if x < bounds.x || x > bounds.x + bounds.width {
+                continue;
+            }
 
-                renderer.fill_quad(
-                    renderer::Quad {
-                        bounds: selection_bounds,
-                        ..Default::default()
+            renderer.fill_quad(
+                renderer::Quad {
+                    bounds: Rectangle {
+                        x,
+                        y: bounds.y,
+                        width: 1.0,
+                        height: bounds.height,
                     },
-                    selection_color,
-                );
-            } else {
-                // Multi-line selection.
-                for line in start.line..=end.line {
-                    let line_y = bounds.y + (line as f32 * line_height - self.scroll_offset.y);
-
-                    let (start_col, end_col) = if line == start.line {
-                        // First line: from start position to end of line.
-                        (start.col, self.buffer.borrow().grapheme_len(line))
-                    } else if line == end.line {
-                        // Last line: from beginning to end position.
-                        (0, end.col)
-                    } else {
-                        // Middle lines: entire line.
-                        (0, self.buffer.borrow().grapheme_len(line))
-                    };
+                    ..Default::default()
+                },
+                ruler_color,
+            );
+        }
+    }
 
-                    let start_x =
-                        bounds.x + (start_col as f32 * char_width - self.scroll_offset.x);
-                    let mut width = (end_col - start_col) as f32 * char_width;
+    /// Screen rectangles covering every cursor's selection.
+    /// Selections are normalized first, so ones made by moving left/up (active end before the
+    /// anchor) render the same as forward ones.
+    fn selection_rects(
+        &self,
+        rows: &RowMap,
+        bounds: Rectangle,
+        char_width: f32,
+        line_height: f32,
+    ) -> Vec<Rectangle> {
+        let buffer = self.buffer.borrow();
+        let mut rects = Vec::new();
+        let mut push = |line: usize, (row, row_start, cols): (usize, usize, Range<usize>)| {
+            let x = self.column_to_row_x(rows, line, cols.start, row_start, char_width);
+            let mut width = self.column_to_row_x(rows, line, cols.end, row_start, char_width) - x;
+
+            // For empty lines or zero-width selections, show at least a small highlight.
+            if width < char_width * 0.5 {
+                width = char_width * 0.5;
+            }
 
-                    // For empty lines or zero-width selections, show at least a small highlight.
-                    if width < char_width * 0.5 {
-                        width = char_width * 0.5;
-                    }
+            rects.push(Rectangle {
+                x: bounds.x + (x - self.scroll_offset.x),
+                y: bounds.y + (row as f32 * line_height - self.scroll_offset.y),
+                width,
+                height: line_height,
+            });
+        };
 
-                    let selection_bounds = Rectangle {
-                        x: start_x,
-                        y: line_y,
-                        width,
-                        height: line_height,
-                    };
-
-                    renderer.fill_quad(
-                        renderer::Quad {
-                            bounds: selection_bounds,
-                            ..Default::default()
-                        },
-                        selection_color,
-                    );
+        // A block is the same columns on every line, virtual space included.
+        if self.key_engine.mode == EditorMode::VisualBlock {
+            let (lines, cols) = self.multi_cursor.primary().block();
+            for line in lines {
+                for segment in rows.segments(line, cols.clone()) {
+                    push(line, segment);
                 }
             }
+
+            return rects;
+        }
+
+        for cursor in self.multi_cursor.all_cursors() {
+            let (start, end) = cursor.selection_range(&buffer);
+
+            // A charwise selection ending right at the start of a line leaves that line alone.
+            let last_line = match self.key_engine.mode {
+                EditorMode::VisualLine => end.line,
+                _ if end.col == 0 && end.line > start.line => end.line - 1,
+                _ => end.line,
+            };
+
+            for line in start.line..=last_line {
+                let (start_col, end_col) = if start.line == end.line {
+                    // Single line selection.
+                    // NOTE: We add 1 here to treat the upper bound as inclusive.
+                    (start.col, end.col + 1)
+                } else if line == start.line {
+                    // First line: from start position to end of line.
+                    (start.col, buffer.grapheme_len(line))
+                } else if line == end.line {
+                    // Last line: from beginning to end position.
+                    (0, end.col)
+                } else {
+                    // Middle lines: entire line.
+                    (0, buffer.grapheme_len(line))
+                };
+
+                // Wrapped lines may need a rectangle per screen row.
+                // `segments` never sees a reversed range, `start_col` can sit past a short line's end.
+                for segment in rows.segments(line, start_col..end_col.max(start_col)) {
+                    push(line, segment);
+                }
+            }
+        }
+
+        rects
+    }
+
+    /// Screen rectangle of the bracket matching the one under the primary cursor, if it's on one
+    /// of the configured `match_pairs`.
+    fn matching_bracket_rect(
+        &self,
+        rows: &RowMap,
+        bounds: Rectangle,
+        char_width: f32,
+        line_height: f32,
+    ) -> Option<Rectangle> {
+        let buffer = self.buffer.borrow();
+        let offset = self.multi_cursor.position().offset;
+        let under = buffer.char_at(offset)?;
+        if !self.config.match_pairs.iter().any(|&(open, close)| under == open || under == close) {
+            return None;
+        }
+
+        let pos = buffer.offset_to_position(buffer.matching_pair(offset, &self.config.match_pairs)?);
+        let point = self.position_to_point(pos, rows, bounds, char_width, line_height);
+        Some(Rectangle::new(point, Size::new(char_width, line_height)))
+    }
+
+    /// Draws the visual selection background.
+    fn draw_selection(
+        &self,
+        renderer: &mut impl iced::advanced::text::Renderer,
+        rows: &RowMap,
+        bounds: Rectangle,
+        char_width: f32,
+        line_height: f32,
+    ) {
+        // Selection color.
+        let selection_color = Color::from_rgba(0.3, 0.5, 0.8, 0.3);
+
+        for selection_bounds in self.selection_rects(rows, bounds, char_width, line_height) {
+            renderer.fill_quad(
+                renderer::Quad {
+                    bounds: selection_bounds,
+                    ..Default::default()
+                },
+                selection_color,
+            );
+        }
+    }
+
+    /// Draws the command line being typed, or the last message, over the bottom row. Messages
+    /// with several lines, like `:ls`, take a row for each. `warning` is shown if there's neither.
+    fn draw_command_line(
+        &self,
+        renderer: &mut impl iced::advanced::text::Renderer<Font = iced::Font>,
+        bounds: Rectangle,
+        char_width: f32,
+        line_height: f32,
+        warning: Option<&str>,
+    ) {
+        let content = if self.key_engine.mode == EditorMode::Command {
+            format!(":{}", self.key_engine.command_line)
+        } else if let Some(message) = self.message.as_deref().or(warning) {
+            message.to_string()
+        } else {
+            return;
+        };
+
+        let height = content.lines().count().max(1) as f32 * line_height;
+        let row = Rectangle {
+            y: bounds.y + bounds.height - height,
+            height,
+            ..bounds
+        };
+
+        renderer.fill_quad(
+            renderer::Quad {
+                bounds: row,
+                ..Default::default()
+            },
+            Color::from_rgb(0.1, 0.1, 0.1),
+        );
+
+        let cursor_col = content.chars().count();
+        renderer.fill_text(
+            Text {
+                content,
+                bounds: row.size(),
+                size: self.config.font_size,
+                line_height: self.config.line_height.into(),
+                font: renderer.default_font(),
+                horizontal_alignment: alignment::Horizontal::Left,
+                vertical_alignment: alignment::Vertical::Top,
+                shaping: iced::widget::text::Shaping::Basic,
+                wrapping: iced::widget::text::Wrapping::None,
+            },
+            row.position(),
+            Color::WHITE,
+            bounds,
+        );
+
+        if self.is_focused && self.key_engine.mode == EditorMode::Command {
+            renderer.fill_quad(
+                renderer::Quad {
+                    bounds: Rectangle {
+                        x: row.x + cursor_col as f32 * char_width,
+                        width: 2.0,
+                        ..row
+                    },
+                    ..Default::default()
+                },
+                Color::WHITE,
+            );
         }
     }
 }
@@ -329,9 +1005,12 @@ where
     ) -> iced::advanced::layout::Node {
         let state = tree.state.downcast_mut::<EditorState>();
 
-        if state.char_width.is_none() {
+        let font = (renderer.default_font(), self.config.font_size);
+        if state.font != Some(font) {
+            state.font_warning = self.check_monospace(renderer);
             state.char_width = Some(self.char_width(renderer));
-            state.line_height = Some(self.line_height(renderer));
+            state.line_height = Some(self.line_height());
+            state.font = Some(font);
         }
 
         state.bounds = Rectangle {
@@ -360,16 +1039,14 @@ where
         let char_w = state
             .char_width
             .unwrap_or_else(|| self.char_width(renderer));
-        let line_height = state
-            .line_height
-            .unwrap_or_else(|| self.line_height(renderer));
+        let line_height = state.line_height.unwrap_or_else(|| self.line_height());
 
         // Draw background.
         renderer.fill_quad(
             renderer::Quad {
                 bounds,
                 border: Border {
-                    color: Color::BLACK,
+                    color: self.border_color(),
                     width: 1.0,
                     radius: 0.0.into(),
                 },
@@ -378,36 +1055,70 @@ where
             Color::from_rgb(0.1, 0.1, 0.1),
         );
 
-        // Calculate visible line range.
-        let first_line = (self.scroll_offset.y / line_height).floor() as usize;
-        let visible_lines = (bounds.height / line_height).ceil() as usize;
-        let total_lines = self.buffer.borrow().content.len_lines();
-        let end_line = (first_line + visible_lines).min(total_lines);
+        let view = self.view_options();
+        let text_bounds = self.text_bounds(bounds, char_w);
+        let rows = self.row_map(text_bounds, char_w);
 
-        // Calculate visible column range.
+        // Calculate visible row range.
+        let first_row = (self.scroll_offset.y / line_height).floor() as usize;
+        let visible_rows = (bounds.height / line_height).ceil() as usize;
+
+        // Calculate visible screen column range, for lines that aren't wrapped.
         let first_col = (self.scroll_offset.x / char_w).floor() as usize;
-        let visible_cols = (bounds.width / char_w).ceil() as usize;
+        let visible_cols = (text_bounds.width / char_w).ceil() as usize;
+
+        self.draw_rulers(renderer, text_bounds, char_w);
 
         // Draw selection background.
         if self.key_engine.mode != EditorMode::Insert {
-            self.draw_selection(renderer, bounds, char_w, line_height);
-        } 
-
-        // Render each visible line.
-        for line_idx in first_line..end_line {
-            let visible_content =
-                self.buffer
-                    .borrow()
-                    .grapheme_substring(line_idx, first_col, visible_cols);
-            let y = bounds.y + (line_idx as f32 * line_height - self.scroll_offset.y);
-            let position = Point::new(bounds.x, y);
+            self.draw_selection(renderer, &rows, text_bounds, char_w, line_height);
+        }
+
+        // Highlight the bracket matching the one under the cursor.
+        if let Some(bracket_bounds) = self.matching_bracket_rect(&rows, text_bounds, char_w, line_height) {
+            renderer.fill_quad(
+                renderer::Quad {
+                    bounds: bracket_bounds,
+                    ..Default::default()
+                },
+                Color::from_rgba(1.0, 1.0, 1.0, 0.25),
+            );
+        }
+
+        // Render each visible row.
+        let number_width = self.gutter_columns().saturating_sub(1);
+        let buffer = self.buffer.borrow();
+        for row in first_row..first_row + visible_rows {
+            let Some(ScreenRow { line, cols }) = rows.row(row) else {
+                break;
+            };
+
+            // Grapheme columns to draw, and where the first one goes. When scrolled sideways the
+            // first one can be a tab that's only partly visible.
+            let (start_col, len, x) = match &cols {
+                Some(cols) => {
+                    let indent = rows.indent(line, cols.start) as f32 * char_w;
+                    (cols.start, cols.len(), text_bounds.x + indent)
+                }
+                None => {
+                    let start = buffer.col_at_display(line, first_col);
+                    let end = buffer.col_at_display(line, first_col + visible_cols) + 1;
+                    let x = self.column_to_x(line, start, char_w) - self.scroll_offset.x;
+                    (start, end - start, text_bounds.x + x)
+                }
+            };
+
+            let visible_content = self.row_text(&buffer, line, start_col, len, view.list);
+
+            let y = bounds.y + (row as f32 * line_height - self.scroll_offset.y);
+            let position = Point::new(x, y);
 
             renderer.fill_text(
                 Text {
                     content: visible_content,
-                    bounds: Size::new(bounds.width, line_height), // Size per line.
+                    bounds: Size::new(text_bounds.width, line_height), // Size per line.
                     size: self.config.font_size,
-                    line_height: 1.2.into(),
+                    line_height: self.config.line_height.into(),
                     font: renderer.default_font(),
                     horizontal_alignment: alignment::Horizontal::Left,
                     vertical_alignment: alignment::Vertical::Top,
@@ -416,22 +1127,66 @@ where
                 },
                 position,
                 iced::Color::WHITE,
-                bounds, // Clip to widget bounds.
+                text_bounds, // Clip to the text area.
+            );
+
+            // Only the first row of a wrapped line gets a number.
+            if view.number && cols.is_none_or(|cols| cols.start == 0) {
+                renderer.fill_text(
+                    Text {
+                        content: format!("{:>number_width$}", line + 1),
+                        bounds: Size::new(text_bounds.x - bounds.x, line_height),
+                        size: self.config.font_size,
+                        line_height: self.config.line_height.into(),
+                        font: renderer.default_font(),
+                        horizontal_alignment: alignment::Horizontal::Left,
+                        vertical_alignment: alignment::Vertical::Top,
+                        shaping: iced::widget::text::Shaping::Basic,
+                        wrapping: iced::widget::text::Wrapping::None,
+                    },
+                    Point::new(bounds.x, y),
+                    Color::from_rgb(0.5, 0.5, 0.5),
+                    bounds,
+                );
+            }
+        }
+        drop(buffer);
+
+        for row in self.end_of_buffer_rows(&rows, first_row..first_row + visible_rows) {
+            let y = bounds.y + (row as f32 * line_height - self.scroll_offset.y);
+            renderer.fill_text(
+                Text {
+                    content: "~".to_string(),
+                    bounds: Size::new(char_w, line_height),
+                    size: self.config.font_size,
+                    line_height: self.config.line_height.into(),
+                    font: renderer.default_font(),
+                    horizontal_alignment: alignment::Horizontal::Left,
+                    vertical_alignment: alignment::Vertical::Top,
+                    shaping: iced::widget::text::Shaping::Basic,
+                    wrapping: iced::widget::text::Wrapping::None,
+                },
+                Point::new(bounds.x, y),
+                Color::from_rgb(0.3, 0.3, 0.5),
+                bounds,
             );
         }
 
         // Draw all cursors.
         for cursor in self.multi_cursor.all_cursors() {
-            let pos = cursor.position();
-            let cursor_x = bounds.x + (pos.col as f32 * char_w - self.scroll_offset.x);
-            let cursor_y = bounds.y + (pos.line as f32 * line_height - self.scroll_offset.y);
-            self.draw_cursor(
-                renderer,
-                cursor,
-                Point::new(cursor_x, cursor_y),
-                char_w,
-                line_height,
-                layout,
+            let (cursor_bounds, under) = self.cursor_bounds(cursor, &rows, text_bounds, char_w, line_height);
+            self.draw_cursor(renderer, cursor_bounds, under, layout);
+        }
+
+        self.draw_command_line(renderer, bounds, char_w, line_height, state.font_warning.as_deref());
+
+        if state.flash_until.is_some_and(|until| Instant::now() < until) {
+            renderer.fill_quad(
+                renderer::Quad {
+                    bounds,
+                    ..Default::default()
+                },
+                Color::from_rgba(1.0, 1.0, 1.0, 0.15),
             );
         }
     }
@@ -452,35 +1207,56 @@ where
         let char_width = editor_state
             .char_width
             .unwrap_or_else(|| self.char_width(renderer));
-        let line_height = self.line_height(renderer);
+        let line_height = self.line_height();
         editor_state.bounds = layout.bounds();
 
         match event {
             Event::Mouse(mouse_event) => match mouse_event {
                 mouse::Event::ButtonPressed(mouse::Button::Left) => {
                     // If clicked inside our widget, focus. Otherwise, unfocus.
-                    if cursor.is_over(layout.bounds()) {
+                    if let Some(point) = cursor.position_over(layout.bounds()) {
                         self.is_focused = true;
+                        // Clicking a line number selects that line, dragging from there extends it.
+                        if let Some(line) = self.gutter_line_at(point, layout.bounds(), char_width, line_height) {
+                            self.select_lines(line, line);
+                            editor_state.gutter_drag = Some(line);
+                        } else {
+                            let pos = self.position_at(point, layout.bounds(), char_width, line_height);
+                            self.click(pos);
+                            editor_state.text_drag = Some(pos);
+                        }
                         return event::Status::Captured;
                     } else {
                         self.is_focused = false;
                     }
                 }
-                mouse::Event::WheelScrolled { delta } => {
-                    if cursor.is_over(layout.bounds()) {
-                        match delta {
-                            mouse::ScrollDelta::Lines { y, .. } => {
-                                self.scroll_offset.y =
-                                    (self.scroll_offset.y - y * line_height).max(0.0);
-                            }
-                            mouse::ScrollDelta::Pixels { y, .. } => {
-                                self.scroll_offset.y = (self.scroll_offset.y - y).max(0.0);
-                            }
+                mouse::Event::CursorMoved { position } => {
+                    if let Some(anchor) = editor_state.gutter_drag {
+                        let line = self.line_at_y(position.y, layout.bounds(), char_width, line_height);
+                        self.select_lines(anchor, line);
+                        self.ensure_cursor_visible(layout.bounds(), char_width, line_height);
+                        return event::Status::Captured;
+                    }
+                    if let Some(anchor) = editor_state.text_drag {
+                        let pos = self.position_at(position, layout.bounds(), char_width, line_height);
+                        // Until the mouse leaves the grapheme it was pressed on, it's a click.
+                        if pos != anchor || self.key_engine.mode == EditorMode::Visual {
+                            self.drag_to(anchor, pos);
+                            self.ensure_cursor_visible(layout.bounds(), char_width, line_height);
                         }
-
                         return event::Status::Captured;
                     }
                 }
+                mouse::Event::ButtonReleased(mouse::Button::Left) => {
+                    editor_state.gutter_drag = None;
+                    editor_state.text_drag = None;
+                }
+                mouse::Event::WheelScrolled { delta } if cursor.is_over(layout.bounds()) => {
+                    let text_bounds = self.text_bounds(layout.bounds(), char_width);
+                    let total_rows = self.row_map(text_bounds, char_width).total_rows();
+                    self.scroll_offset.y = self.wheel_scroll_offset(delta, line_height, total_rows);
+                    return event::Status::Captured;
+                }
                 _ => {}
             },
 
@@ -495,31 +1271,47 @@ where
                     return event::Status::Ignored;
                 }
 
-                let maybe_action = translate_to_keyevent(&key, &text, modifiers)
-                    .and_then(|ke| self.key_engine.handle_key(ke));
-
-                if let Some(action) = maybe_action {
-                    match action {
-                        atlas_keys::EngineAction::Action(action) => {
-                            execute(
-                                action,
-                                &mut self.buffer.borrow_mut(),
-                                &mut self.multi_cursor,
-                                &self.key_engine.mode,
-                            );
-                            self.ensure_cursor_visible(
-                                editor_state.bounds,
-                                char_width,
-                                line_height,
-                            );
-                            return event::Status::Captured;
-                        }
-                        atlas_keys::EngineAction::App(app_action) => {
-                            shell.publish(app_action);
-                            return event::Status::Captured;
+                let Some(key_event) = translate_to_keyevent(&key, &text, modifiers) else {
+                    return event::Status::Ignored;
+                };
+                editor_state.font_warning = None;
+
+                // Keys the engine hands back (see `KeyEngine::take_replay`) go first, in order.
+                let mut keys = vec![key_event];
+                let mut status = event::Status::Ignored;
+                while let Some(key_event) = keys.pop() {
+                    if let Some(action) = self.key_engine.handle_key(key_event) {
+                        match action {
+                            atlas_keys::EngineAction::Action(action) => {
+                                if !self.perform(action, editor_state.bounds, char_width) {
+                                    self.ring_bell(editor_state, shell);
+                                }
+                                self.ensure_cursor_visible(
+                                    editor_state.bounds,
+                                    char_width,
+                                    line_height,
+                                );
+                            }
+                            atlas_keys::EngineAction::App(app_action) => shell.publish(app_action),
+                            atlas_keys::EngineAction::Ex(line) => {
+                                if let Some(message) = self.run_ex_command(&line) {
+                                    shell.publish(message);
+                                }
+                                if self.message.is_some() {
+                                    self.ring_bell(editor_state, shell);
+                                }
+                                self.ensure_cursor_visible(
+                                    editor_state.bounds,
+                                    char_width,
+                                    line_height,
+                                );
+                            }
                         }
+                        status = event::Status::Captured;
                     }
+                    keys.extend(self.key_engine.take_replay().into_iter().rev());
                 }
+                return status;
             }
             _ => {}
         }
@@ -541,17 +1333,82 @@ where
     }
 }
 
+/// Whether every measured glyph width matches the first one (within a small tolerance),
+/// i.e. whether the font can be treated as monospaced.
+fn is_uniform_width(widths: &[f32]) -> bool {
+    const TOLERANCE: f32 = 0.05; // Relative to the first glyph.
+
+    let Some(&first) = widths.first() else {
+        return true;
+    };
+
+    widths
+        .iter()
+        .all(|w| (w - first).abs() <= first.abs() * TOLERANCE)
+}
+
+/// Makes whitespace visible, like Vim's `list` with `listchars=tab:> ,trail:-,nbsp:+`.
+/// Trailing spaces are only marked when `content` reaches the end of its line.
+/// Tabs are left alone for `expand_tabs`.
+fn show_whitespace(content: &str, reaches_end: bool) -> String {
+    let trailing_from = if reaches_end {
+        content.trim_end_matches(' ').len()
+    } else {
+        content.len()
+    };
+
+    content
+        .char_indices()
+        .map(|(i, c)| match c {
+            '\u{a0}' => '+',
+            ' ' if i >= trailing_from => '-',
+            c => c,
+        })
+        .collect()
+}
+
+/// Replaces tabs with spaces up to the next `tab_width` stop, `content` starting at screen column
+/// `start`. With `list` set, tabs start with a `>` instead.
+fn expand_tabs(content: &str, start: usize, tab_width: usize, list: bool) -> String {
+    let tab_width = tab_width.max(1);
+    let mut expanded = String::with_capacity(content.len());
+    let mut col = start;
+
+    for c in content.chars() {
+        if c == '\t' {
+            let width = tab_width - col % tab_width;
+            expanded.push(if list { '>' } else { ' ' });
+            expanded.extend(std::iter::repeat_n(' ', width - 1));
+            col += width;
+        } else {
+            expanded.push(c);
+            col += 1;
+        }
+    }
+
+    expanded
+}
+
 fn translate_to_keyevent(
     key: &Key,
     text: &Option<SmolStr>,
     modifiers: keyboard::Modifiers,
 ) -> Option<KeyEvent> {
-    let text_str = text.as_ref().map(|t| t.to_string());
+    // With Ctrl or Alt these are bound like any other key, e.g. `<C-Enter>`.
+    let plain = !modifiers.control() && !modifiers.alt();
+    // Prefer the text the key produced, falling back to the character itself. Either can be
+    // several bytes (`é`, emoji).
+    let text_str = text.as_ref().map(|t| t.to_string()).or_else(|| match key.as_ref() {
+        Key::Character(c) if plain => Some(c.to_string()),
+        _ => None,
+    });
 
     match key.as_ref() {
-        Key::Named(keyboard::key::Named::Escape) => Some(KeyEvent::Esc),
-        Key::Named(keyboard::key::Named::Backspace) => Some(KeyEvent::Backspace),
-        Key::Named(keyboard::key::Named::Enter) => Some(KeyEvent::Enter),
+        Key::Named(keyboard::key::Named::Escape) if plain => Some(KeyEvent::Esc),
+        Key::Named(keyboard::key::Named::Backspace) if plain => Some(KeyEvent::Backspace),
+        Key::Named(keyboard::key::Named::Enter) if plain => Some(KeyEvent::Enter),
+        // `<C-[>` is Esc, like in a terminal.
+        Key::Character("[") if modifiers.control() && !modifiers.alt() => Some(KeyEvent::Esc),
         _ => Some(KeyEvent::Key {
             key: key.clone(),
             text: text_str,
@@ -559,3 +1416,948 @@ fn translate_to_keyevent(
         }),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cursor_y_uses_configured_line_height() {
+        let mut editor = Editor::new();
+        editor.config.font_size = iced::Pixels(10.0);
+        editor.config.line_height = 1.5;
+        *editor.buffer.borrow_mut() = Buffer::new("a\nb\nc", "t");
+
+        let bounds = Rectangle::new(Point::ORIGIN, Size::new(800.0, 600.0));
+        let line_height = editor.line_height();
+        assert_eq!(line_height, 15.0);
+
+        let rows = editor.row_map(bounds, 6.0);
+        let point = editor.position_to_point(TextPosition::new(2, 0, 0), &rows, bounds, 6.0, line_height);
+        assert_eq!(point.y, 30.0);
+
+        // Changing the multiplier moves the cursor accordingly.
+        editor.config.line_height = 1.0;
+        let point =
+            editor.position_to_point(TextPosition::new(2, 0, 0), &rows, bounds, 6.0, editor.line_height());
+        assert_eq!(point.y, 20.0);
+    }
+
+    #[test]
+    fn wheel_scroll_honors_speed_and_direction() {
+        let mut editor = Editor::new();
+        *editor.buffer.borrow_mut() = Buffer::new(&"line\n".repeat(100), "t");
+        editor.scroll_offset.y = 200.0;
+        let down = mouse::ScrollDelta::Lines { x: 0.0, y: -2.0 };
+
+        // Default: two lines down.
+        assert_eq!(editor.wheel_scroll_offset(down, 10.0, 101), 220.0);
+
+        editor.config.scroll_speed = 2.5;
+        assert_eq!(editor.wheel_scroll_offset(down, 10.0, 101), 250.0);
+
+        editor.config.scroll_invert = true;
+        assert_eq!(editor.wheel_scroll_offset(down, 10.0, 101), 150.0);
+
+        // Pixel deltas are scaled the same way.
+        editor.config.scroll_invert = false;
+        editor.config.scroll_speed = 0.5;
+        let pixels = mouse::ScrollDelta::Pixels { x: 0.0, y: -4.0 };
+        assert_eq!(editor.wheel_scroll_offset(pixels, 10.0, 101), 202.0);
+    }
+
+    #[test]
+    fn wheel_scroll_is_clamped_to_buffer() {
+        let mut editor = Editor::new();
+        *editor.buffer.borrow_mut() = Buffer::new("a\nb\nc\n", "t"); // 4 lines.
+        editor.config.scroll_speed = 10.0;
+
+        let up = mouse::ScrollDelta::Lines { x: 0.0, y: 5.0 };
+        let down = mouse::ScrollDelta::Lines { x: 0.0, y: -5.0 };
+
+        assert_eq!(editor.wheel_scroll_offset(up, 10.0, 4), 0.0);
+        assert_eq!(editor.wheel_scroll_offset(down, 10.0, 4), 30.0);
+    }
+
+    #[test]
+    fn ruler_scrolls_with_text() {
+        let mut editor = Editor::new();
+        editor.config.color_columns = vec![80, 100];
+        let bounds = Rectangle::new(Point::new(10.0, 0.0), Size::new(800.0, 600.0));
+
+        assert_eq!(editor.ruler_x(80, bounds, 8.0), 650.0);
+
+        editor.scroll_offset.x = 160.0; // 20 columns.
+        assert_eq!(editor.ruler_x(80, bounds, 8.0), 490.0);
+        assert_eq!(editor.ruler_x(100, bounds, 8.0), 650.0);
+    }
+
+    #[test]
+    fn setlocal_only_affects_its_buffer() {
+        let mut a = Editor::new();
+        let b = Editor::new();
+
+        a.run_ex_command("setlocal wrap nu");
+        assert_eq!(a.message, None);
+        assert!(a.view_options().wrap);
+        assert!(a.view_options().number);
+        assert!(!b.view_options().wrap);
+        assert!(!b.view_options().number);
+
+        // `:set` changes the global value and drops the buffer's override.
+        a.run_ex_command("set nowrap");
+        assert!(!a.view_options().wrap);
+        assert_eq!(a.buffer.borrow().options.wrap, None);
+    }
+
+    #[test]
+    fn filetype_defaults_layer_under_set() {
+        let mut editor = Editor::new();
+        *editor.buffer.borrow_mut() = Buffer::new("# notes", "notes.md");
+        assert!(editor.view_options().wrap);
+
+        editor.run_ex_command("setl wrap!");
+        assert!(!editor.view_options().wrap);
+
+        assert!(editor.buffer.borrow().options.auto_indent);
+        editor.run_ex_command("set noai");
+        assert!(!editor.buffer.borrow().options.auto_indent);
+    }
+
+    #[test]
+    fn bad_commands_are_reported() {
+        let mut editor = Editor::new();
+
+        editor.run_ex_command("set bogus");
+        assert_eq!(editor.message.as_deref(), Some("E518: Unknown option: bogus"));
+
+        editor.run_ex_command("set sw=2 ts=0");
+        assert!(editor.message.as_deref().is_some_and(|m| m.starts_with("E474")));
+        assert_eq!(editor.buffer.borrow().options.shift_width, 2);
+    }
+
+    #[test]
+    fn earlier_and_later_step_through_undo_states() {
+        let mut editor = Editor::new();
+        *editor.buffer.borrow_mut() = Buffer::new("abc\n", "t");
+
+        // Two deletes and an Insert session, which counts once.
+        press(&mut editor, "xxiz");
+        if let Some(atlas_keys::EngineAction::Action(action)) = editor.key_engine.handle_key(KeyEvent::Esc) {
+            editor.perform(action, Rectangle::default(), 10.0);
+        }
+        assert_eq!(editor.buffer.borrow().content.to_string(), "zc\n");
+
+        editor.run_ex_command("earlier 2");
+        assert_eq!(editor.buffer.borrow().content.to_string(), "bc\n");
+        editor.run_ex_command("later");
+        assert_eq!(editor.buffer.borrow().content.to_string(), "c\n");
+        editor.run_ex_command("earlier 9");
+        assert_eq!(editor.buffer.borrow().content.to_string(), "abc\n");
+        assert!(editor.buffer.borrow().modified);
+
+        editor.run_ex_command("earlier");
+        assert_eq!(editor.message.as_deref(), Some("Already at oldest change"));
+
+        // A new change drops the states ahead.
+        press(&mut editor, "x");
+        editor.run_ex_command("later");
+        assert_eq!(editor.message.as_deref(), Some("Already at newest change"));
+        assert_eq!(editor.buffer.borrow().content.to_string(), "bc\n");
+    }
+
+    #[test]
+    fn global_deletes_matching_lines() {
+        let mut editor = Editor::new();
+        *editor.buffer.borrow_mut() = Buffer::new("fn a() {}\n// note\nfn b() {}\n// todo\n", "t");
+
+        editor.run_ex_command("g/^\\/\\//d");
+        assert_eq!(editor.message, None);
+        assert_eq!(editor.buffer.borrow().content.to_string(), "fn a() {}\nfn b() {}\n");
+
+        editor.run_ex_command("v/a/d");
+        assert_eq!(editor.buffer.borrow().content.to_string(), "fn a() {}\n");
+
+        editor.run_ex_command("g/zzz/d");
+        assert_eq!(editor.message.as_deref(), Some("E486: Pattern not found: zzz"));
+
+        editor.run_ex_command("g/a/normal @q");
+        assert!(editor.message.as_deref().is_some_and(|m| m.starts_with("E492")));
+    }
+
+    #[test]
+    fn wrapped_cursor_moves_to_its_row() {
+        let mut editor = Editor::new();
+        *editor.buffer.borrow_mut() = Buffer::new(&"x".repeat(25), "t");
+        editor.config.wrap = true;
+
+        // 10 columns wide.
+        let bounds = Rectangle::new(Point::ORIGIN, Size::new(100.0, 600.0));
+        let rows = editor.row_map(bounds, 10.0);
+        let point = editor.position_to_point(TextPosition::new(0, 23, 23), &rows, bounds, 10.0, 20.0);
+        assert_eq!(point, Point::new(30.0, 40.0));
+    }
+
+    #[test]
+    fn wrap_column_overrides_the_window_width() {
+        let mut editor = Editor::new();
+        *editor.buffer.borrow_mut() = Buffer::new(&"x".repeat(25), "t");
+        editor.config.wrap = true;
+        editor.config.wrap_column = Some(10);
+
+        // 30 columns wide, still 10 columns per row.
+        let bounds = Rectangle::new(Point::ORIGIN, Size::new(300.0, 600.0));
+        let rows = editor.row_map(bounds, 10.0);
+        assert_eq!(rows.total_rows(), 3);
+        assert_eq!(rows.row_cols(0, 12), Some(10..20));
+
+        // A narrower window wraps at its own width.
+        let bounds = Rectangle::new(Point::ORIGIN, Size::new(50.0, 600.0));
+        assert_eq!(editor.row_map(bounds, 10.0).total_rows(), 5);
+
+        // Only while wrapping.
+        editor.config.wrap = false;
+        assert_eq!(editor.row_map(bounds, 10.0).total_rows(), 1);
+    }
+
+    #[test]
+    fn break_indent_shifts_continuation_rows() {
+        let mut editor = Editor::new();
+        *editor.buffer.borrow_mut() = Buffer::new(&format!("  {}", "x".repeat(20)), "t");
+        editor.config.wrap = true;
+        editor.config.break_indent = true;
+
+        // 10 columns wide, continuation rows get 8 after the indent: 0..10, 10..18, 18..22.
+        let bounds = Rectangle::new(Point::ORIGIN, Size::new(100.0, 600.0));
+        let rows = editor.row_map(bounds, 10.0);
+        let point = editor.position_to_point(TextPosition::new(0, 19, 19), &rows, bounds, 10.0, 20.0);
+        assert_eq!(point, Point::new(30.0, 40.0));
+
+        let point = editor.position_to_point(TextPosition::new(0, 10, 10), &rows, bounds, 10.0, 20.0);
+        assert_eq!(point, Point::new(20.0, 20.0));
+    }
+
+    #[test]
+    fn row_motions_stop_at_screen_rows() {
+        let mut editor = Editor::new();
+        // 80 columns wide in `press`, so this line takes two rows.
+        *editor.buffer.borrow_mut() = Buffer::new(&"x".repeat(100), "t");
+
+        // Without wrap `g$` is `$`.
+        press(&mut editor, "g$");
+        assert_eq!(editor.multi_cursor.position().col, 99);
+
+        editor.config.wrap = true;
+        press(&mut editor, "0lllg$");
+        assert_eq!(editor.multi_cursor.position().col, 79);
+        press(&mut editor, "l");
+        assert_eq!(editor.multi_cursor.position().col, 80);
+        press(&mut editor, "g$");
+        assert_eq!(editor.multi_cursor.position().col, 99);
+        press(&mut editor, "hhg0");
+        assert_eq!(editor.multi_cursor.position().col, 80);
+        press(&mut editor, "$");
+        assert_eq!(editor.multi_cursor.position().col, 99);
+        press(&mut editor, "0");
+        assert_eq!(editor.multi_cursor.position().col, 0);
+    }
+
+    #[test]
+    fn tabs_expand_to_tab_stops() {
+        assert_eq!(expand_tabs("\ta\tb", 0, 4, false), "    a   b");
+        // Starting mid-line, the first tab only reaches the next stop.
+        assert_eq!(expand_tabs("\tb", 2, 4, false), "  b");
+        assert_eq!(expand_tabs("a\tb", 0, 4, true), "a>  b");
+    }
+
+    #[test]
+    fn column_and_x_round_trip_over_tabs() {
+        let editor = Editor::new();
+        *editor.buffer.borrow_mut() = Buffer::new("\tif x {\t// y", "t");
+        editor.buffer.borrow_mut().options.tab_width = 4;
+
+        assert_eq!(editor.column_to_x(0, 0, 10.0), 0.0);
+        assert_eq!(editor.column_to_x(0, 1, 10.0), 40.0);
+        assert_eq!(editor.column_to_x(0, 8, 10.0), 120.0); // After "if x {" and the second tab.
+
+        for col in 0..=12 {
+            let x = editor.column_to_x(0, col, 10.0);
+            assert_eq!(editor.x_to_column(0, x, 10.0), col, "col {col}");
+            // Anywhere within the cell works too.
+            assert_eq!(editor.x_to_column(0, x + 9.0, 10.0), col, "col {col}");
+        }
+
+        // Every cell covered by a tab is the tab.
+        assert_eq!(editor.x_to_column(0, 25.0, 10.0), 0);
+        assert_eq!(editor.x_to_column(0, -5.0, 10.0), 0);
+    }
+
+    #[test]
+    fn selection_over_tabs_covers_expanded_width() {
+        let mut editor = Editor::new();
+        *editor.buffer.borrow_mut() = Buffer::new("\tab", "t");
+        editor.buffer.borrow_mut().options.tab_width = 4;
+
+        press(&mut editor, "vl");
+        assert_eq!(selection_rects(&editor), vec![Rectangle::new(Point::ORIGIN, Size::new(50.0, 20.0))]);
+    }
+
+    #[test]
+    fn list_is_toggled_per_buffer_and_drawn() {
+        let mut a = Editor::new();
+        *a.buffer.borrow_mut() = Buffer::new("\tx  \n", "t");
+        let b = Editor::new();
+        let drawn = |editor: &Editor| {
+            let buffer = editor.buffer.borrow();
+            editor.row_text(&buffer, 0, 0, 4, editor.view_options().list)
+        };
+        assert_eq!(drawn(&a), "    x  ");
+
+        a.run_ex_command("setlocal list");
+        assert_eq!(a.buffer.borrow().options.list, Some(true));
+        assert!(!b.view_options().list);
+        assert_eq!(drawn(&a), ">   x--");
+
+        a.run_ex_command("setl nolist");
+        assert_eq!(a.buffer.borrow().options.list, Some(false));
+        assert_eq!(drawn(&a), "    x  ");
+
+        // `:set` drops the override for the global value.
+        a.run_ex_command("set list");
+        assert_eq!(a.buffer.borrow().options.list, None);
+        assert_eq!(drawn(&a), ">   x--");
+    }
+
+    #[test]
+    fn rows_past_the_end_get_a_marker() {
+        let mut editor = Editor::new();
+        *editor.buffer.borrow_mut() = Buffer::new("one\ntwo", "t");
+        let bounds = Rectangle::new(Point::ORIGIN, Size::new(100.0, 600.0));
+        let rows = editor.row_map(bounds, 10.0);
+
+        assert_eq!(editor.end_of_buffer_rows(&rows, 0..30), 2..30);
+        // Scrolled past the end, every row.
+        assert_eq!(editor.end_of_buffer_rows(&rows, 5..35), 5..35);
+
+        // Wrapped rows are text too.
+        editor.config.wrap = true;
+        editor.buffer.borrow_mut().content.insert(0, &"x".repeat(25));
+        let rows = editor.row_map(bounds, 10.0);
+        assert_eq!(editor.end_of_buffer_rows(&rows, 0..30), 4..30);
+
+        editor.config.end_of_buffer_markers = false;
+        assert!(editor.end_of_buffer_rows(&rows, 0..30).is_empty());
+    }
+
+    #[test]
+    fn list_marks_tabs_and_trailing_spaces() {
+        assert_eq!(expand_tabs(&show_whitespace("\ta b  ", true), 0, 4, true), ">   a b--");
+        assert_eq!(show_whitespace("\ta b  ", false), "\ta b  ");
+        assert_eq!(show_whitespace("a\u{a0}b", true), "a+b");
+    }
+
+    /// Drives an `Editor` through `on_event` the way iced does, in an 800 by 600 window with 10 by
+    /// 20 pixel cells. Unlike `press`, this goes through the widget's own input handling.
+    struct Harness {
+        editor: Editor,
+        tree: Tree,
+        node: layout::Node,
+        messages: Vec<Message>,
+    }
+
+    impl Harness {
+        fn new(content: &str) -> Self {
+            let mut editor = Editor::new();
+            *editor.buffer.borrow_mut() = Buffer::new(content, "t");
+            editor.config.font_size = iced::Pixels(10.0);
+            editor.config.line_height = 2.0;
+
+            // Measuring needs the font, the cached width stands in for it.
+            let mut tree = Tree::new(&editor as &dyn Widget<Message, Theme, ()>);
+            tree.state.downcast_mut::<EditorState>().char_width = Some(10.0);
+
+            let node = layout::Node::new(Size::new(800.0, 600.0));
+            Self { editor, tree, node, messages: Vec::new() }
+        }
+
+        fn event(&mut self, event: Event, cursor: mouse::Cursor) -> event::Status {
+            let viewport = self.node.bounds();
+            Widget::<Message, Theme, ()>::on_event(
+                &mut self.editor,
+                &mut self.tree,
+                event,
+                Layout::new(&self.node),
+                cursor,
+                &(),
+                &mut iced::advanced::clipboard::Null,
+                &mut Shell::new(&mut self.messages),
+                &viewport,
+            )
+        }
+
+        fn key(&mut self, key: Key, text: Option<&str>, modifiers: keyboard::Modifiers) -> event::Status {
+            let event = keyboard::Event::KeyPressed {
+                key: key.clone(),
+                modified_key: key,
+                physical_key: keyboard::key::Physical::Unidentified(keyboard::key::NativeCode::Unidentified),
+                location: keyboard::Location::Standard,
+                modifiers,
+                text: text.map(SmolStr::new),
+            };
+            self.event(Event::Keyboard(event), mouse::Cursor::Unavailable)
+        }
+
+        fn typed(&mut self, keys: &str) {
+            for c in keys.chars() {
+                self.key(Key::Character(c.to_string().into()), Some(&c.to_string()), keyboard::Modifiers::empty());
+            }
+        }
+
+        fn mouse(&mut self, event: mouse::Event, at: Point) -> event::Status {
+            self.event(Event::Mouse(event), mouse::Cursor::Available(at))
+        }
+
+        fn content(&self) -> String {
+            self.editor.buffer.borrow().content.to_string()
+        }
+    }
+
+    #[test]
+    fn keys_reach_the_buffer_once_focused() {
+        let mut harness = Harness::new("hello\n");
+        let none = keyboard::Modifiers::empty();
+
+        assert_eq!(harness.key(Key::Character("x".into()), Some("x"), none), event::Status::Ignored);
+        assert_eq!(harness.content(), "hello\n");
+
+        let click = mouse::Event::ButtonPressed(mouse::Button::Left);
+        assert_eq!(harness.mouse(click, Point::new(5.0, 10.0)), event::Status::Captured);
+        assert_eq!(harness.key(Key::Character("x".into()), Some("x"), none), event::Status::Captured);
+        assert_eq!(harness.content(), "ello\n");
+
+        // Typing in Insert mode, and `<C-[>` back out of it.
+        harness.typed("i!");
+        harness.key(Key::Character("[".into()), None, keyboard::Modifiers::CTRL);
+        assert_eq!(harness.editor.key_engine.mode, EditorMode::Normal);
+        assert_eq!(harness.content(), "!ello\n");
+
+        // `:vnew` comes out as a message for the app.
+        harness.typed(":vnew");
+        harness.key(Key::Named(keyboard::key::Named::Enter), None, none);
+        assert!(matches!(harness.messages.as_slice(), [Message::SplitVertical]));
+    }
+
+    #[test]
+    fn failed_motions_and_commands_flash_with_visual_bell() {
+        let mut harness = Harness::new("hello\n");
+        harness.mouse(mouse::Event::ButtonPressed(mouse::Button::Left), Point::new(100.0, 10.0));
+        let flashing = |harness: &Harness| harness.tree.state.downcast_ref::<EditorState>().flash_until.is_some();
+
+        // Off by default.
+        harness.typed("h");
+        assert!(!flashing(&harness));
+
+        harness.editor.config.visual_bell = true;
+        harness.typed("l");
+        assert!(!flashing(&harness));
+        harness.typed("0h");
+        assert!(flashing(&harness));
+
+        harness.tree.state.downcast_mut::<EditorState>().flash_until = None;
+        harness.typed(":nope");
+        harness.key(Key::Named(keyboard::key::Named::Enter), None, keyboard::Modifiers::empty());
+        assert!(flashing(&harness));
+        assert!(harness.editor.message.is_some());
+    }
+
+    #[test]
+    fn yank_command_copies_line_ranges() {
+        let mut harness = Harness::new("one\ntwo\nthree\nfour\n");
+        let enter = |harness: &mut Harness| {
+            harness.key(Key::Named(keyboard::key::Named::Enter), None, keyboard::Modifiers::empty());
+        };
+        harness.mouse(mouse::Event::ButtonPressed(mouse::Button::Left), Point::new(100.0, 10.0));
+
+        harness.typed(":%y a");
+        enter(&mut harness);
+        assert_eq!(harness.editor.registers.get('a'), Some(("one\ntwo\nthree\nfour\n", RegisterKind::Linewise)));
+
+        // From Visual mode the command line starts with the selected lines.
+        harness.typed("jvj:");
+        assert_eq!(harness.editor.key_engine.command_line, "'<,'>");
+        harness.typed("y");
+        enter(&mut harness);
+        assert_eq!(harness.editor.registers.get('"'), Some(("two\nthree\n", RegisterKind::Linewise)));
+        assert_eq!(harness.editor.key_engine.mode, EditorMode::Normal);
+        assert!(!harness.editor.multi_cursor.primary().has_selection());
+
+        // The marks stay for later.
+        harness.typed("gg:'<,'>y");
+        enter(&mut harness);
+        assert_eq!(harness.editor.registers.unnamed(), "two\nthree\n");
+        harness.typed(":5y");
+        enter(&mut harness);
+        assert_eq!(harness.editor.message.as_deref(), Some("E16: Invalid range"));
+    }
+
+    #[test]
+    fn gutter_clicks_and_drags_go_through_on_event() {
+        let mut harness = Harness::new("one\ntwo\nthree\nfour\n");
+        harness.editor.config.number = true;
+
+        harness.mouse(mouse::Event::ButtonPressed(mouse::Button::Left), Point::new(5.0, 25.0));
+        assert!(harness.editor.is_focused);
+        assert_eq!(harness.editor.key_engine.mode, EditorMode::VisualLine);
+        assert_eq!(harness.editor.buffer.borrow().selection_text(harness.editor.multi_cursor.primary()), "two\n");
+
+        // Dragging works anywhere, it's the height that picks the line.
+        harness.mouse(mouse::Event::CursorMoved { position: Point::new(300.0, 45.0) }, Point::new(300.0, 45.0));
+        assert_eq!(harness.editor.buffer.borrow().selection_text(harness.editor.multi_cursor.primary()), "two\nthree\n");
+
+        // Once released, moving the mouse leaves the selection alone.
+        harness.mouse(mouse::Event::ButtonReleased(mouse::Button::Left), Point::new(300.0, 45.0));
+        harness.mouse(mouse::Event::CursorMoved { position: Point::new(5.0, 65.0) }, Point::new(5.0, 65.0));
+        assert_eq!(harness.editor.buffer.borrow().selection_text(harness.editor.multi_cursor.primary()), "two\nthree\n");
+
+        // A click on the text itself doesn't select lines.
+        harness.key(Key::Named(keyboard::key::Named::Escape), None, keyboard::Modifiers::empty());
+        harness.mouse(mouse::Event::ButtonPressed(mouse::Button::Left), Point::new(100.0, 65.0));
+        assert_eq!(harness.editor.key_engine.mode, EditorMode::Normal);
+    }
+
+    #[test]
+    fn dragging_over_the_text_selects_in_visual_mode() {
+        let mut harness = Harness::new("one two\nthree four\n");
+        let selection = |harness: &Harness| {
+            harness.editor.buffer.borrow().selection_text(harness.editor.multi_cursor.primary())
+        };
+        let moved = |position| mouse::Event::CursorMoved { position };
+
+        // From "two" on the first line to "three" on the second, both ends included.
+        harness.mouse(mouse::Event::ButtonPressed(mouse::Button::Left), Point::new(45.0, 10.0));
+        assert_eq!(harness.editor.multi_cursor.position(), TextPosition::new(0, 4, 4));
+        harness.mouse(moved(Point::new(47.0, 12.0)), Point::new(47.0, 12.0));
+        assert_eq!(harness.editor.key_engine.mode, EditorMode::Normal);
+        harness.mouse(moved(Point::new(25.0, 30.0)), Point::new(25.0, 30.0));
+        harness.mouse(mouse::Event::ButtonReleased(mouse::Button::Left), Point::new(25.0, 30.0));
+        assert_eq!(harness.editor.key_engine.mode, EditorMode::Visual);
+        assert_eq!(selection(&harness), "two\nthr");
+
+        // Operators act on it.
+        harness.typed("d");
+        assert_eq!(harness.content(), "one ee four\n");
+        assert_eq!(harness.editor.key_engine.mode, EditorMode::Normal);
+
+        // A click without a drag leaves Visual mode, past the end of a line it's on the last character.
+        harness.typed("v");
+        harness.mouse(mouse::Event::ButtonPressed(mouse::Button::Left), Point::new(300.0, 10.0));
+        harness.mouse(mouse::Event::ButtonReleased(mouse::Button::Left), Point::new(300.0, 10.0));
+        assert_eq!(harness.editor.key_engine.mode, EditorMode::Normal);
+        assert!(!harness.editor.multi_cursor.primary().has_selection());
+        assert_eq!(harness.editor.multi_cursor.position().col, 10);
+    }
+
+    #[test]
+    fn home_end_and_page_keys_move_in_every_mode() {
+        use keyboard::key::Named;
+        let content: String = (0..100).map(|i| format!("line {i}\n")).collect();
+        let mut harness = Harness::new(&content);
+        let none = keyboard::Modifiers::empty();
+        harness.mouse(mouse::Event::ButtonPressed(mouse::Button::Left), Point::new(100.0, 10.0));
+
+        let page_down = translate_to_keyevent(&Key::Named(Named::PageDown), &None, none);
+        assert!(matches!(page_down, Some(KeyEvent::Key { key: Key::Named(Named::PageDown), .. })));
+
+        // 30 rows fit the window, a page keeps two of them.
+        harness.key(Key::Named(Named::PageDown), None, none);
+        assert_eq!(harness.editor.multi_cursor.position().line, 31);
+        assert_eq!(harness.editor.scroll_offset.y, 28.0 * 20.0);
+        harness.key(Key::Named(Named::PageUp), None, none);
+        assert_eq!(harness.editor.multi_cursor.position().line, 3);
+        assert_eq!(harness.editor.scroll_offset.y, 0.0);
+
+        harness.typed("gg");
+        harness.key(Key::Named(Named::End), None, none);
+        assert_eq!(harness.editor.multi_cursor.position().col, 5);
+        harness.key(Key::Named(Named::Home), None, none);
+        assert_eq!(harness.editor.multi_cursor.position().col, 0);
+
+        // In Insert mode they move instead of typing, `<End>` goes past the last character.
+        harness.typed("i");
+        harness.key(Key::Named(Named::End), None, none);
+        harness.typed("!");
+        harness.key(Key::Named(Named::Home), None, none);
+        harness.typed(">");
+        assert!(harness.content().starts_with(">line 0!\nline 1\n"));
+
+        harness.key(Key::Named(Named::PageDown), None, none);
+        assert_eq!(harness.editor.key_engine.mode, EditorMode::Insert);
+        assert_eq!(harness.editor.multi_cursor.position().line, 31);
+    }
+
+    #[test]
+    fn a_count_sets_how_far_ctrl_d_and_ctrl_u_scroll() {
+        let content: String = (0..100).map(|i| format!("line {i}\n")).collect();
+        let mut harness = Harness::new(&content);
+        harness.mouse(mouse::Event::ButtonPressed(mouse::Button::Left), Point::new(100.0, 10.0));
+        let ctrl = |harness: &mut Harness, c: &str| {
+            harness.key(Key::Character(c.into()), None, keyboard::Modifiers::CTRL);
+            (harness.editor.scroll_offset.y / 20.0, harness.editor.multi_cursor.position().line)
+        };
+
+        // Half of the 30 rows to begin with, the cursor kept clear of the top margin.
+        assert_eq!(ctrl(&mut harness, "d"), (15.0, 18));
+
+        harness.typed("10");
+        assert_eq!(ctrl(&mut harness, "d"), (25.0, 28));
+        assert_eq!(harness.editor.scroll_lines, Some(10));
+        // The count is remembered, both ways.
+        assert_eq!(ctrl(&mut harness, "d"), (35.0, 38));
+        assert_eq!(ctrl(&mut harness, "u"), (25.0, 28));
+    }
+
+    #[test]
+    fn tilde_toggles_case_at_every_cursor_as_one_change() {
+        let mut harness = Harness::new("a1é-ß x\nBc\n");
+        harness.mouse(mouse::Event::ButtonPressed(mouse::Button::Left), Point::new(100.0, 10.0));
+        harness.typed("0");
+        {
+            let editor = &mut harness.editor;
+            let buffer = editor.buffer.borrow();
+            editor.multi_cursor.add_cursor(TextPosition::new(1, 0, 8), &buffer);
+        }
+        let cols = |harness: &Harness| {
+            harness.editor.multi_cursor.all_cursors().iter().map(|cursor| cursor.position().col).collect::<Vec<_>>()
+        };
+
+        // Characters without case are skipped over, and the count stops at the end of the line.
+        harness.typed("3~");
+        assert_eq!(harness.content(), "A1É-ß x\nbC\n");
+        assert_eq!(cols(&harness), [3, 1]);
+        harness.typed("~");
+        assert_eq!(harness.content(), "A1É-ß x\nbc\n");
+        assert_eq!(cols(&harness), [4, 1]);
+        // `ß` has no single character capital to become.
+        harness.typed("~");
+        assert_eq!(harness.content(), "A1É-ß x\nbC\n");
+        assert_eq!(cols(&harness), [5, 1]);
+
+        harness.typed("u");
+        assert_eq!(harness.content(), "A1É-ß x\nbc\n");
+        harness.typed("uu");
+        assert_eq!(harness.content(), "a1é-ß x\nBc\n");
+    }
+
+    #[test]
+    fn u_and_ctrl_r_undo_whole_inserts_and_put_the_cursors_back() {
+        let mut harness = Harness::new("one\ntwo\n");
+        harness.mouse(mouse::Event::ButtonPressed(mouse::Button::Left), Point::new(100.0, 10.0));
+        harness.typed("0l");
+        {
+            let editor = &mut harness.editor;
+            let buffer = editor.buffer.borrow();
+            editor.multi_cursor.add_cursor(TextPosition::new(1, 1, 5), &buffer);
+        }
+        let cursors = |harness: &Harness| {
+            let positions = harness.editor.multi_cursor.all_cursors().iter().map(|cursor| cursor.position());
+            positions.map(|pos| (pos.line, pos.col)).collect::<Vec<_>>()
+        };
+
+        harness.typed("iab");
+        harness.key(Key::Named(keyboard::key::Named::Escape), None, keyboard::Modifiers::empty());
+        harness.typed("0");
+        assert_eq!(harness.content(), "oabne\ntabwo\n");
+
+        harness.typed("u");
+        assert_eq!(harness.content(), "one\ntwo\n");
+        assert_eq!(cursors(&harness), [(0, 1), (1, 1)]);
+
+        // Redoing puts them back where the insert left them.
+        harness.key(Key::Character("r".into()), None, keyboard::Modifiers::CTRL);
+        assert_eq!(harness.content(), "oabne\ntabwo\n");
+        assert_eq!(cursors(&harness), [(0, 3), (1, 3)]);
+
+        // Nothing left to redo.
+        harness.key(Key::Character("r".into()), None, keyboard::Modifiers::CTRL);
+        assert_eq!(harness.content(), "oabne\ntabwo\n");
+    }
+
+    /// Feeds `keys` through the editor's key engine, executing every resulting action.
+    fn press(editor: &mut Editor, keys: &str) {
+        let mut events: Vec<KeyEvent> = keys
+            .chars()
+            .rev()
+            .map(|c| KeyEvent::Key {
+                key: Key::Character(c.to_string().into()),
+                text: Some(c.to_string()),
+                modifiers: keyboard::Modifiers::empty(),
+            })
+            .collect();
+
+        while let Some(event) = events.pop() {
+            if let Some(atlas_keys::EngineAction::Action(action)) = editor.key_engine.handle_key(event) {
+                editor.perform(action, Rectangle::new(Point::ORIGIN, Size::new(800.0, 600.0)), 10.0);
+            }
+            events.extend(editor.key_engine.take_replay().into_iter().rev());
+        }
+    }
+
+    fn selection_rects(editor: &Editor) -> Vec<Rectangle> {
+        let bounds = Rectangle::new(Point::ORIGIN, Size::new(800.0, 600.0));
+        let rows = editor.row_map(bounds, 10.0);
+        editor.selection_rects(&rows, bounds, 10.0, 20.0)
+    }
+
+    fn cursor_bounds(editor: &Editor) -> (Rectangle, String) {
+        let bounds = Rectangle::new(Point::ORIGIN, Size::new(800.0, 600.0));
+        let rows = editor.row_map(bounds, 10.0);
+        editor.cursor_bounds(editor.multi_cursor.primary(), &rows, bounds, 10.0, 20.0)
+    }
+
+    #[test]
+    fn ctrl_keys_reach_the_keymap() {
+        let ctrl = keyboard::Modifiers::CTRL;
+        let enter = Key::Named(keyboard::key::Named::Enter);
+
+        assert!(matches!(translate_to_keyevent(&enter, &None, keyboard::Modifiers::empty()), Some(KeyEvent::Enter)));
+        assert!(matches!(translate_to_keyevent(&enter, &None, ctrl), Some(KeyEvent::Key { .. })));
+        assert!(matches!(translate_to_keyevent(&Key::Character("[".into()), &None, ctrl), Some(KeyEvent::Esc)));
+    }
+
+    #[test]
+    fn multi_byte_characters_carry_their_text() {
+        let none = keyboard::Modifiers::empty();
+
+        let event = translate_to_keyevent(&Key::Character("é".into()), &None, none);
+        assert!(matches!(event, Some(KeyEvent::Key { text: Some(t), .. }) if t == "é"));
+
+        let event = translate_to_keyevent(&Key::Character("👍".into()), &Some("👍".into()), none);
+        assert!(matches!(event, Some(KeyEvent::Key { text: Some(t), .. }) if t == "👍"));
+
+        // Ctrl combinations don't type anything.
+        let event = translate_to_keyevent(&Key::Character("é".into()), &None, keyboard::Modifiers::CTRL);
+        assert!(matches!(event, Some(KeyEvent::Key { text: None, .. })));
+    }
+
+    #[test]
+    fn cursor_at_end_of_line() {
+        let mut editor = Editor::new();
+        assert_eq!(cursor_bounds(&editor), (Rectangle::new(Point::ORIGIN, Size::new(10.0, 20.0)), " ".into()));
+
+        *editor.buffer.borrow_mut() = Buffer::new("abc\n\tx", "t");
+        editor.buffer.borrow_mut().options.tab_width = 4;
+        press(&mut editor, "$");
+        let last = (Rectangle::new(Point::new(20.0, 0.0), Size::new(10.0, 20.0)), "c".to_string());
+        assert_eq!(cursor_bounds(&editor), last);
+
+        // The bar goes right after the last character.
+        editor.key_engine.mode = EditorMode::Insert;
+        editor.multi_cursor.move_to_line_end(&editor.buffer.borrow(), &EditorMode::Insert);
+        assert_eq!(editor.multi_cursor.position().col, 3);
+        let bar = (Rectangle::new(Point::new(30.0, 0.0), Size::new(2.0, 20.0)), " ".to_string());
+        assert_eq!(cursor_bounds(&editor), bar);
+
+        // Back in Normal mode the block stays on the last character, even before the cursor is
+        // clamped.
+        editor.key_engine.mode = EditorMode::Normal;
+        assert_eq!(cursor_bounds(&editor), last);
+
+        // A block over a tab covers all of it.
+        press(&mut editor, "j0");
+        assert_eq!(cursor_bounds(&editor), (Rectangle::new(Point::new(0.0, 20.0), Size::new(40.0, 20.0)), " ".into()));
+    }
+
+    #[test]
+    fn leftward_selection_renders_like_forward_one() {
+        let mut editor = Editor::new();
+        *editor.buffer.borrow_mut() = Buffer::new("hello world", "t");
+
+        press(&mut editor, "llllvhhh");
+        assert!(editor.multi_cursor.primary().has_selection());
+
+        // Columns 1 through 4, inclusive.
+        assert_eq!(selection_rects(&editor), vec![Rectangle::new(Point::new(10.0, 0.0), Size::new(40.0, 20.0))]);
+    }
+
+    #[test]
+    fn upward_multi_line_selection_renders() {
+        let mut editor = Editor::new();
+        *editor.buffer.borrow_mut() = Buffer::new("abc\nlonger line\nxy", "t");
+
+        // Anchor at line 2, col 1, then move up twice, landing on line 0 col 1.
+        press(&mut editor, "jjlvkk");
+        let (start, end) = editor.multi_cursor.primary().get_selection_range();
+        assert_eq!((start.line, end.line), (0, 2));
+
+        assert_eq!(
+            selection_rects(&editor),
+            vec![
+                Rectangle::new(Point::new(10.0, 0.0), Size::new(20.0, 20.0)),
+                Rectangle::new(Point::new(0.0, 20.0), Size::new(110.0, 20.0)),
+                Rectangle::new(Point::new(0.0, 40.0), Size::new(10.0, 20.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn selection_ending_at_a_line_start_skips_that_line() {
+        let mut editor = Editor::new();
+        *editor.buffer.borrow_mut() = Buffer::new("abc\ndef\n\nx", "t");
+
+        press(&mut editor, "lvjh");
+        assert_eq!(editor.multi_cursor.position().col, 0);
+        assert_eq!(selection_rects(&editor), vec![Rectangle::new(Point::new(10.0, 0.0), Size::new(20.0, 20.0))]);
+
+        // Whole lines are whole lines, an empty one still gets its sliver.
+        let shift_v = KeyEvent::Key {
+            key: Key::Character("V".into()),
+            text: Some("V".to_string()),
+            modifiers: keyboard::Modifiers::SHIFT,
+        };
+        if let Some(atlas_keys::EngineAction::Action(action)) = editor.key_engine.handle_key(shift_v) {
+            editor.perform(action, Rectangle::new(Point::ORIGIN, Size::new(800.0, 600.0)), 10.0);
+        }
+        assert_eq!(editor.key_engine.mode, EditorMode::VisualLine);
+        press(&mut editor, "j");
+        assert_eq!(
+            selection_rects(&editor),
+            vec![
+                Rectangle::new(Point::new(0.0, 0.0), Size::new(30.0, 20.0)),
+                Rectangle::new(Point::new(0.0, 20.0), Size::new(30.0, 20.0)),
+                Rectangle::new(Point::new(0.0, 40.0), Size::new(5.0, 20.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn block_selection_extends_past_short_lines() {
+        let mut editor = Editor::new();
+        *editor.buffer.borrow_mut() = Buffer::new("long line\nab", "t");
+
+        press(&mut editor, "llll");
+        let event = KeyEvent::Key {
+            key: Key::Character("v".into()),
+            text: Some("v".to_string()),
+            modifiers: keyboard::Modifiers::ALT,
+        };
+        editor.key_engine.handle_key(event);
+        press(&mut editor, "lj");
+        assert_eq!(editor.key_engine.mode, EditorMode::VisualBlock);
+
+        // Columns 4..=5 on both lines, even though the second one ends at column 2.
+        assert_eq!(
+            selection_rects(&editor),
+            vec![
+                Rectangle::new(Point::new(40.0, 0.0), Size::new(20.0, 20.0)),
+                Rectangle::new(Point::new(40.0, 20.0), Size::new(20.0, 20.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn match_pairs_come_from_the_config() {
+        let mut editor = Editor::new();
+        *editor.buffer.borrow_mut() = Buffer::new("Vec<f(u8)>", "t");
+        let bracket_rect = |editor: &Editor| {
+            let bounds = Rectangle::new(Point::ORIGIN, Size::new(800.0, 600.0));
+            let rows = editor.row_map(bounds, 10.0);
+            editor.matching_bracket_rect(&rows, bounds, 10.0, 20.0)
+        };
+
+        // The default pairs skip the angle brackets.
+        press(&mut editor, "%");
+        assert_eq!(editor.multi_cursor.position().col, 8);
+        assert_eq!(bracket_rect(&editor), Some(Rectangle::new(Point::new(50.0, 0.0), Size::new(10.0, 20.0))));
+
+        editor.config.match_pairs.push(('<', '>'));
+        press(&mut editor, "0%");
+        assert_eq!(editor.multi_cursor.position().col, 9);
+        assert_eq!(bracket_rect(&editor), Some(Rectangle::new(Point::new(30.0, 0.0), Size::new(10.0, 20.0))));
+
+        // Nothing to highlight off a bracket.
+        press(&mut editor, "0");
+        assert_eq!(bracket_rect(&editor), None);
+    }
+
+    #[test]
+    fn gutter_hits_pick_the_line_under_the_click() {
+        let mut editor = Editor::new();
+        *editor.buffer.borrow_mut() = Buffer::new("one\ntwo\nthree\n", "t");
+        let bounds = Rectangle::new(Point::ORIGIN, Size::new(800.0, 600.0));
+
+        // No line numbers, no gutter.
+        assert_eq!(editor.gutter_line_at(Point::new(5.0, 25.0), bounds, 10.0, 20.0), None);
+
+        // `number_width` columns, 40 pixels wide.
+        editor.config.number = true;
+        assert_eq!(editor.gutter_line_at(Point::new(5.0, 25.0), bounds, 10.0, 20.0), Some(1));
+        assert_eq!(editor.gutter_line_at(Point::new(45.0, 25.0), bounds, 10.0, 20.0), None);
+        // Below the text is the last line.
+        assert_eq!(editor.gutter_line_at(Point::new(5.0, 500.0), bounds, 10.0, 20.0), Some(2));
+
+        // Scrolled down a line, the same spot shows the next one.
+        editor.scroll_offset.y = 20.0;
+        assert_eq!(editor.gutter_line_at(Point::new(5.0, 25.0), bounds, 10.0, 20.0), Some(2));
+    }
+
+    #[test]
+    fn the_gutter_is_at_least_number_width_wide() {
+        let mut editor = Editor::new();
+        *editor.buffer.borrow_mut() = Buffer::new("one\ntwo\n", "t");
+        editor.config.number = true;
+
+        // Growing past nine lines doesn't move the text.
+        assert_eq!(editor.gutter_width(10.0), 40.0);
+        *editor.buffer.borrow_mut() = Buffer::new(&"line\n".repeat(20), "t");
+        assert_eq!(editor.gutter_width(10.0), 40.0);
+
+        // Five digits and a space don't fit in four.
+        *editor.buffer.borrow_mut() = Buffer::new(&"line\n".repeat(12_000), "t");
+        assert_eq!(editor.gutter_width(10.0), 60.0);
+
+        editor.run_ex_command("set nuw=1");
+        *editor.buffer.borrow_mut() = Buffer::new("one\ntwo\n", "t");
+        assert_eq!(editor.gutter_width(10.0), 20.0);
+    }
+
+    #[test]
+    fn the_focused_pane_has_its_own_border_color() {
+        let editor = Editor::new();
+        assert_eq!(editor.border_color(), editor.config.border_color);
+
+        let editor = editor.focused(true);
+        assert_eq!(editor.border_color(), editor.config.focused_border_color);
+        assert_ne!(editor.config.focused_border_color, editor.config.border_color);
+    }
+
+    #[test]
+    fn gutter_clicks_select_whole_lines() {
+        let mut editor = Editor::new();
+        *editor.buffer.borrow_mut() = Buffer::new("one\ntwo\nthree\n", "t");
+        press(&mut editor, "ll");
+
+        editor.select_lines(1, 1);
+        assert_eq!(editor.key_engine.mode, EditorMode::VisualLine);
+        assert_eq!(editor.buffer.borrow().selection_text(editor.multi_cursor.primary()), "two\n");
+
+        // Dragging up keeps the line the click started on.
+        editor.select_lines(1, 0);
+        assert_eq!(editor.buffer.borrow().selection_text(editor.multi_cursor.primary()), "one\ntwo\n");
+
+        // It's a regular VisualLine selection, `d` deletes it.
+        press(&mut editor, "d");
+        assert_eq!(editor.buffer.borrow().content.to_string(), "three\n");
+    }
+
+    #[test]
+    fn uniform_width_detection() {
+        // Monospace: every glyph has the same advance.
+        assert!(is_uniform_width(&[12.0, 12.0, 12.0, 12.0, 12.0, 12.0]));
+        // Tiny rounding differences are still monospace.
+        assert!(is_uniform_width(&[12.0, 12.01, 11.99]));
+        // Proportional: 'i' and 'l' are much narrower than 'M' and 'W'.
+        assert!(!is_uniform_width(&[14.0, 4.0, 16.0, 4.0, 4.0, 9.0]));
+        // Nothing measured, nothing to complain about.
+        assert!(is_uniform_width(&[]));
+    }
+}
@@ -0,0 +1,175 @@
+//! The file finder (`<C-p>`): the files under the working directory, narrowed down to the ones
+//! fuzzy matching what's typed.
+
+use std::path::{Path, PathBuf};
+
+use atlas_engine::{Message, fuzzy};
+use iced::widget::{column, container, opaque, text, text_input};
+use iced::{Element, Length, Theme, padding};
+
+/// Directories the finder never looks into.
+const IGNORED: &[&str] = &[".git", "target"];
+
+#[derive(Debug, Default)]
+pub struct Finder {
+    pub query: String,
+    files: Option<Vec<PathBuf>>, // `None` until they've been listed.
+    names: Vec<String>,          // `files` as shown, which is what the query matches against.
+    matches: Vec<usize>,         // Indices into `files`, best first.
+    selected: usize,             // Index into `matches`.
+}
+
+impl Finder {
+    const MAX_RESULTS: usize = 12;
+    const WIDTH: f32 = 600.0;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn input_id() -> text_input::Id {
+        text_input::Id::new("finder")
+    }
+
+    pub fn set_files(&mut self, files: Vec<PathBuf>) {
+        self.names = files.iter().map(|file| file.display().to_string()).collect();
+        self.files = Some(files);
+        self.filter();
+    }
+
+    pub fn set_query(&mut self, query: String) {
+        self.query = query;
+        self.selected = 0;
+        self.filter();
+    }
+
+    /// Moves the selection `delta` matches down (up when negative), stopping at either end.
+    pub fn select(&mut self, delta: isize) {
+        let last = self.matches.len().saturating_sub(1);
+        self.selected = self.selected.saturating_add_signed(delta).min(last);
+    }
+
+    /// The selected file, relative to the directory that was listed.
+    pub fn selection(&self) -> Option<&Path> {
+        let file = self.matches.get(self.selected)?;
+        Some(&self.files.as_ref()?[*file])
+    }
+
+    fn filter(&mut self) {
+        self.matches = fuzzy::rank(&self.query, &self.names);
+        self.selected = self.selected.min(self.matches.len().saturating_sub(1));
+    }
+
+    /// The query over the best matches, with the selection highlighted. Meant to be stacked over
+    /// the panes, which it keeps the mouse from.
+    pub fn view(&self) -> Element<'_, Message> {
+        let input = text_input("Find a file", &self.query)
+            .id(Self::input_id())
+            .on_input(Message::FinderQuery)
+            .on_submit(Message::FinderOpen)
+            .padding(6);
+
+        // The selection stays in view when it goes past the last one shown.
+        let first = (self.selected + 1).saturating_sub(Self::MAX_RESULTS);
+        let rows = self.matches.iter().enumerate().skip(first).take(Self::MAX_RESULTS).map(|(i, &file)| {
+            let row = container(text(&self.names[file])).padding([2, 6]).width(Length::Fill);
+            match i == self.selected {
+                true => row.style(selected_row).into(),
+                false => row.into(),
+            }
+        });
+        let status = match &self.files {
+            None => Some("Listing files..."),
+            Some(_) if self.matches.is_empty() => Some("No matching files"),
+            Some(_) => None,
+        };
+
+        let panel = container(column![input, column(rows)].push_maybe(status.map(text)).spacing(6))
+            .padding(10)
+            .width(Self::WIDTH)
+            .style(container::rounded_box);
+
+        container(opaque(panel)).center_x(Length::Fill).padding(padding::top(60)).into()
+    }
+}
+
+fn selected_row(theme: &Theme) -> container::Style {
+    let palette = theme.extended_palette();
+    container::Style {
+        background: Some(palette.primary.weak.color.into()),
+        text_color: Some(palette.primary.weak.text),
+        ..Default::default()
+    }
+}
+
+/// Files under `root`, relative to it and sorted, leaving out the `IGNORED` directories.
+/// Directories that can't be read are skipped.
+pub fn walk_files(root: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut dirs = vec![root.to_path_buf()];
+
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if entry.file_type().is_ok_and(|kind| kind.is_dir()) {
+                if !IGNORED.iter().any(|ignored| entry.file_name() == *ignored) {
+                    dirs.push(path);
+                }
+            } else if path.is_file()
+                && let Ok(relative) = path.strip_prefix(root)
+            {
+                files.push(relative.to_path_buf());
+            }
+        }
+    }
+
+    files.sort();
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn walking_skips_git_and_target() {
+        let root = std::env::temp_dir().join(format!("atlas-walk-{}", std::process::id()));
+        for dir in ["src/bin", ".git/objects", "target/debug", "docs"] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in ["Cargo.toml", "src/main.rs", "src/bin/tool.rs", ".git/HEAD", "target/debug/atlas", ".gitignore"] {
+            std::fs::write(root.join(file), "").unwrap();
+        }
+
+        let files = walk_files(&root);
+        std::fs::remove_dir_all(&root).unwrap();
+        let expected = [".gitignore", "Cargo.toml", "src/bin/tool.rs", "src/main.rs"];
+        assert_eq!(files, expected.map(PathBuf::from));
+    }
+
+    #[test]
+    fn the_selection_follows_the_query() {
+        let mut finder = Finder::new();
+        assert_eq!(finder.selection(), None);
+
+        finder.set_files(["Cargo.toml", "src/main.rs", "src/lib.rs"].map(PathBuf::from).to_vec());
+        assert_eq!(finder.selection(), Some(Path::new("Cargo.toml")));
+        finder.select(5);
+        assert_eq!(finder.selection(), Some(Path::new("src/main.rs")));
+
+        // A new query starts from its best match.
+        finder.set_query("src".to_string());
+        assert_eq!(finder.selection(), Some(Path::new("src/lib.rs")));
+        finder.select(1);
+        finder.select(-3);
+        assert_eq!(finder.selection(), Some(Path::new("src/lib.rs")));
+        finder.select(1);
+        assert_eq!(finder.selection(), Some(Path::new("src/main.rs")));
+
+        finder.set_query("zzz".to_string());
+        assert_eq!(finder.selection(), None);
+    }
+}
@@ -1 +1,3 @@
-pub mod editor; 
+pub mod editor;
+pub mod finder;
+mod rows;
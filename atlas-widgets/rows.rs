@@ -0,0 +1,196 @@
+//! Mapping between buffer lines and the rows they occupy on screen.
+
+use std::ops::Range;
+
+use atlas_engine::{
+    Buffer,
+    wrap::{break_indent, row_for_col, wrap_line},
+};
+
+/// A single row on screen.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScreenRow {
+    pub line: usize,
+    /// Columns shown on this row, `None` when the line isn't wrapped (and scrolls horizontally).
+    pub cols: Option<Range<usize>>,
+}
+
+/// Screen rows of every line, one row per line unless soft wrapping.
+pub struct RowMap {
+    /// Visual rows of each line, `None` without soft wrap.
+    wrapped: Option<Vec<Vec<Range<usize>>>>,
+    /// First screen row of each line, only used when wrapping.
+    starts: Vec<usize>,
+    /// Indent of each line's continuation rows (`breakindent`), only used when wrapping.
+    indents: Vec<usize>,
+    total: usize,
+}
+
+impl RowMap {
+    /// With `indent_continuations` (`breakindent`) the continuation rows of a wrapped line line up
+    /// with its indentation.
+    /// NOTE: Wrapping walks the whole buffer, this should be cached once buffers get big.
+    pub fn new(buffer: &Buffer, wrap_width: Option<usize>, indent_continuations: bool) -> Self {
+        let total_lines = buffer.content.len_lines();
+
+        let Some(width) = wrap_width else {
+            return Self {
+                wrapped: None,
+                starts: Vec::new(),
+                indents: Vec::new(),
+                total: total_lines,
+            };
+        };
+
+        let indents: Vec<_> = (0..total_lines)
+            .map(|line| if indent_continuations { break_indent(buffer, line, width) } else { 0 })
+            .collect();
+        let wrapped: Vec<_> = (0..total_lines)
+            .map(|line| wrap_line(buffer, line, width, indents[line]))
+            .collect();
+
+        let mut starts = Vec::with_capacity(total_lines);
+        let mut total = 0;
+        for rows in &wrapped {
+            starts.push(total);
+            total += rows.len();
+        }
+
+        Self {
+            wrapped: Some(wrapped),
+            starts,
+            indents,
+            total,
+        }
+    }
+
+    /// Screen columns the row of `line` starting at column `row_start` is indented by.
+    pub fn indent(&self, line: usize, row_start: usize) -> usize {
+        if row_start == 0 {
+            return 0;
+        }
+
+        self.indents.get(line).copied().unwrap_or(0)
+    }
+
+    pub fn total_rows(&self) -> usize {
+        self.total
+    }
+
+    /// Screen row holding `line`/`col`, and the column that row starts at.
+    pub fn locate(&self, line: usize, col: usize) -> (usize, usize) {
+        let Some(wrapped) = &self.wrapped else {
+            return (line, 0);
+        };
+
+        let Some(rows) = wrapped.get(line) else {
+            return (self.total, 0);
+        };
+
+        let row = row_for_col(rows, col);
+        (self.starts[line] + row, rows[row].start)
+    }
+
+    /// Columns of the screen row holding `line`/`col`, `None` when not wrapping.
+    pub fn row_cols(&self, line: usize, col: usize) -> Option<Range<usize>> {
+        let rows = self.wrapped.as_ref()?.get(line)?;
+        Some(rows[row_for_col(rows, col)].clone())
+    }
+
+    /// What's shown on screen row `row`, if anything.
+    pub fn row(&self, row: usize) -> Option<ScreenRow> {
+        if row >= self.total {
+            return None;
+        }
+
+        let Some(wrapped) = &self.wrapped else {
+            return Some(ScreenRow { line: row, cols: None });
+        };
+
+        let line = self.starts.partition_point(|&start| start <= row) - 1;
+        let cols = wrapped[line][row - self.starts[line]].clone();
+
+        Some(ScreenRow {
+            line,
+            cols: Some(cols),
+        })
+    }
+
+    /// Splits the columns `cols` of `line` into the screen rows they're displayed on.
+    /// Each segment is (screen row, column the row starts at, columns of `cols` on that row).
+    /// Ranges starting past the end of the line (e.g. a selected newline) stay on its last row.
+    pub fn segments(&self, line: usize, cols: Range<usize>) -> Vec<(usize, usize, Range<usize>)> {
+        let Some(rows) = self.wrapped.as_ref().and_then(|wrapped| wrapped.get(line)) else {
+            return vec![(line, 0, cols)];
+        };
+
+        let segments: Vec<_> = rows
+            .iter()
+            .enumerate()
+            .filter(|(_, row)| row.start < cols.end && cols.start < row.end)
+            .map(|(i, row)| {
+                let start = cols.start.max(row.start);
+                let end = cols.end.min(row.end);
+                (self.starts[line] + i, row.start, start..end)
+            })
+            .collect();
+
+        if segments.is_empty() {
+            let (row, row_start) = self.locate(line, cols.start);
+            return vec![(row, row_start, cols)];
+        }
+
+        segments
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_wrapped_lines_to_rows() {
+        let buffer = Buffer::new("abcdefghij\n\nxyz", "t");
+        let rows = RowMap::new(&buffer, Some(4), false);
+
+        assert_eq!(rows.total_rows(), 5); // 3 + 1 + 1.
+        assert_eq!(rows.locate(0, 5), (1, 4));
+        assert_eq!(rows.locate(2, 1), (4, 0));
+        assert_eq!(rows.row(3), Some(ScreenRow { line: 1, cols: Some(0..0) }));
+        assert_eq!(rows.row(2), Some(ScreenRow { line: 0, cols: Some(8..10) }));
+        assert_eq!(rows.row(5), None);
+
+        assert_eq!(rows.segments(0, 2..9), vec![(0, 0, 2..4), (1, 4, 4..8), (2, 8, 8..9)]);
+        assert_eq!(rows.segments(1, 0..0), vec![(3, 0, 0..0)]);
+        assert_eq!(rows.segments(2, 3..4), vec![(4, 0, 3..4)]);
+        assert_eq!(rows.row_cols(0, 5), Some(4..8));
+    }
+
+    #[test]
+    fn unwrapped_rows_are_lines() {
+        let buffer = Buffer::new("abcdefghij\nxyz", "t");
+        let rows = RowMap::new(&buffer, None, true);
+
+        assert_eq!(rows.total_rows(), 2);
+        assert_eq!(rows.locate(0, 7), (0, 0));
+        assert_eq!(rows.row(1), Some(ScreenRow { line: 1, cols: None }));
+        assert_eq!(rows.segments(0, 2..9), vec![(0, 0, 2..9)]);
+        assert_eq!(rows.row_cols(0, 5), None);
+    }
+
+    #[test]
+    fn continuation_rows_carry_the_indent() {
+        let buffer = Buffer::new("  abcdefghij\nxyz", "t");
+
+        let rows = RowMap::new(&buffer, Some(6), true);
+        assert_eq!(rows.row(1), Some(ScreenRow { line: 0, cols: Some(6..10) }));
+        assert_eq!(rows.locate(0, 11), (2, 10));
+        assert_eq!(rows.indent(0, 0), 0);
+        assert_eq!(rows.indent(0, 10), 2);
+        assert_eq!(rows.indent(1, 0), 0);
+
+        let rows = RowMap::new(&buffer, Some(6), false);
+        assert_eq!(rows.row(1), Some(ScreenRow { line: 0, cols: Some(6..12) }));
+        assert_eq!(rows.indent(0, 6), 0);
+    }
+}
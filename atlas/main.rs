@@ -1,8 +1,20 @@
-use atlas_engine::Message;
-use atlas_widgets::editor::Editor;
-use iced::widget::pane_grid;
+use std::{
+    cell::RefCell,
+    path::{Path, PathBuf},
+    rc::Rc,
+    thread,
+    time::Duration,
+};
+
+use atlas_config::{Config, PathDisplay};
+use atlas_engine::{Buffer, Message, Timer};
+use atlas_widgets::editor::{Editor, SharedBuffer};
+use atlas_widgets::finder::{self, Finder};
+use iced::futures::channel::{mpsc, oneshot};
+use iced::futures::{SinkExt, StreamExt};
+use iced::widget::{pane_grid, stack, text, text_input};
 use iced::{
-    Element,
+    Element, Subscription, Task, event, window,
     widget::pane_grid::{Axis, Pane},
 };
 
@@ -11,36 +23,73 @@ use iced::{
 pub struct Atlas {
     panes: pane_grid::State<Editor>,
     active_pane: Pane,
+    buffers: Vec<SharedBuffer>, // Every buffer shown so far, `:ls` numbers them from 1.
+    finder: Option<Finder>, // Over the panes while it's open.
+    config: Config,
+    cwd: PathBuf, // Relative file paths are shown against this.
 }
 
 impl Default for Atlas {
     fn default() -> Self {
-        let (panes, first_editor) = pane_grid::State::new(Editor::new());
+        let editor = Editor::new();
+        let buffers = vec![editor.buffer.clone()];
+        let (panes, first_editor) = pane_grid::State::new(editor);
 
         Self {
             panes,
             active_pane: first_editor,
+            buffers,
+            finder: None,
+            config: Config::default(),
+            cwd: std::env::current_dir().unwrap_or_default(),
         }
     }
 }
 
 impl Atlas {
+    /// Starts with an empty buffer, loading `path` in its place in the background if given.
+    fn new(path: Option<PathBuf>) -> (Self, Task<Message>) {
+        let mut atlas = Self::default();
+        let Some(path) = path else {
+            return (atlas, Task::none());
+        };
+
+        // The empty buffer is only listed if it's still around once the file is loaded.
+        atlas.buffers.clear();
+        let task = atlas.open(path);
+        (atlas, task)
+    }
+
+    /// Loads `path` into the active pane in the background, or shows it right away if a buffer
+    /// already has it.
+    fn open(&mut self, path: PathBuf) -> Task<Message> {
+        let pane = self.active_pane;
+        let absolute = self.cwd.join(&path);
+        let open = self
+            .buffers
+            .iter()
+            .position(|buffer| buffer.borrow().file_path.as_ref().is_some_and(|file| self.cwd.join(file) == absolute));
+        if let Some(i) = open {
+            self.switch_buffer(i + 1);
+            return Task::none();
+        }
+
+        if let Some(editor) = self.panes.get_mut(pane) {
+            editor.message = Some(format!("\"{}\" loading...", path.display()));
+        }
+        Task::perform(load_file(path), move |result| Message::FileLoaded(pane, result))
+    }
+
     /// Generates the window title based on the active buffer.
     fn title(&self) -> String {
         "Atlas".into()
     }
 
     /// Handles all editor actions and updates state accordingly.
-    fn update(&mut self, message: Message) {
+    fn update(&mut self, message: Message) -> Task<Message> {
         match message {
-            Message::SplitVertical => {
-                self.panes
-                    .split(Axis::Vertical, self.active_pane, Editor::new());
-            }
-            Message::SplitHorizontal => {
-                self.panes
-                    .split(Axis::Horizontal, self.active_pane, Editor::new());
-            }
+            Message::SplitVertical => self.split(Axis::Vertical),
+            Message::SplitHorizontal => self.split(Axis::Horizontal),
             Message::Quit => {
                 std::process::exit(0);
             }
@@ -51,29 +100,301 @@ impl Atlas {
             Message::Resized(resize_event) => {
                 self.panes.resize(resize_event.split, resize_event.ratio);
             }
-            Message::CloseSplit => {
-                if let Some((_removed_editor, sibling)) = self.panes.close(self.active_pane) {
-                    self.active_pane = sibling;
-                } else {
-                    println!("no split to close");
+            Message::CloseSplit { force } => self.close(force),
+            Message::FileLoaded(pane, result) => {
+                // The pane may have been closed while loading.
+                if let Some(editor) = self.panes.get_mut(pane) {
+                    match result {
+                        // The buffer it replaces stays in `:ls`.
+                        Ok(buffer) => editor.show_buffer(Rc::new(RefCell::new(*buffer))),
+                        Err(error) => editor.message = Some(error),
+                    }
+                }
+            }
+            Message::Autosave => self.autosave(),
+            Message::Tick(timer) => self.tick(timer),
+            Message::ListBuffers => {
+                let list = self.buffer_list();
+                if let Some(editor) = self.panes.get_mut(self.active_pane) {
+                    editor.message = Some(list);
+                }
+            }
+            Message::SwitchBuffer(id) => self.switch_buffer(id),
+            Message::OpenFinder => {
+                self.finder = Some(Finder::new());
+                let list = Task::perform(list_files(self.cwd.clone()), Message::FinderFiles);
+                return Task::batch([text_input::focus(Finder::input_id()), list]);
+            }
+            Message::FinderFiles(files) => {
+                if let Some(finder) = &mut self.finder {
+                    finder.set_files(files);
+                }
+            }
+            Message::FinderQuery(query) => {
+                if let Some(finder) = &mut self.finder {
+                    finder.set_query(query);
+                }
+            }
+            Message::FinderSelect(delta) => {
+                if let Some(finder) = &mut self.finder {
+                    finder.select(delta);
+                }
+            }
+            Message::FinderOpen => {
+                let selection = self.finder.take().and_then(|finder| finder.selection().map(Path::to_path_buf));
+                if let Some(path) = selection {
+                    return self.open(path);
                 }
             }
+            Message::CloseFinder => self.finder = None,
+        }
+
+        self.track_buffers();
+        Task::none()
+    }
+
+    /// Adds the buffers panes got since the last call to `buffers`.
+    fn track_buffers(&mut self) {
+        for (_, editor) in self.panes.iter() {
+            if !self.buffers.iter().any(|buffer| Rc::ptr_eq(buffer, &editor.buffer)) {
+                self.buffers.push(editor.buffer.clone());
+            }
+        }
+    }
+
+    /// `:ls`, a line per buffer like Vim's: its number, `%` if it's the active pane's, `a` if a
+    /// pane shows it (`h` for hidden otherwise), `+` if it's modified, and its name.
+    fn buffer_list(&self) -> String {
+        let active = self.panes.get(self.active_pane).map(|editor| &editor.buffer);
+        let lines: Vec<String> = self
+            .buffers
+            .iter()
+            .enumerate()
+            .map(|(i, shared)| {
+                let current = if active.is_some_and(|active| Rc::ptr_eq(active, shared)) { '%' } else { ' ' };
+                let shown = self.panes.iter().any(|(_, editor)| Rc::ptr_eq(&editor.buffer, shared));
+                let buffer = shared.borrow();
+                format!(
+                    "{:>3} {current}{} {} \"{}\"",
+                    i + 1,
+                    if shown { 'a' } else { 'h' },
+                    if buffer.modified { '+' } else { ' ' },
+                    buffer_name(&buffer, self.config.path_display, &self.cwd),
+                )
+            })
+            .collect();
+
+        lines.join("\n")
+    }
+
+    /// `:b {id}`: buffer `id` of `:ls` in the active pane, from the top.
+    fn switch_buffer(&mut self, id: usize) {
+        let Some(editor) = self.panes.get_mut(self.active_pane) else {
+            return;
+        };
+
+        match id.checked_sub(1).and_then(|i| self.buffers.get(i)) {
+            Some(buffer) => editor.show_buffer(buffer.clone()),
+            None => editor.message = Some(format!("E86: Buffer {id} does not exist")),
         }
     }
 
+    /// Opens a split next to the active pane, with a new empty scratch buffer in it.
+    fn split(&mut self, axis: Axis) {
+        self.panes.split(axis, self.active_pane, Editor::new());
+    }
+
+    /// Closes the active pane, unless it's the last window on a buffer with unsaved changes and
+    /// the close isn't `force`d. The last pane stays open on a new scratch buffer instead.
+    fn close(&mut self, force: bool) {
+        let Some(editor) = self.panes.get(self.active_pane) else {
+            return;
+        };
+        let windows = self
+            .panes
+            .iter()
+            .filter(|(_, other)| Rc::ptr_eq(&other.buffer, &editor.buffer))
+            .count();
+
+        if !can_close(editor.buffer.borrow().modified, windows, force) {
+            if let Some(editor) = self.panes.get_mut(self.active_pane) {
+                editor.message = Some("E37: No write since last change (add ! to override)".to_string());
+            }
+            return;
+        }
+
+        if let Some((_removed_editor, sibling)) = self.panes.close(self.active_pane) {
+            self.active_pane = sibling;
+        } else if let Some(editor) = self.panes.get_mut(self.active_pane) {
+            editor.show_buffer(Editor::new().buffer);
+        }
+    }
+
+    /// The timers that should be running, and how often each fires.
+    /// Anything needing periodic wakeups registers here and handles its tick in `tick`.
+    fn timers(&self) -> Vec<(Timer, Duration)> {
+        let mut timers = Vec::new();
+        if self.config.autosave {
+            timers.push((Timer::Autosave, self.config.autosave_interval));
+        }
+        timers
+    }
+
+    fn tick(&mut self, timer: Timer) {
+        match timer {
+            Timer::Autosave => self.autosave(),
+        }
+    }
+
+    fn autosave(&mut self) {
+        for (_, editor) in self.panes.iter_mut() {
+            let result = {
+                let mut buffer = editor.buffer.borrow_mut();
+                if !buffer.needs_autosave() {
+                    continue;
+                }
+                buffer.save()
+            };
+
+            if let Err(error) = result {
+                editor.message = Some(format!("Autosave failed: {error}"));
+            }
+        }
+    }
+
+    fn subscription(&self) -> Subscription<Message> {
+        let ticks = self
+            .timers()
+            .into_iter()
+            .map(|(timer, interval)| Subscription::run_with_id((timer, interval), every(timer, interval)));
+
+        let focus_lost = self.config.autosave.then(|| {
+            event::listen_with(|event, _, _| match event {
+                iced::Event::Window(window::Event::Unfocused) => Some(Message::Autosave),
+                _ => None,
+            })
+        });
+
+        let finder_keys = self.finder.is_some().then(|| event::listen_with(finder_key));
+
+        Subscription::batch(ticks.chain(focus_lost).chain(finder_keys))
+    }
+
     /// Renders the entire editor interface.
-    fn view(&self) -> Element<Message> {
-        pane_grid(&self.panes, |pane_id, editor, _| {
-            let elem: Element<_> = editor.clone().focused(pane_id == self.active_pane).into();
-            pane_grid::Content::new(elem)
+    fn view(&self) -> Element<'_, Message> {
+        // Keys go to the finder while it's open.
+        let focused = |pane| pane == self.active_pane && self.finder.is_none();
+        let panes = pane_grid(&self.panes, |pane_id, editor, _| {
+            let elem: Element<_> = editor.clone().focused(focused(pane_id)).into();
+            let title = pane_title(&editor.buffer.borrow(), self.config.path_display, &self.cwd);
+
+            pane_grid::Content::new(elem).title_bar(pane_grid::TitleBar::new(text(title)).padding(2))
         })
         .on_click(Message::PaneClicked)
         .on_drag(Message::Dragged)
-        .on_resize(6, Message::Resized)
-        .into()
+        .on_resize(6, Message::Resized);
+
+        match &self.finder {
+            Some(finder) => stack![panes, finder.view()].into(),
+            None => panes.into(),
+        }
+    }
+}
+
+/// Title shown above each pane: `buffer_name`, with a dot when it has unsaved changes.
+fn pane_title(buffer: &Buffer, display: PathDisplay, cwd: &Path) -> String {
+    let name = buffer_name(buffer, display, cwd);
+    if buffer.modified { format!("{name} ●") } else { name }
+}
+
+/// The buffer's path (or name, for scratch buffers) as `display` says.
+fn buffer_name(buffer: &Buffer, display: PathDisplay, cwd: &Path) -> String {
+    match &buffer.file_path {
+        Some(path) => display_path(path, display, cwd),
+        None => buffer.name.clone(),
     }
 }
 
+/// Whether a window can close: changes are only lost with the last of the `windows` showing
+/// their buffer, which then takes `force`.
+fn can_close(modified: bool, windows: usize, force: bool) -> bool {
+    force || !modified || windows > 1
+}
+
+/// `path` shown as `display` says, relative paths being relative to `cwd`.
+fn display_path(path: &Path, display: PathDisplay, cwd: &Path) -> String {
+    let absolute = cwd.join(path);
+    let shown = match display {
+        PathDisplay::Name => Path::new(absolute.file_name().unwrap_or(absolute.as_os_str())),
+        PathDisplay::Relative => absolute.strip_prefix(cwd).unwrap_or(&absolute),
+        PathDisplay::Absolute => &absolute,
+    };
+
+    shown.display().to_string()
+}
+
+/// Reads `path` on its own thread, so big files don't block the event loop.
+async fn load_file(path: PathBuf) -> Result<Box<Buffer>, String> {
+    let (sender, receiver) = oneshot::channel();
+
+    thread::spawn(move || {
+        let result = Buffer::from_path(&path)
+            .map(Box::new)
+            .map_err(|error| format!("\"{}\" {error}", path.display()));
+        let _ = sender.send(result);
+    });
+
+    receiver.await.map_err(|_| "file loader stopped unexpectedly".to_string())?
+}
+
+/// Lists the files under `root` for the finder on their own thread, big trees take a while.
+async fn list_files(root: PathBuf) -> Vec<PathBuf> {
+    let (sender, receiver) = oneshot::channel();
+    thread::spawn(move || {
+        let _ = sender.send(finder::walk_files(&root));
+    });
+
+    receiver.await.unwrap_or_default()
+}
+
+/// The keys the finder's text input leaves alone: arrows (or `<C-n>`/`<C-p>`) move the
+/// selection, `<Esc>` closes it.
+fn finder_key(event: iced::Event, _: event::Status, _: window::Id) -> Option<Message> {
+    use iced::keyboard::{Event::KeyPressed, Key, key::Named};
+
+    let iced::Event::Keyboard(KeyPressed { key, modifiers, .. }) = event else {
+        return None;
+    };
+    match key.as_ref() {
+        Key::Named(Named::ArrowDown) => Some(Message::FinderSelect(1)),
+        Key::Named(Named::ArrowUp) => Some(Message::FinderSelect(-1)),
+        Key::Named(Named::Escape) => Some(Message::CloseFinder),
+        Key::Character("n") if modifiers.control() => Some(Message::FinderSelect(1)),
+        Key::Character("p") if modifiers.control() => Some(Message::FinderSelect(-1)),
+        _ => None,
+    }
+}
+
+/// Emits `Message::Tick(timer)` every `interval`. The executor has no timers, so a thread does the
+/// sleeping.
+fn every(timer: Timer, interval: Duration) -> impl iced::futures::Stream<Item = Message> {
+    iced::stream::channel(1, move |mut output| async move {
+        let (ticks, mut receiver) = mpsc::unbounded();
+        thread::spawn(move || {
+            // Stops once the subscription (and so the receiver) is dropped.
+            while ticks.unbounded_send(()).is_ok() {
+                thread::sleep(interval);
+            }
+        });
+
+        // The first tick fires right away, skip it.
+        receiver.next().await;
+        while receiver.next().await.is_some() {
+            let _ = output.send(Message::Tick(timer)).await;
+        }
+    })
+}
+
 pub struct Iosevka;
 
 impl Iosevka {
@@ -87,7 +408,309 @@ impl Iosevka {
 
 fn main() -> iced::Result {
     iced::application(Atlas::title, Atlas::update, Atlas::view)
+        .subscription(Atlas::subscription)
         .font(include_bytes!("../fonts/iosevka-regular.ttf"))
         .default_font(Iosevka::REGULAR)
-        .run()
+        .run_with(|| Atlas::new(std::env::args_os().nth(1).map(PathBuf::from)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pane_title_marks_modified_buffers() {
+        let mut buffer = Buffer::new("", "notes.md");
+        let cwd = Path::new("/home/atlas");
+        assert_eq!(pane_title(&buffer, PathDisplay::Absolute, cwd), "notes.md");
+
+        buffer.modified = true;
+        assert_eq!(pane_title(&buffer, PathDisplay::Name, cwd), "notes.md ●");
+
+        buffer.file_path = Some(PathBuf::from("src/notes.md"));
+        assert_eq!(pane_title(&buffer, PathDisplay::Relative, cwd), "src/notes.md ●");
+    }
+
+    #[test]
+    fn paths_are_shown_as_configured() {
+        let cwd = Path::new("/home/atlas/project");
+        let inside = Path::new("/home/atlas/project/src/main.rs");
+        let outside = Path::new("/etc/hosts");
+        let relative = Path::new("src/lib.rs");
+
+        assert_eq!(display_path(inside, PathDisplay::Name, cwd), "main.rs");
+        assert_eq!(display_path(inside, PathDisplay::Relative, cwd), "src/main.rs");
+        assert_eq!(display_path(inside, PathDisplay::Absolute, cwd), "/home/atlas/project/src/main.rs");
+
+        assert_eq!(display_path(outside, PathDisplay::Relative, cwd), "/etc/hosts");
+        assert_eq!(display_path(outside, PathDisplay::Name, cwd), "hosts");
+
+        assert_eq!(display_path(relative, PathDisplay::Relative, cwd), "src/lib.rs");
+        assert_eq!(display_path(relative, PathDisplay::Absolute, cwd), "/home/atlas/project/src/lib.rs");
+    }
+
+    #[test]
+    fn loaded_file_replaces_the_pane_buffer() {
+        let path = std::env::temp_dir().join(format!("atlas-load-{}.txt", std::process::id()));
+        std::fs::write(&path, "first\nsecond\n").unwrap();
+
+        let result = iced::futures::executor::block_on(load_file(path.clone()));
+        std::fs::remove_file(&path).unwrap();
+        let buffer = result.unwrap();
+        assert_eq!(buffer.visible_line_content(1), "second");
+        assert!(buffer.name.starts_with("atlas-load-"));
+
+        let (mut atlas, _) = Atlas::new(Some(path.clone()));
+        let pane = atlas.active_pane;
+        assert!(atlas.panes.get(pane).unwrap().message.as_deref().unwrap().ends_with("loading..."));
+
+        let _ = atlas.update(Message::FileLoaded(pane, Ok(buffer)));
+        let editor = atlas.panes.get(pane).unwrap();
+        assert_eq!(editor.message, None);
+        assert_eq!(editor.buffer.borrow().visible_line_content(0), "first");
+    }
+
+    #[test]
+    fn autosave_skips_scratch_and_unmodified_buffers() {
+        let path = std::env::temp_dir().join(format!("atlas-autosave-{}.txt", std::process::id()));
+        std::fs::write(&path, "saved\n").unwrap();
+
+        let mut atlas = Atlas::default();
+        let file_pane = atlas.active_pane;
+        let (scratch_pane, _) = atlas.panes.split(Axis::Vertical, file_pane, Editor::new()).unwrap();
+        let _ = atlas.update(Message::FileLoaded(file_pane, Ok(Box::new(Buffer::from_path(&path).unwrap()))));
+
+        for pane in [file_pane, scratch_pane] {
+            let mut buffer = atlas.panes.get(pane).unwrap().buffer.borrow_mut();
+            buffer.content.insert(0, "edited ");
+            buffer.modified = true;
+        }
+
+        let _ = atlas.update(Message::Autosave);
+        let saved = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(saved, "edited saved\n");
+        assert!(!atlas.panes.get(file_pane).unwrap().buffer.borrow().modified);
+        // Scratch buffers stay modified, with nowhere to be saved.
+        assert!(atlas.panes.get(scratch_pane).unwrap().buffer.borrow().modified);
+        assert_eq!(atlas.panes.get(scratch_pane).unwrap().message, None);
+    }
+
+    #[test]
+    fn only_the_last_window_on_a_modified_buffer_refuses_to_close() {
+        assert!(can_close(false, 1, false));
+        assert!(!can_close(true, 1, false));
+        assert!(can_close(true, 1, true));
+        assert!(can_close(true, 2, false));
+
+        // Two windows on the same modified buffer.
+        let mut atlas = Atlas::default();
+        let first = atlas.active_pane;
+        let shared = atlas.panes.get(first).unwrap().clone();
+        shared.buffer.borrow_mut().modified = true;
+        let (second, _) = atlas.panes.split(Axis::Vertical, first, shared).unwrap();
+
+        atlas.active_pane = second;
+        let _ = atlas.update(Message::CloseSplit { force: false });
+        assert_eq!(atlas.panes.len(), 1);
+        assert_eq!(atlas.active_pane, first);
+
+        // The last one keeps the buffer open, and says why.
+        atlas.panes.split(Axis::Vertical, first, Editor::new()).unwrap();
+        let _ = atlas.update(Message::CloseSplit { force: false });
+        assert_eq!(atlas.panes.len(), 2);
+        let message = atlas.panes.get(first).unwrap().message.clone();
+        assert_eq!(message.as_deref(), Some("E37: No write since last change (add ! to override)"));
+
+        let quit = atlas.panes.get_mut(first).unwrap().run_ex_command("q!").unwrap();
+        let _ = atlas.update(quit);
+        assert_eq!(atlas.panes.len(), 1);
+        assert!(atlas.panes.get(first).is_none());
+    }
+
+    #[test]
+    fn closing_the_last_pane_leaves_a_scratch_buffer() {
+        let mut atlas = Atlas::default();
+        let only = atlas.active_pane;
+        let editor = atlas.panes.get_mut(only).unwrap();
+        editor.buffer.borrow_mut().content.insert(0, "text");
+        let old = editor.buffer.clone();
+
+        let _ = atlas.update(Message::CloseSplit { force: false });
+        assert_eq!(atlas.panes.len(), 1);
+        assert_eq!(atlas.active_pane, only);
+        let editor = atlas.panes.get(only).unwrap();
+        assert!(!Rc::ptr_eq(&editor.buffer, &old));
+        assert_eq!(editor.buffer.borrow().content.len_chars(), 0);
+
+        // Unsaved changes still need a `!`.
+        editor.buffer.borrow_mut().content.insert(0, "text");
+        editor.buffer.borrow_mut().modified = true;
+        let _ = atlas.update(Message::CloseSplit { force: false });
+        assert_eq!(atlas.panes.get(only).unwrap().buffer.borrow().content.to_string(), "text");
+
+        let _ = atlas.update(Message::CloseSplit { force: true });
+        let editor = atlas.panes.get(only).unwrap();
+        assert_eq!(editor.buffer.borrow().content.len_chars(), 0);
+        assert!(!editor.buffer.borrow().modified);
+    }
+
+    #[test]
+    fn vnew_opens_an_empty_scratch_buffer() {
+        let mut atlas = Atlas::default();
+        let first = atlas.active_pane;
+        let editor = atlas.panes.get_mut(first).unwrap();
+        editor.buffer.borrow_mut().content.insert(0, "text");
+
+        let message = editor.run_ex_command("vnew").unwrap();
+        assert!(matches!(message, Message::SplitVertical));
+        let _ = atlas.update(message);
+
+        assert_eq!(atlas.panes.len(), 2);
+        let (_, new) = atlas.panes.iter().find(|(pane, _)| **pane != first).unwrap();
+        let old = atlas.panes.get(first).unwrap();
+        assert!(!Rc::ptr_eq(&new.buffer, &old.buffer));
+        assert_eq!(new.buffer.borrow().content.len_chars(), 0);
+        assert_eq!(new.buffer.borrow().file_path, None);
+        assert_eq!(old.buffer.borrow().content.to_string(), "text");
+    }
+
+    #[test]
+    fn ls_lists_every_buffer_shown_so_far() {
+        let mut atlas = Atlas::default();
+        let first = atlas.active_pane;
+        atlas.cwd = PathBuf::from("/home/atlas");
+        atlas.panes.get(first).unwrap().buffer.borrow_mut().file_path = Some(PathBuf::from("src/main.rs"));
+        let _ = atlas.update(Message::SplitVertical);
+        let (&second, editor) = atlas.panes.iter().find(|(pane, _)| **pane != first).unwrap();
+        editor.buffer.borrow_mut().modified = true;
+
+        let ls = atlas.panes.get_mut(first).unwrap().run_ex_command("ls").unwrap();
+        assert!(matches!(ls, Message::ListBuffers));
+        let _ = atlas.update(ls);
+        let message = atlas.panes.get(first).unwrap().message.clone();
+        assert_eq!(message.as_deref(), Some("  1 %a   \"main.rs\"\n  2  a + \"Atlas\""));
+
+        // Closed panes leave their buffers hidden, still listed.
+        atlas.active_pane = second;
+        let _ = atlas.update(Message::CloseSplit { force: true });
+        let _ = atlas.update(Message::ListBuffers);
+        let message = atlas.panes.get(first).unwrap().message.clone();
+        assert_eq!(message.as_deref(), Some("  1 %a   \"main.rs\"\n  2  h + \"Atlas\""));
+    }
+
+    #[test]
+    fn b_switches_the_active_pane_to_a_listed_buffer() {
+        let mut atlas = Atlas::default();
+        let first = atlas.active_pane;
+        let _ = atlas.update(Message::SplitVertical);
+        let (&second, editor) = atlas.panes.iter().find(|(pane, _)| **pane != first).unwrap();
+        editor.buffer.borrow_mut().content.insert(0, "second");
+        atlas.active_pane = second;
+
+        let b = atlas.panes.get_mut(second).unwrap().run_ex_command("b 1").unwrap();
+        assert!(matches!(b, Message::SwitchBuffer(1)));
+        let _ = atlas.update(b);
+        let [one, _] = atlas.buffers.as_slice() else { panic!("expected two buffers") };
+        assert!(Rc::ptr_eq(&atlas.panes.get(second).unwrap().buffer, one));
+
+        // The buffer it showed stays around for `:b 2`.
+        let _ = atlas.update(Message::SwitchBuffer(2));
+        assert_eq!(atlas.panes.get(second).unwrap().buffer.borrow().content.to_string(), "second");
+
+        let _ = atlas.update(Message::SwitchBuffer(3));
+        assert_eq!(atlas.panes.get(second).unwrap().message.as_deref(), Some("E86: Buffer 3 does not exist"));
+        assert_eq!(atlas.buffers.len(), 2);
+    }
+
+    #[test]
+    fn the_pane_keeps_its_registers_and_options_across_b() {
+        let mut atlas = Atlas::default();
+        let pane = atlas.active_pane;
+        let _ = atlas.update(Message::SplitVertical);
+        let editor = atlas.panes.get_mut(pane).unwrap();
+        editor.registers.set_unnamed_lines("yanked\n");
+        editor.scroll_lines = Some(5);
+
+        let _ = atlas.update(Message::SwitchBuffer(2));
+        let editor = atlas.panes.get(pane).unwrap();
+        assert!(Rc::ptr_eq(&editor.buffer, &atlas.buffers[1]));
+        assert_eq!(editor.registers.unnamed(), "yanked\n");
+        assert!(editor.registers.is_linewise());
+        assert_eq!(editor.scroll_lines, Some(5));
+    }
+
+    #[test]
+    fn the_finder_opens_its_selection_in_the_active_pane() {
+        let mut atlas = Atlas::default();
+        let pane = atlas.active_pane;
+        atlas.cwd = PathBuf::from("/home/atlas");
+        let shown = atlas.panes.get(pane).unwrap().buffer.clone();
+        shown.borrow_mut().file_path = Some(PathBuf::from("/home/atlas/src/lib.rs"));
+        let _ = atlas.update(Message::SplitVertical);
+
+        let _ = atlas.update(Message::OpenFinder);
+        assert!(atlas.finder.is_some());
+        let files = ["Cargo.toml", "src/lib.rs", "src/main.rs"].map(PathBuf::from).to_vec();
+        let _ = atlas.update(Message::FinderFiles(files));
+        let _ = atlas.update(Message::FinderQuery("main".to_string()));
+
+        // Files no buffer has yet are loaded in the background.
+        let _ = atlas.update(Message::FinderOpen);
+        assert!(atlas.finder.is_none());
+        let message = atlas.panes.get(pane).unwrap().message.clone();
+        assert_eq!(message.as_deref(), Some("\"src/main.rs\" loading..."));
+
+        // The others are shown right away, relative paths being relative to the cwd.
+        let (&other, _) = atlas.panes.iter().find(|(p, _)| **p != pane).unwrap();
+        atlas.active_pane = other;
+        let _ = atlas.update(Message::OpenFinder);
+        let _ = atlas.update(Message::FinderFiles(vec![PathBuf::from("src/lib.rs")]));
+        let _ = atlas.update(Message::FinderOpen);
+        assert!(Rc::ptr_eq(&atlas.panes.get(other).unwrap().buffer, &shown));
+
+        // Closing opens nothing.
+        let _ = atlas.update(Message::OpenFinder);
+        let _ = atlas.update(Message::CloseFinder);
+        assert!(atlas.finder.is_none());
+        assert!(Rc::ptr_eq(&atlas.panes.get(other).unwrap().buffer, &shown));
+    }
+
+    #[test]
+    fn timers_are_registered_by_their_subsystem() {
+        let mut atlas = Atlas::default();
+        assert_eq!(atlas.timers(), []);
+
+        atlas.config.autosave = true;
+        atlas.config.autosave_interval = Duration::from_secs(5);
+        assert_eq!(atlas.timers(), [(Timer::Autosave, Duration::from_secs(5))]);
+    }
+
+    #[test]
+    fn autosave_tick_saves_buffers() {
+        let path = std::env::temp_dir().join(format!("atlas-tick-{}.txt", std::process::id()));
+        std::fs::write(&path, "saved\n").unwrap();
+
+        let mut atlas = Atlas::default();
+        let pane = atlas.active_pane;
+        let _ = atlas.update(Message::FileLoaded(pane, Ok(Box::new(Buffer::from_path(&path).unwrap()))));
+        atlas.panes.get(pane).unwrap().buffer.borrow_mut().modified = true;
+
+        let _ = atlas.update(Message::Tick(Timer::Autosave));
+        std::fs::remove_file(&path).unwrap();
+        assert!(!atlas.panes.get(pane).unwrap().buffer.borrow().modified);
+    }
+
+    #[test]
+    fn load_errors_are_shown_in_the_pane() {
+        let path = PathBuf::from("/nonexistent/atlas/file.txt");
+        let result = iced::futures::executor::block_on(load_file(path.clone()));
+        let error = result.unwrap_err();
+        assert!(error.starts_with("\"/nonexistent/atlas/file.txt\""));
+
+        let (mut atlas, _) = Atlas::new(Some(path));
+        let pane = atlas.active_pane;
+        let _ = atlas.update(Message::FileLoaded(pane, Err(error.clone())));
+        assert_eq!(atlas.panes.get(pane).unwrap().message, Some(error));
+    }
 }